@@ -0,0 +1,81 @@
+// Walks a node through joining a two elder section, printing every message and decision as it
+// happens. Run it with `cargo run --example join_walkthrough`.
+//
+// Node 0 and 1 are the genesis elders and node 2 wants to join:
+// 1. Node 2 sends ReqJoin to both elders.
+// 2. Each elder witnesses the join of node 2 with the next ord_idx and syncs the other nodes.
+// 3. Once a majority of the elders (here both) have witnessed the join, every node that hears
+//    about it decides node 2 is a member.
+//
+// The walkthrough panics if the nodes don't end up agreeing, so it doubles as a smoke test.
+
+use std::collections::BTreeSet;
+
+use stable_set::{
+    event_log::{Event, InProcess},
+    ModelCfg,
+};
+use stateright::actor::Id;
+
+const MAX_STEPS: usize = 1_000;
+
+fn main() {
+    let cfg = ModelCfg {
+        elder_count: 2,
+        server_count: 3,
+        ..ModelCfg::default()
+    };
+
+    let peers = Vec::from_iter((0..cfg.server_count).map(Id::from));
+    let joiner = peers[2];
+
+    let mut nodes = InProcess::start(peers.iter().map(|id| (*id, cfg.node(peers.clone(), None))));
+
+    println!("on start:");
+    for event in nodes.events.iter() {
+        println!("  {event}");
+    }
+
+    let mut step = 0;
+    while let Some(events) = nodes.step() {
+        step += 1;
+        assert!(step < MAX_STEPS, "the walkthrough never went quiet");
+
+        println!("step {step}:");
+        for event in events {
+            match event {
+                Event::Decided { .. } | Event::Rejected { .. } => println!("  ** {event}"),
+                _ => println!("  {event}"),
+            }
+        }
+    }
+
+    let decided = Vec::from_iter(nodes.states().map(|(_, state)| {
+        let stable_set = &state.membership.stable_set;
+        (stable_set.gen(), stable_set.members())
+    }));
+    assert!(
+        decided.windows(2).all(|w| w[0] == w[1]),
+        "nodes disagree on the members: {decided:?}"
+    );
+    assert!(
+        nodes.state(joiner).membership.is_member(joiner),
+        "{joiner} never joined"
+    );
+
+    // Anyone knowing the genesis elders can check node 2 is a member from the proof alone.
+    let genesis_elders = BTreeSet::from_iter(peers[..cfg.elder_count].iter().copied());
+    let proof = nodes
+        .state(peers[0])
+        .membership
+        .stable_set
+        .membership_proof(joiner)
+        .expect("no proof for the joiner");
+    assert!(
+        proof.verify(&genesis_elders),
+        "the joiner's membership proof doesn't verify"
+    );
+
+    let (gen, members) = &decided[0];
+    println!("all nodes agree on {members:?} at generation {gen}");
+}
@@ -0,0 +1,113 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use stateright::actor::Id;
+
+use crate::{
+    fake_crypto::{SectionSig, Sig},
+    ledger,
+    membership::SyncPayload,
+    stable_set::Member,
+    Action, Msg,
+};
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Mutation {
+    // Claim a join for a member that never asked to join, backed by fabricated witnesses.
+    InjectJoin {
+        member: Member,
+        witnesses: BTreeSet<Id>,
+    },
+    // Claim that an existing member is leaving, backed by fabricated witnesses.
+    InjectLeave {
+        member: Member,
+        witnesses: BTreeSet<Id>,
+    },
+    // Rewrite the outputs of reissue requests to pay `to`, keeping the owners' signatures.
+    RedirectReissue {
+        to: Id,
+    },
+    // Move one unit between the commitments of reissue requests without redoing their tags. The
+    // sender is the one cheating here, so the Tx is re-signed as the sender.
+    MalformCommitment,
+}
+
+// A network that tampers with the stable set of every message crossing a compromised link.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MutatingNetwork {
+    pub compromised_links: BTreeSet<(Id, Id)>,
+    pub mutation: Mutation,
+}
+
+impl MutatingNetwork {
+    pub fn tamper(&self, src: Id, dst: Id, mut msg: Msg) -> Msg {
+        if !self.compromised_links.contains(&(src, dst)) {
+            return msg;
+        }
+
+        match self.mutation.clone() {
+            Mutation::InjectJoin { member, witnesses } => {
+                // A digest leaves it nothing to inject into.
+                let SyncPayload::StableSet(stable_set, _) = Arc::make_mut(&mut msg.sync) else {
+                    return msg;
+                };
+                // Without the witnesses' keys all it can do is pass off its own signature as theirs.
+                let sig = stable_set
+                    .joining_members
+                    .entry(member.clone())
+                    .or_insert_with(|| SectionSig::new(witnesses.clone()));
+                for witness in witnesses {
+                    sig.add_share(witness, Sig::sign(dst, member.clone()));
+                }
+            }
+            Mutation::InjectLeave { member, witnesses } => {
+                let SyncPayload::StableSet(stable_set, _) = Arc::make_mut(&mut msg.sync) else {
+                    return msg;
+                };
+                stable_set
+                    .leaving_members
+                    .entry(member)
+                    .or_default()
+                    .extend(witnesses);
+            }
+            Mutation::RedirectReissue { to } => {
+                if let Action::Wallet(
+                    ledger::Msg::ReqReissue(tx) | ledger::Msg::FallbackReissue(tx),
+                ) = Arc::make_mut(&mut msg.action)
+                {
+                    for output in tx.outputs.iter_mut() {
+                        output.owner = Some(to);
+                    }
+                    // It can only sign as itself, which doesn't stand in for the input owners.
+                    tx.sign(to);
+                }
+            }
+            Mutation::MalformCommitment => {
+                if let Action::Wallet(
+                    ledger::Msg::ReqReissue(tx) | ledger::Msg::FallbackReissue(tx),
+                ) = Arc::make_mut(&mut msg.action)
+                {
+                    if let [first, .., last] = tx.outputs.as_mut_slice() {
+                        if last.commitment.amount > 0 {
+                            last.commitment.amount -= 1;
+                            first.commitment.amount += 1;
+                        }
+                    } else if let [only] = tx.outputs.as_mut_slice() {
+                        only.commitment.amount += 1;
+                    }
+                    tx.sign(src);
+                }
+            }
+        }
+
+        msg
+    }
+
+    pub fn fabricated_ids(&self) -> BTreeSet<Id> {
+        match &self.mutation {
+            Mutation::InjectJoin { member, .. } => BTreeSet::from_iter([member.id]),
+            Mutation::InjectLeave { .. }
+            | Mutation::RedirectReissue { .. }
+            | Mutation::MalformCommitment => BTreeSet::new(),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use stateright::actor::{Envelope, Id};
+
+use crate::{
+    dkg::{self, KeySet},
+    fake_crypto::{Evidence, SigSet},
+    handover::{self, Sap},
+    health::{self, HealthRecord},
+    ledger::{self, Dbc, DbcId, Tx},
+    membership::{self, SyncPayload},
+    section::Prefix,
+    stable_set::{Member, StableSet},
+    Action, Msg,
+};
+
+const ID_SIZE: usize = 8;
+const U64_SIZE: usize = 8;
+
+// Rough estimate of how many bytes a value would take on the wire.
+pub trait WireSize {
+    fn wire_size(&self) -> usize;
+}
+
+impl WireSize for Id {
+    fn wire_size(&self) -> usize {
+        ID_SIZE
+    }
+}
+
+impl WireSize for BTreeSet<Id> {
+    fn wire_size(&self) -> usize {
+        U64_SIZE + self.len() * ID_SIZE
+    }
+}
+
+// The bits and their length.
+impl WireSize for Prefix {
+    fn wire_size(&self) -> usize {
+        U64_SIZE + 1
+    }
+}
+
+impl WireSize for Member {
+    fn wire_size(&self) -> usize {
+        2 * U64_SIZE + ID_SIZE + 1
+    }
+}
+
+impl WireSize for KeySet {
+    fn wire_size(&self) -> usize {
+        self.elders.wire_size() + U64_SIZE + self.acks.wire_size()
+    }
+}
+
+impl WireSize for Sap {
+    fn wire_size(&self) -> usize {
+        let signed_in = U64_SIZE + self.prefix().wire_size() + self.elders().wire_size();
+        signed_in
+            + self.signers().len() * (ID_SIZE + signed_in)
+            + 1
+            + self.key_set().map_or(0, KeySet::wire_size)
+    }
+}
+
+// The sender, the claimed signer and the forged share, leaving out the message it covers.
+impl WireSize for Evidence {
+    fn wire_size(&self) -> usize {
+        2 * ID_SIZE + ID_SIZE + 2 * U64_SIZE
+    }
+}
+
+impl WireSize for HealthRecord {
+    fn wire_size(&self) -> usize {
+        3 * U64_SIZE
+    }
+}
+
+impl WireSize for StableSet {
+    fn wire_size(&self) -> usize {
+        let members = U64_SIZE + self.members().iter().map(Member::wire_size).sum::<usize>();
+        let joining = U64_SIZE
+            + self
+                .joining_members
+                .iter()
+                .chain(self.proofs.iter())
+                .map(|(m, sig)| {
+                    m.wire_size()
+                        + sig.voters.wire_size()
+                        + sig.signers().len() * (ID_SIZE + m.wire_size())
+                })
+                .sum::<usize>();
+        let leaving = U64_SIZE
+            + self
+                .leaving_members
+                .iter()
+                .map(|(m, witnesses)| m.wire_size() + witnesses.wire_size())
+                .sum::<usize>();
+        let blacklist = U64_SIZE
+            + self
+                .blacklist
+                .iter()
+                .map(|(entry, sig)| {
+                    U64_SIZE
+                        + entry.member.wire_size()
+                        + sig.voters.wire_size()
+                        + sig.signers().len() * (ID_SIZE + U64_SIZE + entry.member.wire_size())
+                })
+                .sum::<usize>();
+
+        let snapshots = U64_SIZE
+            + self
+                .snapshot
+                .iter()
+                .map(|(snapshot, sig)| (snapshot, sig))
+                .chain(self.pending_snapshots.iter())
+                .map(|(snapshot, sig)| {
+                    let snapshot_size = 2 * U64_SIZE
+                        + snapshot
+                            .members
+                            .iter()
+                            .map(Member::wire_size)
+                            .sum::<usize>();
+                    snapshot_size
+                        + sig.voters.wire_size()
+                        + sig.signers().len() * (ID_SIZE + snapshot_size)
+                })
+                .sum::<usize>();
+
+        U64_SIZE + members + joining + leaving + blacklist + snapshots
+    }
+}
+
+impl WireSize for Tx {
+    fn wire_size(&self) -> usize {
+        let unsigned = U64_SIZE
+            + self.inputs.iter().map(Dbc::wire_size).sum::<usize>()
+            + U64_SIZE
+            + self.outputs.len() * (ID_SIZE + 3 * U64_SIZE)
+            + 1
+            + U64_SIZE;
+
+        // Each owner signature covers the Tx without the signatures.
+        unsigned + U64_SIZE + self.owner_sigs.len() * (ID_SIZE + unsigned)
+    }
+}
+
+impl WireSize for DbcId {
+    fn wire_size(&self) -> usize {
+        // The Tx hash and the output index.
+        2 * U64_SIZE
+    }
+}
+
+impl WireSize for Dbc {
+    fn wire_size(&self) -> usize {
+        U64_SIZE + self.tx.wire_size()
+    }
+}
+
+impl WireSize for Action {
+    fn wire_size(&self) -> usize {
+        let payload = match self {
+            Action::Membership(membership::Msg::ReqJoin(id))
+            | Action::Membership(membership::Msg::ReqLeave(id)) => id.wire_size(),
+            Action::Membership(membership::Msg::JoinShare(member))
+            | Action::Membership(membership::Msg::ProposeRemove(member, _)) => {
+                member.wire_size() + 1
+            }
+            Action::Membership(membership::Msg::Challenge(_))
+            | Action::Membership(membership::Msg::ChallengeResponse(_)) => U64_SIZE,
+            Action::Membership(membership::Msg::MembershipDigest(..)) => 2 * U64_SIZE,
+            Action::Membership(membership::Msg::Misbehavior(id, evidence)) => {
+                id.wire_size() + evidence.wire_size()
+            }
+            Action::Wallet(ledger::Msg::ReqReissue(tx))
+            | Action::Wallet(ledger::Msg::FallbackReissue(tx))
+            | Action::Wallet(ledger::Msg::AbortVote(tx)) => tx.wire_size(),
+            Action::Wallet(ledger::Msg::ReissueRejected(tx, _)) => tx.wire_size() + 1,
+            Action::Wallet(ledger::Msg::LedgerDigest(_)) => U64_SIZE,
+            Action::Wallet(ledger::Msg::LedgerSync(entries)) => {
+                U64_SIZE
+                    + entries
+                        .iter()
+                        .map(|(tx, receipt)| receipt_size(tx, receipt))
+                        .sum::<usize>()
+            }
+            Action::Wallet(ledger::Msg::ReqReissueBatch(txs)) => {
+                U64_SIZE + txs.iter().map(Tx::wire_size).sum::<usize>()
+            }
+            Action::Wallet(ledger::Msg::Committed(tx, witnesses)) => {
+                tx.wire_size() + witnesses.wire_size()
+            }
+            Action::Wallet(ledger::Msg::TxCommitted(tx, receipt)) => receipt_size(tx, receipt),
+            Action::Wallet(ledger::Msg::DoubleSpend(proof)) => {
+                let (a, b) = &proof.txs;
+                proof.dbc_id.wire_size() + 2 * (a.wire_size() + b.wire_size()) + ID_SIZE
+            }
+            Action::Wallet(ledger::Msg::QuerySpent(dbc_id))
+            | Action::Wallet(ledger::Msg::IsSpentQuery(dbc_id)) => dbc_id.wire_size(),
+            Action::Wallet(ledger::Msg::IsSpentResponse(dbc_id, ..)) => {
+                2 * (dbc_id.wire_size() + 1) + ID_SIZE
+            }
+            Action::Wallet(ledger::Msg::SpentStatus(dbc_id, entry)) => {
+                dbc_id.wire_size()
+                    + 1
+                    + entry
+                        .as_ref()
+                        .map_or(0, |(tx, receipt)| receipt_size(tx, receipt))
+            }
+            Action::Wallet(ledger::Msg::Custody(custody)) => {
+                custody
+                    .committed
+                    .iter()
+                    .map(|(tx, receipt)| receipt_size(tx, receipt))
+                    .sum::<usize>()
+                    + custody.pending.iter().map(Tx::wire_size).sum::<usize>()
+                    + 2 * U64_SIZE
+                    + ID_SIZE
+                    + U64_SIZE
+            }
+            Action::Handover(handover::Msg::ReqHandoverShare(_, prefix, elders, key_set)) => {
+                U64_SIZE
+                    + prefix.wire_size()
+                    + elders.wire_size()
+                    + 1
+                    + key_set.as_ref().map_or(0, KeySet::wire_size)
+            }
+            Action::Handover(handover::Msg::HandoverShare(_, prefix, elders, _)) => {
+                2 * (U64_SIZE + prefix.wire_size() + elders.wire_size()) + ID_SIZE
+            }
+            Action::Handover(handover::Msg::Handover(sap)) => sap.wire_size(),
+            Action::Handover(handover::Msg::ReqSapsSince(_)) => U64_SIZE,
+            Action::Handover(handover::Msg::VotingAt(..)) => 2 * U64_SIZE,
+            Action::Handover(handover::Msg::SapChainSegment(saps)) => {
+                U64_SIZE + saps.iter().map(Sap::wire_size).sum::<usize>()
+            }
+            Action::Dkg(dkg::Msg::Part(candidates, _) | dkg::Msg::Ack(candidates, _)) => {
+                candidates.wire_size() + U64_SIZE
+            }
+            Action::Health(health::Msg::Share(record, _)) => 2 * record.wire_size() + ID_SIZE,
+            Action::Health(health::Msg::Query(_)) => U64_SIZE,
+            Action::Health(health::Msg::Statement(record, section_sig)) => {
+                record.wire_size()
+                    + section_sig.voters.wire_size()
+                    + section_sig.signers().len() * (ID_SIZE + record.wire_size())
+            }
+            Action::Sync {
+                membership_delta,
+                ledger_delta,
+                ..
+            } => {
+                U64_SIZE
+                    + 2 * U64_SIZE
+                    + membership_delta
+                        .joined
+                        .iter()
+                        .chain(membership_delta.left.iter())
+                        .map(Member::wire_size)
+                        .sum::<usize>()
+                    + U64_SIZE
+                    + ledger_delta.iter().map(Tx::wire_size).sum::<usize>()
+            }
+            Action::ReqSync(_) => U64_SIZE,
+            Action::ReqFullSync
+            | Action::StartReissue
+            | Action::TriggerLeave
+            | Action::Heartbeat => 0,
+        };
+
+        // One byte for the variant tag.
+        1 + payload
+    }
+}
+
+// A Tx along with the shares signing it, each the signer's id and the signed Tx.
+fn receipt_size(tx: &Tx, receipt: &SigSet<Tx>) -> usize {
+    tx.wire_size() + U64_SIZE + receipt.ids().len() * (ID_SIZE + tx.wire_size())
+}
+
+impl WireSize for Msg {
+    fn wire_size(&self) -> usize {
+        // One byte for whether a delta's generation follows, or only a generation and a digest do.
+        let sync = match &*self.sync {
+            SyncPayload::StableSet(stable_set, since) => {
+                stable_set.wire_size() + 1 + since.map_or(0, |_| U64_SIZE)
+            }
+            SyncPayload::Digest(..) => 1 + 2 * U64_SIZE,
+        };
+        sync + self.action.wire_size()
+    }
+}
+
+// Model history tallying the serialized size of every message sent, broken down by action kind.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Traffic {
+    pub msgs: usize,
+    pub bytes: usize,
+    pub msgs_by_kind: BTreeMap<&'static str, usize>,
+    pub bytes_by_kind: BTreeMap<&'static str, usize>,
+    // Txs carried by reissue requests, what the request count would be with one Tx per message.
+    pub reissued_txs: usize,
+    // Only tallied on a lossy network, together with `msgs` it tells how many messages were dropped.
+    pub delivered: usize,
+    // Every message sent and delivered in order, only kept when the model records the message log.
+    pub log: Vec<MsgRecord>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MsgRecord {
+    pub step: usize,
+    pub src: Id,
+    pub dst: Id,
+    pub kind: &'static str,
+    // The generation of the stable set the message carried.
+    pub gen: u64,
+    pub delivered: bool,
+    pub ledger: bool,
+}
+
+impl Traffic {
+    pub fn record(&self, msg: &Msg) -> Self {
+        let size = serde_json::to_vec(msg)
+            .map(|bytes| bytes.len())
+            .unwrap_or(0);
+
+        let mut traffic = self.clone();
+        traffic.msgs += 1;
+        traffic.bytes += size;
+        *traffic.msgs_by_kind.entry(msg.action.kind()).or_default() += 1;
+        *traffic.bytes_by_kind.entry(msg.action.kind()).or_default() += size;
+        traffic.reissued_txs += match &*msg.action {
+            Action::Wallet(ledger::Msg::ReqReissue(_)) => 1,
+            Action::Wallet(ledger::Msg::ReqReissueBatch(txs)) => txs.len(),
+            _ => 0,
+        };
+        traffic
+    }
+
+    // Counts a message without serializing it, for when only the drops are of interest.
+    pub fn count_sent(&self) -> Self {
+        Self {
+            msgs: self.msgs + 1,
+            ..self.clone()
+        }
+    }
+
+    pub fn count_delivered(&self) -> Self {
+        Self {
+            delivered: self.delivered + 1,
+            ..self.clone()
+        }
+    }
+
+    pub fn log(&self, delivered: bool, envelope: Envelope<&Msg>) -> Self {
+        let mut traffic = self.clone();
+        traffic.log.push(MsgRecord {
+            step: self.log.len(),
+            src: envelope.src,
+            dst: envelope.dst,
+            kind: envelope.msg.action.kind(),
+            gen: match &*envelope.msg.sync {
+                SyncPayload::StableSet(stable_set, _) => stable_set.gen(),
+                SyncPayload::Digest(gen, _) => *gen,
+            },
+            delivered,
+            ledger: matches!(*envelope.msg.action, Action::Wallet(_)),
+        });
+        traffic
+    }
+
+    // Whatever was sent and neither delivered nor still in flight.
+    pub fn dropped(&self, in_flight: usize) -> usize {
+        self.msgs.saturating_sub(self.delivered + in_flight)
+    }
+
+    pub fn bytes_per(&self, changes: usize) -> usize {
+        self.bytes / changes.max(1)
+    }
+
+    pub fn report(&self, joins: usize, reissues: usize) -> String {
+        format!(
+            "{} msgs, {} bytes, {} bytes/join ({joins} joins), {} bytes/reissue ({reissues} reissues), by kind: {:?}",
+            self.msgs,
+            self.bytes,
+            self.bytes_per(joins),
+            self.bytes_per(reissues),
+            self.bytes_by_kind
+        )
+    }
+}
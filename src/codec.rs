@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use stateright::actor::Id;
+
+use crate::{
+    fake_crypto::SectionSig,
+    stable_set::{Blacklisting, Member, Snapshot, StableSet},
+};
+
+// Serializes maps as a sequence of pairs, JSON only allows string keys and ours are structs.
+pub mod as_pairs {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Vec::<(K, V)>::deserialize(deserializer).map(BTreeMap::from_iter)
+    }
+}
+
+// Layout used before departed members and the blacklist were tracked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StableSetV0 {
+    pub members: BTreeSet<Member>,
+    #[serde(with = "as_pairs")]
+    pub joining_members: BTreeMap<Member, BTreeSet<Id>>,
+    #[serde(with = "as_pairs")]
+    pub leaving_members: BTreeMap<Member, BTreeSet<Id>>,
+}
+
+// Layout used while joins were decided by counting witness ids rather than by signature.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StableSetV1 {
+    #[serde(default)]
+    pub gen: u64,
+    pub members: BTreeSet<Member>,
+    pub dead: BTreeSet<Member>,
+    #[serde(with = "as_pairs")]
+    pub joining_members: BTreeMap<Member, BTreeSet<Id>>,
+    #[serde(with = "as_pairs")]
+    pub leaving_members: BTreeMap<Member, BTreeSet<Id>>,
+    #[serde(default, with = "as_pairs")]
+    pub removing_members: BTreeMap<Member, BTreeSet<Id>>,
+    #[serde(with = "as_pairs")]
+    pub blacklist: BTreeMap<Blacklisting, SectionSig<Blacklisting>>,
+}
+
+// Layout used while forced removals were backed by witness ids rather than signature shares.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StableSetV2 {
+    #[serde(default)]
+    pub gen: u64,
+    pub members: BTreeSet<Member>,
+    pub dead: BTreeSet<Member>,
+    #[serde(with = "as_pairs")]
+    pub joining_members: BTreeMap<Member, SectionSig<Member>>,
+    #[serde(default, with = "as_pairs")]
+    pub proofs: BTreeMap<Member, SectionSig<Member>>,
+    #[serde(with = "as_pairs")]
+    pub leaving_members: BTreeMap<Member, BTreeSet<Id>>,
+    #[serde(default, with = "as_pairs")]
+    pub removing_members: BTreeMap<Member, BTreeSet<Id>>,
+    #[serde(with = "as_pairs")]
+    pub blacklist: BTreeMap<Blacklisting, SectionSig<Blacklisting>>,
+    #[serde(default)]
+    pub snapshot: Option<(Snapshot, SectionSig<Snapshot>)>,
+    #[serde(default, with = "as_pairs")]
+    pub pending_snapshots: BTreeMap<Snapshot, SectionSig<Snapshot>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "version")]
+pub enum VersionedStableSet {
+    #[serde(rename = "0")]
+    V0(StableSetV0),
+    #[serde(rename = "1")]
+    V1(StableSetV1),
+    #[serde(rename = "2")]
+    V2(StableSetV2),
+    #[serde(rename = "3")]
+    V3(StableSet),
+}
+
+impl From<VersionedStableSet> for StableSet {
+    fn from(versioned: VersionedStableSet) -> Self {
+        match versioned {
+            VersionedStableSet::V0(v0) => StableSet::from(v0),
+            VersionedStableSet::V1(v1) => StableSet::from(v1),
+            VersionedStableSet::V2(v2) => StableSet::from(v2),
+            VersionedStableSet::V3(v3) => v3,
+        }
+    }
+}
+
+pub fn encode(stable_set: &StableSet) -> serde_json::Result<Vec<u8>> {
+    serde_json::to_vec(&VersionedStableSet::V3(stable_set.clone()))
+}
+
+// Decodes any version we've ever written, snapshots from before versioning are v0 without a tag.
+pub fn decode(bytes: &[u8]) -> serde_json::Result<StableSet> {
+    match serde_json::from_slice::<VersionedStableSet>(bytes) {
+        Ok(versioned) => Ok(versioned.into()),
+        Err(err) => serde_json::from_slice::<StableSetV0>(bytes)
+            .map(StableSet::from)
+            .map_err(|_| err),
+    }
+}
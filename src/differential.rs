@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+
+use stateright::{actor::Id, Checker, Expectation, Model};
+
+use crate::{
+    fake_crypto::{Quorum, QuorumPolicy},
+    ledger::Aggregation,
+    progress, ModelCfg,
+};
+
+// The protocol variants `compare` knows by name, each the default scenario with one knob turned.
+pub const VARIANTS: [&str; 23] = [
+    "default",
+    "agreed-ord-idx",
+    "serialize-churn",
+    "delta-sync",
+    "digest-sync",
+    "deferred-sync",
+    "hash-only-sync",
+    "read-only-on-leave",
+    "coordinator",
+    "double-spend-proofs",
+    "simple-majority",
+    "max-members",
+    "resource-proof",
+    "pending-ttl",
+    "clients",
+    "health",
+    "snapshots",
+    "msg-log",
+    "prune-spentbook",
+    "query-spent-inputs",
+    "ledger-anti-entropy",
+    "ledger-custody",
+    "sap-catch-up",
+];
+
+pub fn variant(name: &str) -> Option<ModelCfg> {
+    let base = ModelCfg::default();
+    let cfg = match name {
+        "default" => base,
+        "agreed-ord-idx" => ModelCfg {
+            agreed_ord_idx: true,
+            ..base
+        },
+        "serialize-churn" => ModelCfg {
+            serialize_churn: true,
+            ..base
+        },
+        "delta-sync" => ModelCfg {
+            delta_sync: true,
+            ..base
+        },
+        "digest-sync" => ModelCfg {
+            digest_sync: true,
+            ..base
+        },
+        "deferred-sync" => ModelCfg {
+            deferred_sync: true,
+            ..base
+        },
+        "hash-only-sync" => ModelCfg {
+            hash_only_sync: true,
+            ..base
+        },
+        "read-only-on-leave" => ModelCfg {
+            read_only_on_leave: true,
+            ..base
+        },
+        "coordinator" => ModelCfg {
+            aggregation: Aggregation::Coordinator,
+            ..base
+        },
+        "double-spend-proofs" => ModelCfg {
+            double_spend_proofs: true,
+            ..base
+        },
+        "simple-majority" => ModelCfg {
+            quorum: QuorumPolicy::uniform(Quorum::SimpleMajority),
+            ..base
+        },
+        // Fewer places than servers, so later joiners wait in the leader's queue.
+        "max-members" => ModelCfg {
+            max_members: Some(3),
+            ..base
+        },
+        // The last candidate never answers its challenge, which has to expire.
+        "resource-proof" => ModelCfg {
+            resource_proof: true,
+            lazy_candidates: BTreeSet::from([Id::from(base.server_count - 1)]),
+            pending_ttl: Some(2),
+            ..base
+        },
+        "pending-ttl" => ModelCfg {
+            pending_ttl: Some(2),
+            ..base
+        },
+        // Reissues come from a client actor rather than the nodes themselves.
+        "clients" => ModelCfg {
+            client_count: 1,
+            ..base
+        },
+        "health" => ModelCfg {
+            health_period: Some(2),
+            ..base
+        },
+        "snapshots" => ModelCfg {
+            snapshot_period: Some(2),
+            ..base
+        },
+        // Logs every message so the properties reading the log have something to check.
+        "msg-log" => ModelCfg {
+            record_msg_log: true,
+            ..base
+        },
+        "prune-spentbook" => ModelCfg {
+            prune_spentbook: true,
+            ..base
+        },
+        "query-spent-inputs" => ModelCfg {
+            query_spent_inputs: true,
+            ..base
+        },
+        "ledger-anti-entropy" => ModelCfg {
+            ledger_anti_entropy: true,
+            ..base
+        },
+        "ledger-custody" => ModelCfg {
+            ledger_custody: true,
+            ..base
+        },
+        "sap-catch-up" => ModelCfg {
+            sap_catch_up: true,
+            ..base
+        },
+        _ => return None,
+    };
+    Some(cfg)
+}
+
+// Whether each property held under either variant, in the order the model declares them. A bounded
+// check only tells what it found within its depth: an `Always` or `Eventually` property holds when
+// no counterexample turned up, a `Sometimes` one when an example did.
+pub struct Comparison {
+    pub variants: [String; 2],
+    pub states: [usize; 2],
+    pub properties: Vec<(&'static str, [bool; 2])>,
+}
+
+pub fn compare(variants: [(&str, ModelCfg); 2], depth: usize) -> Comparison {
+    let mut states = [0; 2];
+    let mut held = vec![];
+
+    for (i, (_, cfg)) in variants.iter().enumerate() {
+        let model = cfg.clone().into_model();
+        let properties = model.properties();
+        let checker = progress::join(
+            model
+                .checker()
+                .threads(num_cpus::get())
+                .target_max_depth(depth)
+                .spawn_bfs(),
+        );
+
+        states[i] = checker.unique_state_count();
+        let discoveries = checker.discoveries();
+        held.push(Vec::from_iter(properties.iter().map(|p| {
+            let discovered = discoveries.contains_key(p.name);
+            (
+                p.name,
+                discovered == matches!(p.expectation, Expectation::Sometimes),
+            )
+        })));
+    }
+
+    Comparison {
+        variants: variants.map(|(name, _)| name.to_string()),
+        states,
+        properties: Vec::from_iter(
+            held[0]
+                .iter()
+                .zip(held[1].iter())
+                .map(|((name, a), (_, b))| (*name, [*a, *b])),
+        ),
+    }
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .properties
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or_default();
+        let [a, b] = &self.variants;
+        let column = std::cmp::max(a.len(), b.len());
+        let verdict = |held: bool| if held { "holds" } else { "FAILS" };
+
+        writeln!(f, "{:width$}  {a:column$}  {b:column$}", "")?;
+        writeln!(
+            f,
+            "{:width$}  {:column$}  {:column$}",
+            "states", self.states[0], self.states[1]
+        )?;
+        for (name, [held_a, held_b]) in &self.properties {
+            let marker = if held_a != held_b { " <-" } else { "" };
+            writeln!(
+                f,
+                "{name:width$}  {:column$}  {:column$}{marker}",
+                verdict(*held_a),
+                verdict(*held_b)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use stateright::actor::{Id, Out};
+
+use crate::{
+    build_msg,
+    fake_crypto::hash,
+    membership::{Elders, Membership},
+    Node, Rejected,
+};
+
+// The section key the incoming elders generated together. It's complete once every candidate
+// acknowledged the same key.
+#[derive(
+    Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub struct KeySet {
+    pub elders: Elders,
+    pub key: u64,
+    pub acks: BTreeSet<Id>,
+}
+
+impl KeySet {
+    pub fn is_complete(&self) -> bool {
+        self.acks == self.elders
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Msg {
+    // First round, each candidate deals its contribution to the others.
+    Part(Elders, u64),
+    // Second round, sent once every contribution is in, with the key they make up.
+    Ack(Elders, u64),
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+struct Session {
+    parts: BTreeMap<Id, u64>,
+    acks: BTreeMap<Id, u64>,
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Dkg {
+    // One session per set of elder candidates we were one of.
+    sessions: BTreeMap<Elders, Session>,
+    // A slow participant only deals its contribution once its timer fires.
+    pub slow: bool,
+}
+
+// HACK: stands in for a secret share, anyone can check it came from the dealer.
+fn contribution(dealer: Id, candidates: &Elders) -> u64 {
+    hash(&(dealer, candidates))
+}
+
+impl Dkg {
+    pub fn new(slow: bool) -> Self {
+        Self {
+            sessions: Default::default(),
+            slow,
+        }
+    }
+
+    // Joins the DKG for the candidates, unless we're not one of them or already dealt our part.
+    pub fn start(
+        &mut self,
+        membership: &Membership,
+        id: Id,
+        candidates: &Elders,
+        o: &mut Out<Node>,
+    ) {
+        if !candidates.contains(&id) {
+            return;
+        }
+        let session = self.sessions.entry(candidates.clone()).or_default();
+        if session.parts.contains_key(&id) {
+            return;
+        }
+
+        if !self.slow {
+            self.deal(membership, id, candidates, o);
+        }
+    }
+
+    fn deal(&mut self, membership: &Membership, id: Id, candidates: &Elders, o: &mut Out<Node>) {
+        let part = contribution(id, candidates);
+        self.sessions
+            .entry(candidates.clone())
+            .or_default()
+            .parts
+            .insert(id, part);
+        o.broadcast(
+            candidates.iter().filter(|c| c != &&id),
+            &build_msg(membership, Msg::Part(candidates.clone(), part)),
+        );
+        self.try_ack(membership, id, candidates, o);
+    }
+
+    // Acknowledges the key once we hold every contribution.
+    fn try_ack(&mut self, membership: &Membership, id: Id, candidates: &Elders, o: &mut Out<Node>) {
+        let session = self.sessions.entry(candidates.clone()).or_default();
+        if session.parts.len() < candidates.len() || session.acks.contains_key(&id) {
+            return;
+        }
+
+        let key = hash(&session.parts);
+        session.acks.insert(id, key);
+        o.broadcast(
+            candidates.iter().filter(|c| c != &&id),
+            &build_msg(membership, Msg::Ack(candidates.clone(), key)),
+        );
+    }
+
+    pub fn on_msg(
+        &mut self,
+        membership: &Membership,
+        id: Id,
+        src: Id,
+        msg: Msg,
+        o: &mut Out<Node>,
+    ) -> Result<(), Rejected> {
+        match msg {
+            Msg::Part(candidates, part) => {
+                if !candidates.contains(&src) || !candidates.contains(&id) {
+                    return Err(Rejected::NotACandidate);
+                }
+                if part != contribution(src, &candidates) {
+                    return Err(Rejected::BadSignature);
+                }
+
+                // A part can arrive before our own elders decided on the candidates.
+                let session = self.sessions.entry(candidates.clone()).or_default();
+                session.parts.insert(src, part);
+                if session.parts.contains_key(&id) {
+                    self.try_ack(membership, id, &candidates, o);
+                }
+            }
+            Msg::Ack(candidates, key) => {
+                if !candidates.contains(&src) || !candidates.contains(&id) {
+                    return Err(Rejected::NotACandidate);
+                }
+
+                self.sessions
+                    .entry(candidates)
+                    .or_default()
+                    .acks
+                    .insert(src, key);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Deals our contribution if we held it back, and resends what we sent for sessions that haven't
+    // completed in case it was lost.
+    pub fn on_timeout(&mut self, membership: &Membership, id: Id, o: &mut Out<Node>) {
+        let incomplete = Vec::from_iter(
+            self.sessions
+                .keys()
+                .filter(|candidates| self.key_set(candidates).is_none())
+                .cloned(),
+        );
+
+        for candidates in incomplete {
+            let session = &self.sessions[&candidates];
+            let (part, ack) = (
+                session.parts.get(&id).copied(),
+                session.acks.get(&id).copied(),
+            );
+            match part {
+                None => self.deal(membership, id, &candidates, o),
+                Some(part) => o.broadcast(
+                    candidates.iter().filter(|c| c != &&id),
+                    &build_msg(membership, Msg::Part(candidates.clone(), part)),
+                ),
+            }
+            if let Some(key) = ack {
+                o.broadcast(
+                    candidates.iter().filter(|c| c != &&id),
+                    &build_msg(membership, Msg::Ack(candidates.clone(), key)),
+                );
+            }
+        }
+    }
+
+    // Whether we joined the DKG for the candidates and don't hold their key set yet.
+    pub fn is_pending(&self, id: Id, candidates: &Elders) -> bool {
+        candidates.contains(&id)
+            && self.sessions.contains_key(candidates)
+            && self.key_set(candidates).is_none()
+    }
+
+    // The key set for the candidates once every one of them acknowledged the key we derived.
+    pub fn key_set(&self, candidates: &Elders) -> Option<KeySet> {
+        let session = self.sessions.get(candidates)?;
+        if session.parts.len() < candidates.len() {
+            return None;
+        }
+
+        let key = hash(&session.parts);
+        let key_set = KeySet {
+            elders: candidates.clone(),
+            key,
+            acks: BTreeSet::from_iter(
+                session
+                    .acks
+                    .iter()
+                    .filter(|(_, k)| **k == key)
+                    .map(|(id, _)| *id),
+            ),
+        };
+        key_set.is_complete().then_some(key_set)
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+};
+
+use stateright::actor::{Actor, Command, Id, Out};
+
+use crate::{stable_set::Member, Msg, Node, Rejection, State};
+
+// What happened while driving nodes in-process, in the order it happened.
+#[derive(Clone, Debug)]
+pub enum Event {
+    Sent { src: Id, dst: Id, msg: Msg },
+    Delivered { src: Id, dst: Id, msg: Msg },
+    Decided { node: Id, members: BTreeSet<Member> },
+    Rejected { node: Id, rejection: Rejection },
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sent { src, dst, msg } => write!(f, "{src} -> {dst}: {msg:?}"),
+            Self::Delivered { src, dst, msg } => write!(f, "{dst} <- {src}: {msg:?}"),
+            Self::Decided { node, members } => write!(f, "{node} decided {members:?}"),
+            Self::Rejected { node, rejection } => write!(f, "{node} rejected {rejection:?}"),
+        }
+    }
+}
+
+// Runs nodes on the current thread, delivering one message at a time in the order they were sent.
+// Timers never fire, so only message driven behaviour can be walked through.
+pub struct InProcess {
+    nodes: BTreeMap<Id, (Node, State)>,
+    queue: VecDeque<(Id, Id, Msg)>,
+    pub events: Vec<Event>,
+}
+
+impl InProcess {
+    pub fn start(nodes: impl IntoIterator<Item = (Id, Node)>) -> Self {
+        let mut in_process = Self {
+            nodes: BTreeMap::new(),
+            queue: VecDeque::new(),
+            events: vec![],
+        };
+
+        for (id, node) in nodes {
+            let mut o = Out::new();
+            let state = node.on_start(id, &mut o);
+            in_process.nodes.insert(id, (node, state));
+            in_process.dispatch(id, o);
+        }
+
+        in_process
+    }
+
+    pub fn state(&self, id: Id) -> &State {
+        &self.nodes[&id].1
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = (&Id, &State)> + '_ {
+        self.nodes.iter().map(|(id, (_, state))| (id, state))
+    }
+
+    // Delivers the oldest message in flight, returning what happened or None once all is quiet.
+    pub fn step(&mut self) -> Option<&[Event]> {
+        let (src, dst, msg) = self.queue.pop_front()?;
+        let first_event = self.events.len();
+
+        self.events.push(Event::Delivered {
+            src,
+            dst,
+            msg: msg.clone(),
+        });
+
+        let (node, state) = self.nodes.get_mut(&dst)?;
+        let members_before = state.membership.members().clone();
+
+        let mut o = Out::new();
+        let mut cow = Cow::Borrowed(&*state);
+        node.on_msg(dst, &mut cow, src, msg, &mut o);
+        if let Cow::Owned(next_state) = cow {
+            *state = next_state;
+        }
+
+        if let Some(rejection) = state.last_rejection.clone() {
+            self.events.push(Event::Rejected {
+                node: dst,
+                rejection,
+            });
+        }
+
+        let members_after = state.membership.members().clone();
+        if members_before != members_after {
+            self.events.push(Event::Decided {
+                node: dst,
+                members: members_after,
+            });
+        }
+
+        self.dispatch(dst, o);
+
+        Some(&self.events[first_event..])
+    }
+
+    fn dispatch(&mut self, src: Id, o: Out<Node>) {
+        for command in o {
+            if let Command::Send(dst, msg) = command {
+                self.events.push(Event::Sent {
+                    src,
+                    dst,
+                    msg: msg.clone(),
+                });
+                self.queue.push_back((src, dst, msg));
+            }
+        }
+    }
+}
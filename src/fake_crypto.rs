@@ -1,34 +1,311 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::Debug,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
 };
 
 use stateright::actor::Id;
 
+#[cfg(feature = "real-crypto")]
+pub use crate::real_crypto::Ed25519 as Keys;
+#[cfg(not(feature = "real-crypto"))]
+pub type Keys = HashedKeys;
+
+// The threshold every vote took before quorums became configurable, the same as `supermajority`.
 pub fn majority(m: usize, n: usize) -> bool {
+    supermajority(m, n)
+}
+
+// Strictly more than two thirds, so any two quorums overlap in an honest voter as long as fewer
+// than a third are Byzantine.
+pub fn supermajority(m: usize, n: usize) -> bool {
     3 * m > 2 * n
 }
 
+// How many of the voters it takes to decide, so each sub-protocol can be checked with the
+// threshold it actually needs.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Quorum {
+    // More than half, enough against crash faults only.
+    SimpleMajority,
+    // More than two thirds, what `supermajority` checks.
+    #[default]
+    TwoThirds,
+    // More than `num / den` of the voters.
+    Custom(usize, usize),
+}
+
+impl Quorum {
+    pub fn reached(&self, m: usize, n: usize) -> bool {
+        match *self {
+            Quorum::SimpleMajority => 2 * m > n,
+            Quorum::TwoThirds => supermajority(m, n),
+            Quorum::Custom(num, den) => den * m > num * n,
+        }
+    }
+
+    // The fewest of `n` voters that reach the quorum, more than `n` if none do.
+    pub fn threshold(&self, n: usize) -> usize {
+        (0..=n).find(|m| self.reached(*m, n)).unwrap_or(n + 1)
+    }
+
+    // Any two quorums of `n` voters share more than `f` of them, so `f` colluders voting both ways
+    // can't get two conflicting decisions through, nor one on their own.
+    pub fn tolerates(&self, f: usize, n: usize) -> bool {
+        2 * self.threshold(n) > n + f
+    }
+}
+
+// The quorum each sub-protocol decides with, so BFT and crash-fault thresholds can be mixed and
+// compared within one model.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct QuorumPolicy {
+    pub joins: Quorum,
+    // Leaves and forced removals alike.
+    pub leaves: Quorum,
+    pub ledger: Quorum,
+    // Txs that mint or burn, changing the supply can take more than moving value around.
+    pub supply: Quorum,
+    pub handover: Quorum,
+}
+
+impl QuorumPolicy {
+    pub fn uniform(quorum: Quorum) -> Self {
+        Self {
+            joins: quorum,
+            leaves: quorum,
+            ledger: quorum,
+            supply: quorum,
+            handover: quorum,
+        }
+    }
+}
+
+// Every signer counts once unless it's given a weight.
+pub fn total_weight<'a>(
+    ids: impl IntoIterator<Item = &'a Id>,
+    weights: &BTreeMap<Id, u64>,
+) -> usize {
+    ids.into_iter()
+        .map(|id| weights.get(id).copied().unwrap_or(1) as usize)
+        .sum()
+}
+
+fn forged_shares<T>(shares: &BTreeMap<Id, Sig<T>>, sender: Id) -> Vec<Evidence> {
+    Vec::from_iter(
+        shares
+            .iter()
+            .filter(|(signer, sig)| !sig.is_genuine(**signer))
+            .map(|(signer, sig)| Evidence::new(sender, *signer, sig)),
+    )
+}
+
+// HACK: stands in for a cryptographic hash, deterministic but not collision resistant.
+pub fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct PublicKey(pub usize);
+
+impl From<Id> for PublicKey {
+    fn from(id: Id) -> Self {
+        Self(usize::from(id))
+    }
+}
+
+// The bytes a signature covers. serde_json writes struct fields in declaration order and every
+// signed message only holds ordered collections, so equal messages always give equal bytes.
+pub fn signing_bytes<T: serde::Serialize>(msg: &T) -> Vec<u8> {
+    serde_json::to_vec(msg).expect("signed messages always serialize")
+}
+
+// Signed bytes are json, so they read fine in debug output.
+fn fmt_signed(signed: &[u8]) -> String {
+    String::from_utf8_lossy(signed).into_owned()
+}
+
 #[derive(Clone, Eq, Hash, PartialEq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub struct Sig<T> {
-    // HACK: we'll just use the signer's Id and msg as the signature
+    // HACK: we'll just use the signer's Id and the signed bytes as the signature
     signer: Id,
-    msg: T,
+    signed: Vec<u8>,
+    // The generation of the key the signer signed with, a signer's key rotates every time its
+    // section's elders change.
+    #[serde(default)]
+    key_gen: u64,
+    // Only the signer's secret key gives the right tag, so no one can sign in another's name.
+    #[serde(default)]
+    tag: <Keys as SigScheme>::Tag,
+    #[serde(skip)]
+    msg: PhantomData<T>,
 }
 
-impl<T: Debug> Debug for Sig<T> {
+// What backs every signature share. Keys are derived from the signer and the generation of its key,
+// so the protocol never handles key material, and swapping the scheme leaves it untouched.
+pub trait SigScheme {
+    type Tag: Clone
+        + Debug
+        + Default
+        + Eq
+        + Hash
+        + Ord
+        + serde::Serialize
+        + serde::de::DeserializeOwned;
+
+    fn sign(signer: Id, key_gen: u64, signed: &[u8]) -> Self::Tag;
+
+    fn verify(signer: Id, key_gen: u64, signed: &[u8], tag: &Self::Tag) -> bool;
+
+    // A tag in the claimed signer's name, made without its key.
+    fn forge(claimed_signer: Id, key_gen: u64, signed: &[u8]) -> Self::Tag;
+}
+
+// HACK: a keyed hash stands in for each signer's secret key, only the signer itself ever calls
+// `sign`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct HashedKeys;
+
+impl HashedKeys {
+    fn secret_key(signer: Id) -> u64 {
+        hash(&("secret key", signer))
+    }
+}
+
+impl SigScheme for HashedKeys {
+    type Tag = u64;
+
+    fn sign(signer: Id, key_gen: u64, signed: &[u8]) -> u64 {
+        hash(&(Self::secret_key(signer), key_gen, signed))
+    }
+
+    fn verify(signer: Id, key_gen: u64, signed: &[u8], tag: &u64) -> bool {
+        *tag == Self::sign(signer, key_gen, signed)
+    }
+
+    fn forge(claimed_signer: Id, key_gen: u64, signed: &[u8]) -> u64 {
+        hash(&("forged", claimed_signer, key_gen, signed))
+    }
+}
+
+impl<T> Debug for Sig<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}@{:?}", self.msg, self.signer)
+        write!(f, "{}@{:?}", fmt_signed(&self.signed), self.signer)
     }
 }
 
-impl<T: Eq> Sig<T> {
+impl<T> Sig<T> {
+    // Made with the key of the signer it names, whatever message it covers.
+    pub fn is_genuine(&self, claimed_signer: Id) -> bool {
+        self.signer == claimed_signer
+            && Keys::verify(self.signer, self.key_gen, &self.signed, &self.tag)
+    }
+
+    // Only the fake tag is Copy, the real one has to be cloned.
+    #[allow(clippy::clone_on_copy)]
+    fn erase(&self) -> Sig<()> {
+        Sig {
+            signer: self.signer,
+            signed: self.signed.clone(),
+            key_gen: self.key_gen,
+            tag: self.tag.clone(),
+            msg: PhantomData,
+        }
+    }
+}
+
+// A share that isn't genuine for the signer it was handed over as, and the node that sent it. Honest
+// nodes only pass on shares they checked, so whoever sent a forged share forged it.
+#[derive(
+    Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub struct Evidence {
+    pub sender: Id,
+    pub claimed_signer: Id,
+    share: Sig<()>,
+}
+
+impl Evidence {
+    pub fn new<T>(sender: Id, claimed_signer: Id, share: &Sig<T>) -> Self {
+        Self {
+            sender,
+            claimed_signer,
+            share: share.erase(),
+        }
+    }
+
+    // Anyone can check the share is forged, not that `sender` is the one who sent it.
+    pub fn verify(&self) -> bool {
+        !self.share.is_genuine(self.claimed_signer)
+    }
+}
+
+impl<T: serde::Serialize> Sig<T> {
     pub fn verify(&self, id: Id, msg: &T) -> bool {
-        &self.msg == msg && self.signer == id
+        self.signed == signing_bytes(msg)
+            && self.signer == id
+            && Keys::verify(self.signer, self.key_gen, &self.signed, &self.tag)
     }
 
     pub fn sign(signer: Id, msg: T) -> Self {
-        Self { signer, msg }
+        Self::sign_at(signer, 0, msg)
+    }
+
+    pub fn sign_at(signer: Id, key_gen: u64, msg: T) -> Self {
+        let signed = signing_bytes(&msg);
+        Self {
+            signer,
+            tag: Keys::sign(signer, key_gen, &signed),
+            signed,
+            key_gen,
+            msg: PhantomData,
+        }
+    }
+
+    // What a Byzantine node can produce in someone else's name: it looks like their signature but
+    // without their secret key the tag is wrong.
+    pub fn forge(claimed_signer: Id, key_gen: u64, msg: T) -> Self {
+        let signed = signing_bytes(&msg);
+        Self {
+            signer: claimed_signer,
+            tag: Keys::forge(claimed_signer, key_gen, &signed),
+            signed,
+            key_gen,
+            msg: PhantomData,
+        }
+    }
+
+    pub fn key_gen(&self) -> u64 {
+        self.key_gen
     }
 }
 
@@ -37,7 +314,15 @@ pub struct SigSet<T> {
     shares: BTreeMap<Id, Sig<T>>,
 }
 
-impl<T: Eq> SigSet<T> {
+impl<T> Default for SigSet<T> {
+    fn default() -> Self {
+        Self {
+            shares: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: serde::Serialize> SigSet<T> {
     pub fn new() -> Self {
         Self {
             shares: BTreeMap::new(),
@@ -55,35 +340,90 @@ impl<T: Eq> SigSet<T> {
     }
 
     pub fn verify(&self, voters: &BTreeSet<Id>, msg: &T) -> bool {
+        self.verify_weighted(voters, &BTreeMap::new(), msg)
+    }
+
+    // A majority of the voters' weight rather than of their number.
+    pub fn verify_weighted(
+        &self,
+        voters: &BTreeSet<Id>,
+        weights: &BTreeMap<Id, u64>,
+        msg: &T,
+    ) -> bool {
         let valid_shares_from_voters = self
             .shares
             .iter()
             .filter(|(id, _)| voters.contains(id))
             .filter(|(id, sig)| sig.verify(**id, msg))
-            .count();
+            .map(|(id, _)| id);
 
-        majority(valid_shares_from_voters, voters.len())
+        majority(
+            total_weight(valid_shares_from_voters, weights),
+            total_weight(voters, weights),
+        )
     }
 
     pub fn ids(&self) -> BTreeSet<Id> {
-        self.shares.iter().map(|(id, _)| id).cloned().collect()
+        self.shares.keys().cloned().collect()
+    }
+
+    // The voters whose share is genuine, what a quorum check may count.
+    pub fn valid_signers(&self, voters: &BTreeSet<Id>, msg: &T) -> BTreeSet<Id> {
+        BTreeSet::from_iter(
+            self.shares
+                .iter()
+                .filter(|(id, sig)| voters.contains(id) && sig.verify(**id, msg))
+                .map(|(id, _)| *id),
+        )
+    }
+
+    // The shares `verify` would skip for being forged, as evidence against the node that sent them.
+    pub fn forged_shares(&self, sender: Id) -> Vec<Evidence> {
+        forged_shares(&self.shares, sender)
+    }
+
+    // Only the voters' genuine shares.
+    pub fn genuine(&self, voters: &BTreeSet<Id>, msg: &T) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            shares: BTreeMap::from_iter(
+                self.shares
+                    .iter()
+                    .filter(|(id, sig)| voters.contains(id) && sig.verify(**id, msg))
+                    .map(|(id, sig)| (*id, sig.clone())),
+            ),
+        }
+    }
+
+    // The valid shares from the voters, as a section signature.
+    pub fn for_voters(&self, voters: BTreeSet<Id>, msg: &T) -> SectionSig<T>
+    where
+        T: Clone,
+    {
+        let mut sig = SectionSig::new(voters);
+        for (signer, share) in self.shares.iter() {
+            if share.verify(*signer, msg) {
+                sig.add_share(*signer, share.clone());
+            }
+        }
+        sig
     }
 }
 
-impl<T: Debug + Clone + Ord> Debug for SigSet<T> {
+impl<T> Debug for SigSet<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut msgs: BTreeMap<T, BTreeSet<Id>> = Default::default();
+        let mut msgs: BTreeMap<&[u8], BTreeSet<Id>> = Default::default();
 
         for (signer, sig_share) in self.shares.iter() {
-            msgs.entry(sig_share.msg.clone())
-                .or_default()
-                .insert(*signer);
+            msgs.entry(&sig_share.signed).or_default().insert(*signer);
         }
 
         write!(f, "section_sig(")?;
 
         for (msg, signers) in msgs {
-            write!(f, "{msg:?}@{signers:?}")?;
+            write!(f, "{}@{signers:?}", fmt_signed(msg))?;
         }
 
         write!(f, ")")
@@ -94,49 +434,151 @@ impl<T: Debug + Clone + Ord> Debug for SigSet<T> {
 pub struct SectionSig<T> {
     pub voters: BTreeSet<Id>,
     shares: BTreeMap<Id, Sig<T>>,
+    #[serde(default)]
+    pub quorum: Quorum,
+    // Only shares signed with the voters' keys of this generation count.
+    #[serde(default)]
+    pub key_gen: u64,
+    // What each voter's share counts for, voters missing here count once.
+    #[serde(default)]
+    pub weights: BTreeMap<Id, u64>,
 }
 
-impl<T: Eq> SectionSig<T> {
+impl<T: serde::Serialize> SectionSig<T> {
     pub fn new(voters: BTreeSet<Id>) -> Self {
+        Self::with_quorum(voters, Quorum::default())
+    }
+
+    pub fn with_quorum(voters: BTreeSet<Id>, quorum: Quorum) -> Self {
         Self {
             voters,
             shares: Default::default(),
+            quorum,
+            key_gen: 0,
+            weights: BTreeMap::new(),
         }
     }
 
+    pub fn with_key_gen(mut self, key_gen: u64) -> Self {
+        self.key_gen = key_gen;
+        self
+    }
+
+    pub fn with_weights(mut self, weights: BTreeMap<Id, u64>) -> Self {
+        self.weights = weights;
+        self
+    }
+
     pub fn verify(&self, voters: &BTreeSet<Id>, msg: &T) -> bool {
+        self.verify_with(voters, self.quorum, &self.weights, msg)
+    }
+
+    // Counts the shares towards our own quorum and weights, the ones the sig carries are the
+    // sender's say. Whatever comes off the wire is checked this way.
+    pub fn verify_with(
+        &self,
+        voters: &BTreeSet<Id>,
+        quorum: Quorum,
+        weights: &BTreeMap<Id, u64>,
+        msg: &T,
+    ) -> bool {
         &self.voters == voters
-            && self.has_threshold()
-            && self.shares.iter().all(|(id, sig)| sig.verify(*id, msg))
+            && quorum.reached(
+                total_weight(self.shares.keys(), weights),
+                total_weight(voters, weights),
+            )
+            && self
+                .shares
+                .iter()
+                .all(|(id, sig)| sig.verify(*id, msg) && sig.key_gen == self.key_gen)
     }
 
     pub fn add_share(&mut self, signer: Id, sig: Sig<T>) -> bool {
-        if self.voters.contains(&signer) {
+        if self.voters.contains(&signer) && sig.key_gen == self.key_gen {
             self.shares.insert(signer, sig);
         }
 
         self.has_threshold()
     }
 
+    pub fn merge(&mut self, other: SectionSig<T>) -> bool {
+        if self.voters != other.voters {
+            return false;
+        }
+
+        let mut updated = false;
+        for (signer, sig) in other.shares {
+            if !self.shares.contains_key(&signer) {
+                updated = true;
+                self.add_share(signer, sig);
+            }
+        }
+
+        updated
+    }
+
+    pub fn signers(&self) -> BTreeSet<Id> {
+        self.shares.keys().copied().collect()
+    }
+
+    // The shares `add_share` took that were forged, as evidence against the node that sent them.
+    pub fn forged_shares(&self, sender: Id) -> Vec<Evidence> {
+        forged_shares(&self.shares, sender)
+    }
+
+    pub fn is_complete(&self, msg: &T) -> bool {
+        self.verify(&self.voters, msg)
+    }
+
     fn has_threshold(&self) -> bool {
-        majority(self.shares.len(), self.voters.len())
+        self.quorum.reached(
+            total_weight(self.shares.keys(), &self.weights),
+            total_weight(&self.voters, &self.weights),
+        )
     }
 }
 
-impl<T: Debug + Clone + Ord> Debug for SectionSig<T> {
+impl<T: serde::Serialize + Clone> SectionSig<T> {
+    // The same shares counted towards a different set of voters, dropping any that don't verify.
+    pub fn for_voters(&self, voters: BTreeSet<Id>, msg: &T) -> Self {
+        let mut sig = Self::with_quorum(voters, self.quorum)
+            .with_key_gen(self.key_gen)
+            .with_weights(self.weights.clone());
+        sig.merge_shares(self, msg);
+        sig
+    }
+
+    // Takes every valid share `other` holds from one of our voters, whoever it was collected for.
+    // Returns true if we gained a share.
+    pub fn merge_shares(&mut self, other: &SectionSig<T>, msg: &T) -> bool {
+        let mut updated = false;
+        for (signer, sig) in other.shares.iter() {
+            if self.voters.contains(signer)
+                && !self.shares.contains_key(signer)
+                && sig.key_gen == self.key_gen
+                && sig.verify(*signer, msg)
+            {
+                self.shares.insert(*signer, sig.clone());
+                updated = true;
+            }
+        }
+
+        updated
+    }
+}
+
+impl<T: serde::Serialize> Debug for SectionSig<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut msgs: BTreeMap<T, BTreeSet<Id>> = Default::default();
+        let mut msgs: BTreeMap<&[u8], BTreeSet<Id>> = Default::default();
 
         for (signer, sig_share) in self.shares.iter() {
-            msgs.entry(sig_share.msg.clone())
-                .or_default()
-                .insert(*signer);
+            msgs.entry(&sig_share.signed).or_default().insert(*signer);
         }
 
         write!(f, "section_sig(")?;
 
         for (msg, signers) in msgs {
-            write!(f, "{msg:?}@{signers:?}")?;
+            write!(f, "{}@{signers:?}", fmt_signed(msg))?;
         }
 
         if !self.has_threshold() {
@@ -146,3 +588,46 @@ impl<T: Debug + Clone + Ord> Debug for SectionSig<T> {
         write!(f, ")")
     }
 }
+
+// A message together with the section signature vouching for it, the elders that had to sign it and
+// the generation they signed at. Join certificates, SAPs and Tx receipts all travel as one, so
+// anyone knowing the elders can check it on its own.
+#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct Proof<T> {
+    pub msg: T,
+    pub sig: SectionSig<T>,
+    pub signers: BTreeSet<Id>,
+    pub gen: u64,
+}
+
+impl<T: serde::Serialize> Proof<T> {
+    pub fn new(msg: T, sig: SectionSig<T>, gen: u64) -> Self {
+        Self {
+            signers: sig.voters.clone(),
+            msg,
+            sig,
+            gen,
+        }
+    }
+
+    // Holds if `elders` are the ones that signed and enough of them did.
+    pub fn verify(&self, elders: &BTreeSet<Id>) -> bool {
+        &self.signers == elders && self.sig.verify(elders, &self.msg)
+    }
+
+    // The same, counted towards our own quorum and weights, see `SectionSig::verify_with`.
+    pub fn verify_with(
+        &self,
+        elders: &BTreeSet<Id>,
+        quorum: Quorum,
+        weights: &BTreeMap<Id, u64>,
+    ) -> bool {
+        &self.signers == elders && self.sig.verify_with(elders, quorum, weights, &self.msg)
+    }
+}
+
+impl<T: serde::Serialize + Debug> Debug for Proof<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "proof({:?}#{}, {:?})", self.msg, self.gen, self.sig)
+    }
+}
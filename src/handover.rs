@@ -1,39 +1,115 @@
-use std::collections::BTreeSet;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+};
 
 use stateright::actor::{Id, Out};
 
 use crate::{
-    fake_crypto::{SectionSig, Sig},
-    Node,
+    build_msg,
+    dkg::KeySet,
+    fake_crypto::{Evidence, Proof, Quorum, SectionSig, Sig},
+    membership::Membership,
+    section::{id_name, Prefix},
+    Node, Rejected,
 };
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[derive(
+    Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
 pub struct Sap {
-    gen: usize,
-    elders: Elders,
-    sig: SectionSig<(usize, Elders)>,
+    // The elders before signing in the elders of the given generation and section.
+    proof: Proof<SignedIn>,
+    // The key the new elders generated, when handover waits on DKG.
+    key_set: Option<KeySet>,
 }
 impl Sap {
-    fn verify(&self, prev_elders: &BTreeSet<Id>) -> bool {
-        self.sig
-            .verify(prev_elders, &(self.gen, self.elders.clone()))
+    fn new(gen: usize, prefix: Prefix, elders: Elders, sig: SectionSig<SignedIn>) -> Self {
+        Self {
+            proof: Proof::new((gen, prefix, elders), sig, gen as u64),
+            key_set: None,
+        }
+    }
+
+    pub fn gen(&self) -> usize {
+        self.proof.msg.0
+    }
+
+    pub fn prefix(&self) -> Prefix {
+        self.proof.msg.1
+    }
+
+    pub fn elders(&self) -> &Elders {
+        &self.proof.msg.2
+    }
+
+    fn sig(&self) -> &SectionSig<SignedIn> {
+        &self.proof.sig
+    }
+
+    pub fn key_set(&self) -> Option<&KeySet> {
+        self.key_set.as_ref()
+    }
+
+    pub fn signers(&self) -> BTreeSet<Id> {
+        self.sig().signers()
+    }
+
+    pub fn forged_shares(&self, sender: Id) -> Vec<Evidence> {
+        self.sig().forged_shares(sender)
+    }
+
+    // The elders before signed with the keys of their own generation, and counted towards our quorum
+    // rather than whatever quorum the sig claims. Handover counts every elder once, a SAP that
+    // weighs its signers is rejected rather than taken at the sender's word.
+    fn verify(&self, prev_elders: &BTreeSet<Id>, quorum: Quorum) -> bool {
+        self.sig().key_gen + 1 == self.gen() as u64
+            && self.sig().quorum == quorum
+            && self.sig().weights.is_empty()
+            && self
+                .proof
+                .verify_with(prev_elders, quorum, &BTreeMap::new())
     }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Msg {
-    ReqHandoverShare(usize, Elders),
-    HandoverShare(usize, Elders, Sig<(usize, Elders)>),
+    ReqHandoverShare(usize, Prefix, Elders, Option<KeySet>),
+    HandoverShare(usize, Prefix, Elders, Sig<SignedIn>),
     Handover(Sap),
+    // Asks a node further along the handover chain for every SAP after the given generation.
+    ReqSapsSince(usize),
+    SapChainSegment(Vec<Sap>),
+    // The sender only votes on membership views from the given generation on for this handover.
+    VotingAt(usize, u64),
 }
 
 pub type Elders = BTreeSet<Id>;
 
+// The elders of a section at a generation, a split signs in the elders of each half.
+pub type SignedIn = (usize, Prefix, Elders);
+
+// Elders signed in by the ones before them, with the key set they generated if DKG ran.
+type ChainLink = (Prefix, Elders, SectionSig<SignedIn>, Option<KeySet>);
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Handover {
     genesis: Elders,
-    chain: Vec<(Elders, SectionSig<(usize, Elders)>)>,
+    chain: Vec<ChainLink>,
     handover_sig: Option<Sap>,
+    // The sections we signed for each generation and the stable set generation we saw them at.
+    // Elders can see different candidates while membership decisions spread, each signs only the
+    // first sections it's asked about that match its own view, so two sets can't both gather a
+    // majority for the same generation and section.
+    votes: BTreeMap<usize, (u64, BTreeMap<Prefix, Elders>)>,
+    // The latest stable set generation each elder told us it votes at, per handover generation.
+    // Views only move forward, so once too few elders are left that could still sign our vote it
+    // can never be signed in and we're free to vote on our current view instead.
+    voting_at: BTreeMap<usize, BTreeMap<Id, u64>>,
+    // Elders only sign in candidates that completed DKG.
+    pub require_dkg: bool,
+    // The share of the elders before it takes to sign in the next ones.
+    pub quorum: Quorum,
 }
 
 impl Handover {
@@ -43,90 +119,411 @@ impl Handover {
             genesis,
             chain,
             handover_sig: None,
+            votes: BTreeMap::new(),
+            voting_at: BTreeMap::new(),
+            require_dkg: false,
+            quorum: Quorum::default(),
         }
     }
 
     pub fn elders(&self) -> Elders {
-        if let Some((elders, _)) = self.chain.last().cloned() {
+        if let Some((_, elders, ..)) = self.chain.last().cloned() {
             elders
         } else {
             self.genesis.clone()
         }
     }
 
+    pub fn prefix(&self) -> Prefix {
+        self.chain
+            .last()
+            .map_or(Prefix::default(), |(prefix, ..)| *prefix)
+    }
+
+    // The other half of the split that signed in our elders, if the last SAP was one.
+    fn split_sibling(&self) -> Option<Prefix> {
+        let prev = match self.chain.len().checked_sub(2) {
+            Some(i) => self.chain[i].0,
+            None => Prefix::default(),
+        };
+        (self.prefix() != prev)
+            .then(|| self.prefix().sibling())
+            .flatten()
+    }
+
     pub fn gen(&self) -> usize {
         self.chain.len()
     }
 
+    pub fn chain_elders(&self) -> impl Iterator<Item = &Elders> + '_ {
+        self.chain.iter().map(|(_, elders, ..)| elders)
+    }
+
+    // The section each SAP signed in, in chain order.
+    pub fn chain_sections(&self) -> impl Iterator<Item = (&Prefix, &Elders)> + '_ {
+        self.chain
+            .iter()
+            .map(|(prefix, elders, ..)| (prefix, elders))
+    }
+
+    // The genesis elders followed by every elder set signed in since.
+    pub fn elder_sets(&self) -> impl Iterator<Item = &Elders> + '_ {
+        std::iter::once(&self.genesis).chain(self.chain_elders())
+    }
+
+    pub fn key_sets(&self) -> impl Iterator<Item = Option<&KeySet>> + '_ {
+        self.chain.iter().map(|(.., key_set)| key_set.as_ref())
+    }
+
+    // Without DKG any candidates can be signed in, otherwise only those with a complete key set.
+    fn has_key_set(&self, candidates: &Elders, key_set: Option<&KeySet>) -> bool {
+        !self.require_dkg
+            || key_set.is_some_and(|key_set| key_set.is_complete() && &key_set.elders == candidates)
+    }
+
     pub fn on_msg(
         &mut self,
-        elder_candidates: BTreeSet<Id>,
+        membership: &Membership,
         id: Id,
         src: Id,
         msg: Msg,
         o: &mut Out<Node>,
-    ) {
+    ) -> Result<Option<Prefix>, Rejected> {
         let elders = self.elders();
+        let our_prefix = self.prefix();
+        let quorum = self.quorum;
+        let mut split = None;
         match msg {
-            Msg::ReqHandoverShare(gen, candidates) => {
-                if gen == self.gen() + 1 && candidates == elder_candidates {
-                    o.send(
-                        src,
-                        Msg::HandoverShare(gen, elder_candidates, Sig::sign(id, (gen, candidates)))
-                            .into(),
-                    )
+            Msg::ReqHandoverShare(gen, prefix, candidates, key_set) => {
+                if !self.has_key_set(&candidates, key_set.as_ref()) {
+                    return Err(Rejected::IncompleteKeySet);
+                }
+                // Elders that went on with their half of a split still sign in the other half.
+                let for_sibling = gen == self.gen() && self.split_sibling() == Some(prefix);
+                if gen != self.gen() + 1 && !for_sibling {
+                    return Ok(None);
                 }
+                let in_view = membership.next_sections().get(&prefix) == Some(&candidates);
+                match self.votes.get(&gen) {
+                    Some((_, vote)) if vote.get(&prefix) == Some(&candidates) => {}
+                    Some(_) if in_view => return Err(Rejected::ConflictingCandidates),
+                    None if in_view && !for_sibling => {
+                        self.votes.insert(
+                            gen,
+                            (membership.stable_set.gen(), membership.next_sections()),
+                        );
+                        self.announce_vote(membership, id, gen, o);
+                    }
+                    _ => return Ok(None),
+                }
+
+                o.send(
+                    src,
+                    build_msg(
+                        membership,
+                        Msg::HandoverShare(
+                            gen,
+                            prefix,
+                            candidates.clone(),
+                            Sig::sign_at(id, gen as u64 - 1, (gen, prefix, candidates)),
+                        ),
+                    ),
+                )
             }
-            Msg::HandoverShare(gen, candidates, sig) => {
+            Msg::HandoverShare(gen, prefix, candidates, sig) => {
                 if let Some(sap) = self.handover_sig.as_mut() {
-                    if sap.gen == gen
-                        && sap.elders == candidates
-                        && elders.contains(&src)
-                        && sig.verify(src, &(gen, candidates))
-                    {
-                        sap.sig.add_share(src, sig);
+                    if sap.gen() == gen && sap.prefix() == prefix && sap.elders() == &candidates {
+                        if !elders.contains(&src) {
+                            return Err(Rejected::NotAnElder);
+                        }
+                        if !sig.verify(src, &(gen, prefix, candidates)) {
+                            return Err(Rejected::BadSignature);
+                        }
+                        // Signed by an elder still on an older key.
+                        if sig.key_gen() != sap.sig().key_gen {
+                            return Err(Rejected::WrongKeyGen);
+                        }
 
-                        if sap.verify(&elders) {
+                        sap.proof.sig.add_share(src, sig);
+
+                        if sap.verify(&elders, quorum) {
+                            // The members of a new half learn of the split from its first SAP.
+                            let members =
+                                membership.members().iter().map(|m| m.id).filter(|m| {
+                                    prefix != our_prefix && prefix.matches(id_name(*m))
+                                });
                             o.broadcast(
                                 &BTreeSet::from_iter(
-                                    elders.iter().chain(sap.elders.iter()).copied(),
+                                    elders.iter().chain(sap.elders()).copied().chain(members),
                                 ),
-                                &Msg::Handover(sap.clone()).into(),
+                                &build_msg(membership, Msg::Handover(sap.clone())),
                             );
                         }
                     }
                 }
             }
-            Msg::Handover(sap) => {
-                if sap.gen == self.gen() + 1 && sap.verify(&elders) {
-                    self.chain.push((sap.elders, sap.sig))
+            // We're missing the SAPs in between, so can't tell who should have signed it yet.
+            Msg::Handover(sap) if sap.gen() > self.gen() + 1 => {
+                o.send(src, build_msg(membership, Msg::ReqSapsSince(self.gen())));
+            }
+            Msg::Handover(sap) => split = self.extend_chain(id, sap)?,
+            Msg::ReqSapsSince(gen) => {
+                if gen < self.gen() {
+                    let saps = Vec::from_iter((gen + 1..=self.gen()).map(|gen| self.sap(gen)));
+                    o.send(src, build_msg(membership, Msg::SapChainSegment(saps)));
+                }
+            }
+            Msg::SapChainSegment(saps) => {
+                for sap in saps {
+                    split = self.extend_chain(id, sap)?.or(split);
                 }
             }
+            Msg::VotingAt(gen, membership_gen) => {
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+                let heard = self
+                    .voting_at
+                    .entry(gen)
+                    .or_default()
+                    .entry(src)
+                    .or_default();
+                *heard = membership_gen.max(*heard);
+
+                if let Some((voted_at, _)) = self.votes.get(&gen) {
+                    if gen == self.gen() + 1 && !self.may_still_sign_in(gen, *voted_at) {
+                        self.votes.remove(&gen);
+                        self.announce_vote(membership, id, gen, o);
+                    }
+                }
+
+                // The sender may have dropped a vote for other candidates, ask it again.
+                if let Some(sap) = self.handover_sig.as_ref() {
+                    if sap.gen() == gen
+                        && !sap.signers().contains(&src)
+                        && !sap.verify(&elders, quorum)
+                    {
+                        o.send(
+                            src,
+                            build_msg(
+                                membership,
+                                Msg::ReqHandoverShare(
+                                    gen,
+                                    sap.prefix(),
+                                    sap.elders().clone(),
+                                    sap.key_set.clone(),
+                                ),
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(split)
+    }
+
+    // Tells the elders and the candidates we see the stable set generation we vote at from now on.
+    fn announce_vote(&self, membership: &Membership, id: Id, gen: usize, o: &mut Out<Node>) {
+        let recipients = BTreeSet::from_iter(self.elders().into_iter().chain(membership.elders()));
+        o.broadcast(
+            recipients.iter().filter(|e| e != &&id),
+            &build_msg(membership, Msg::VotingAt(gen, membership.stable_set.gen())),
+        );
+    }
+
+    // Whether enough elders could still sign the candidates voted at the given stable set
+    // generation. Elders that told us they vote on later views never sign those candidates again.
+    fn may_still_sign_in(&self, gen: usize, voted_at: u64) -> bool {
+        let elders = self.elders();
+        let moved_on = self.voting_at.get(&gen).map_or(0, |heard| {
+            elders
+                .iter()
+                .filter(|e| heard.get(e).is_some_and(|at| *at > voted_at))
+                .count()
+        });
+        elders.len() - moved_on >= self.quorum.threshold(elders.len())
+    }
+
+    // Every SAP we hold, along with the one we're collecting shares for once it gathered a majority.
+    pub fn verified_saps(&self) -> Vec<Sap> {
+        let mut saps = Vec::from_iter((1..=self.gen()).map(|gen| self.sap(gen)));
+        if let Some(sap) = &self.handover_sig {
+            if sap.verify(&sap.proof.signers, self.quorum) {
+                saps.push(sap.clone());
+            }
         }
+        saps
+    }
+
+    // The SAP that signed in the elders of the given generation, at least 1.
+    fn sap(&self, gen: usize) -> Sap {
+        let (prefix, elders, sig, key_set) = self.chain[gen - 1].clone();
+        Sap {
+            key_set,
+            ..Sap::new(gen, prefix, elders, sig)
+        }
+    }
+
+    // Appends the SAP following our last one, SAPs we already hold are checked against our chain.
+    // Returns the half of our section we go on with when the SAP signed in either half of a split.
+    fn extend_chain(&mut self, id: Id, sap: Sap) -> Result<Option<Prefix>, Rejected> {
+        match sap.gen().cmp(&(self.gen() + 1)) {
+            // A SAP we already hold, or the other half of a split we went through, unless it forks
+            // our history.
+            Ordering::Less => {
+                if sap.gen() == 0 {
+                    return Err(Rejected::UnlinkedSap);
+                }
+                let (prefix, elders, ..) = &self.chain[sap.gen() - 1];
+                let is_sibling = prefix.sibling() == Some(sap.prefix());
+                if !is_sibling && (prefix, elders) != (&sap.prefix(), sap.elders()) {
+                    return Err(Rejected::UnlinkedSap);
+                }
+                Ok(None)
+            }
+            Ordering::Equal => {
+                if !sap.verify(&self.elders(), self.quorum) {
+                    return Err(Rejected::BadSignature);
+                }
+                if !self.has_key_set(sap.elders(), sap.key_set.as_ref()) {
+                    return Err(Rejected::IncompleteKeySet);
+                }
+                let parent = self.prefix();
+                let is_split = parent
+                    .children()
+                    .is_some_and(|halves| halves.contains(&sap.prefix()));
+                if sap.prefix() != parent && !is_split {
+                    return Err(Rejected::UnlinkedSap);
+                }
+
+                // We only go on with the half of a split we fall in.
+                let (gen, prefix, elders) = sap.proof.msg;
+                if !is_split || prefix.matches(id_name(id)) {
+                    self.chain
+                        .push((prefix, elders, sap.proof.sig, sap.key_set));
+                    // Keep our vote on a split, the elders of the other half still need our share.
+                    self.votes
+                        .retain(|g, _| *g > gen || (is_split && *g == gen));
+                    self.voting_at.retain(|g, _| *g > gen);
+                    debug_assert!(self.verify_chain());
+                }
+                Ok(is_split.then(|| parent.child_for(id_name(id))).flatten())
+            }
+            Ordering::Greater => Err(Rejected::UnlinkedSap),
+        }
+    }
+
+    // What colluding elders can put together to sign in candidates the section never decided on.
+    // Their own shares are genuine, and while those fall short of a majority they make up the rest
+    // with shares forged in the names of the honest elders.
+    pub fn forge_sap(&self, byzantine: &BTreeSet<Id>, candidates: Elders) -> Sap {
+        let gen = self.gen() + 1;
+        let key_gen = self.gen() as u64;
+        let msg = (gen, self.prefix(), candidates.clone());
+        let (colluders, honest): (Vec<Id>, Vec<Id>) = self
+            .elders()
+            .into_iter()
+            .partition(|e| byzantine.contains(e));
+
+        let mut sig = SectionSig::with_quorum(self.elders(), self.quorum).with_key_gen(key_gen);
+        let mut reached = false;
+        for elder in colluders {
+            reached = sig.add_share(elder, Sig::sign_at(elder, key_gen, msg.clone()));
+        }
+        if !reached {
+            for elder in honest {
+                sig.add_share(elder, Sig::forge(elder, key_gen, msg.clone()));
+            }
+        }
+
+        Sap::new(gen, self.prefix(), candidates, sig)
+    }
+
+    // Asks the decided elders for the SAPs we missed, if we don't hold one signing them in yet.
+    pub fn catch_up(&self, membership: &Membership, id: Id, o: &mut Out<Node>) {
+        let decided = membership.elders();
+        if self.elders() != decided {
+            o.broadcast(
+                decided.iter().filter(|e| e != &&id),
+                &build_msg(membership, Msg::ReqSapsSince(self.gen())),
+            );
+        }
+    }
+
+    // Walks the chain from genesis, each SAP must be signed by the elders of the generation before,
+    // for their own section or either half of it.
+    pub fn verify_chain(&self) -> bool {
+        let (mut prev_prefix, mut prev_elders) = (Prefix::default(), &self.genesis);
+        for (i, (prefix, elders, sig, _)) in self.chain.iter().enumerate() {
+            let linked = *prefix == prev_prefix
+                || prev_prefix
+                    .children()
+                    .is_some_and(|halves| halves.contains(prefix));
+            if !linked
+                || sig.key_gen != i as u64
+                || sig.quorum != self.quorum
+                || !sig.weights.is_empty()
+                || !sig.verify_with(
+                    prev_elders,
+                    self.quorum,
+                    &BTreeMap::new(),
+                    &(i + 1, *prefix, elders.clone()),
+                )
+            {
+                return false;
+            }
+            (prev_prefix, prev_elders) = (*prefix, elders);
+        }
+        true
     }
 
     pub(crate) fn try_trigger_handover(
         &mut self,
         id: Id,
-        elder_candidates: BTreeSet<Id>,
+        membership: &Membership,
+        key_set: Option<KeySet>,
         o: &mut Out<Node>,
     ) {
-        if self.elders() != elder_candidates && elder_candidates.contains(&id) {
+        // Our half once the section is due to split.
+        let Some((prefix, elder_candidates)) = membership.next_section(id) else {
+            return;
+        };
+        if !self.has_key_set(&elder_candidates, key_set.as_ref()) {
+            return;
+        }
+        if (self.prefix(), self.elders()) != (prefix, elder_candidates.clone())
+            && elder_candidates.contains(&id)
+        {
             let sap = Sap {
-                gen: self.gen() + 1,
-                elders: elder_candidates.clone(),
-                sig: SectionSig::new(self.elders()),
+                key_set: key_set.clone(),
+                ..Sap::new(
+                    self.gen() + 1,
+                    prefix,
+                    elder_candidates.clone(),
+                    SectionSig::with_quorum(self.elders(), self.quorum)
+                        .with_key_gen(self.gen() as u64),
+                )
             };
 
-            if Some(&sap) == self.handover_sig.as_ref() {
-                return;
+            // Already collecting shares for these candidates, restarting would drop them.
+            if let Some(pending) = self.handover_sig.as_ref() {
+                if pending.gen() == sap.gen()
+                    && pending.prefix() == sap.prefix()
+                    && pending.elders() == sap.elders()
+                {
+                    return;
+                }
             }
 
             self.handover_sig = Some(sap);
 
             o.broadcast(
                 &self.elders(),
-                &Msg::ReqHandoverShare(self.gen() + 1, elder_candidates).into(),
+                &build_msg(
+                    membership,
+                    Msg::ReqHandoverShare(self.gen() + 1, prefix, elder_candidates, key_set),
+                ),
             )
         }
     }
@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use stateright::actor::{Id, Out};
+
+use crate::{
+    build_msg,
+    fake_crypto::{Quorum, SectionSig, Sig, SigSet},
+    membership::{Elders, Membership},
+    Node, Rejected,
+};
+
+// What the elders attest the section looked like at a stable set generation. Only what every node
+// at the generation agrees on goes in, pending changes and spentbooks differ from elder to elder
+// and would keep their shares from adding up.
+#[derive(
+    Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub struct HealthRecord {
+    pub gen: u64,
+    pub member_count: usize,
+    pub members_digest: u64,
+}
+
+impl HealthRecord {
+    pub fn observe(membership: &Membership) -> Self {
+        Self {
+            gen: membership.stable_set.gen(),
+            member_count: membership.members().len(),
+            members_digest: membership.stable_set.members_digest(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Msg {
+    Share(HealthRecord, Sig<HealthRecord>),
+    // Anyone can ask for the statement covering a generation.
+    Query(u64),
+    Statement(HealthRecord, SectionSig<HealthRecord>),
+}
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Health {
+    // Section signed statements, oldest first.
+    chain: Vec<(HealthRecord, SectionSig<HealthRecord>)>,
+    pending: BTreeMap<HealthRecord, SigSet<HealthRecord>>,
+    signed_gens: BTreeSet<u64>,
+}
+
+impl Health {
+    pub fn chain(&self) -> impl Iterator<Item = &(HealthRecord, SectionSig<HealthRecord>)> + '_ {
+        self.chain.iter()
+    }
+
+    pub fn latest(&self) -> Option<&HealthRecord> {
+        self.chain.last().map(|(record, _)| record)
+    }
+
+    // The newest statement made at or before `gen`.
+    pub fn statement_at(&self, gen: u64) -> Option<&(HealthRecord, SectionSig<HealthRecord>)> {
+        self.chain.iter().rev().find(|(record, _)| record.gen <= gen)
+    }
+
+    // Every `period` generations each elder signed in through handover signs what it sees and
+    // sends the share to the others. The other members ask the elders for the newest statement.
+    pub fn on_gen(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        period: u64,
+        o: &mut Out<Node>,
+    ) {
+        let gen = membership.stable_set.gen();
+        if gen == 0 || !gen.is_multiple_of(period) || !self.signed_gens.insert(gen) {
+            return;
+        }
+
+        if !elders.contains(&id) {
+            o.broadcast(elders, &build_msg(membership, Msg::Query(gen)));
+            return;
+        }
+
+        let record = HealthRecord::observe(membership);
+        let sig = Sig::sign(id, record.clone());
+        o.broadcast(elders, &build_msg(membership, Msg::Share(record, sig)));
+    }
+
+    // `elder_sets` are the elders of every SAP we hold, shares and statements only count when they
+    // come from one of them.
+    pub fn on_msg(
+        &mut self,
+        membership: &Membership,
+        elder_sets: &[Elders],
+        src: Id,
+        msg: Msg,
+        o: &mut Out<Node>,
+    ) -> Result<(), Rejected> {
+        match msg {
+            Msg::Share(record, sig) => self.on_share(elder_sets, src, record, sig)?,
+            Msg::Query(gen) => {
+                if let Some((record, section_sig)) = self.statement_at(gen).cloned() {
+                    o.send(
+                        src,
+                        build_msg(membership, Msg::Statement(record, section_sig)),
+                    );
+                }
+            }
+            Msg::Statement(record, section_sig) => {
+                // Statements are put together with the default quorum and every elder counting
+                // once, whatever the sender claims.
+                if !elder_sets.iter().any(|elders| {
+                    section_sig.verify_with(elders, Quorum::default(), &BTreeMap::new(), &record)
+                }) {
+                    return Err(Rejected::BadSignature);
+                }
+                if self.latest().map(|r| r.gen < record.gen).unwrap_or(true) {
+                    self.append(record, section_sig);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Collects the shares for a record until a quorum of one of the elder sets signed it.
+    fn on_share(
+        &mut self,
+        elder_sets: &[Elders],
+        src: Id,
+        record: HealthRecord,
+        sig: Sig<HealthRecord>,
+    ) -> Result<(), Rejected> {
+        if !elder_sets.iter().any(|elders| elders.contains(&src)) {
+            return Err(Rejected::NotAnElder);
+        }
+        if !sig.verify(src, &record) {
+            return Err(Rejected::BadSignature);
+        }
+        if self.latest().map(|r| r.gen >= record.gen).unwrap_or(false) {
+            return Ok(());
+        }
+
+        let shares = self.pending.entry(record.clone()).or_default();
+        shares.add_share(src, sig);
+        let section_sig = elder_sets
+            .iter()
+            .rev()
+            .map(|elders| shares.for_voters(elders.clone(), &record))
+            .find(|section_sig| section_sig.is_complete(&record));
+        if let Some(section_sig) = section_sig {
+            self.append(record, section_sig);
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, record: HealthRecord, section_sig: SectionSig<HealthRecord>) {
+        let gen = record.gen;
+        self.chain.push((record, section_sig));
+        self.pending.retain(|pending, _| pending.gen > gen);
+    }
+}
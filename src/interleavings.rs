@@ -0,0 +1,136 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::Arc,
+};
+
+use stateright::actor::{Command, Id, Out};
+
+use crate::{
+    build_msg,
+    fake_crypto::{hash, SectionSig},
+    membership::{Membership, SyncPayload},
+    stable_set::Member,
+    Action, Msg,
+};
+
+type Envelope = (Id, Id, Msg);
+
+// What a node ended up deciding, along with the signatures and witnesses of anything still pending.
+type Outcome = (
+    BTreeSet<Member>,
+    BTreeMap<Member, SectionSig<Member>>,
+    BTreeMap<Member, BTreeSet<Id>>,
+);
+
+// Delivers the messages of a two elder, one joiner scenario in every possible order, without going
+// through the actor model, and checks that every order ends with the same decisions on every node.
+#[test]
+fn join_interleavings_commute() {
+    let genesis = BTreeSet::from_iter([Id::from(0), Id::from(1)]);
+    let joiner = Id::from(2);
+
+    let nodes = Vec::from_iter((0..3).map(|_| Membership::new(&genesis, Default::default())));
+    let network = Vec::from_iter(
+        genesis
+            .iter()
+            .map(|elder| (joiner, *elder, nodes[2].req_join(joiner))),
+    );
+
+    let mut visited = HashSet::new();
+    let mut outcomes = HashSet::new();
+    explore(nodes, network, &mut visited, &mut outcomes);
+
+    assert_eq!(outcomes.len(), 1, "interleavings diverged: {outcomes:#?}");
+
+    let outcome = outcomes.into_iter().next().unwrap();
+    assert!(
+        outcome
+            .iter()
+            .all(|(members, _, _)| members.iter().any(|m| m.id == joiner)),
+        "joiner was not admitted: {outcome:#?}"
+    );
+}
+
+fn explore(
+    nodes: Vec<Membership>,
+    mut network: Vec<Envelope>,
+    visited: &mut HashSet<(Vec<Membership>, Vec<Envelope>)>,
+    outcomes: &mut HashSet<Vec<Outcome>>,
+) {
+    // The same messages in flight in another order are the same state.
+    network.sort_by_cached_key(hash);
+    if !visited.insert((nodes.clone(), network.clone())) {
+        return;
+    }
+
+    if network.is_empty() {
+        outcomes.insert(Vec::from_iter(nodes.iter().map(|n| {
+            (
+                n.members().clone(),
+                n.stable_set.joining_members.clone(),
+                n.stable_set.leaving_members.clone(),
+            )
+        })));
+        return;
+    }
+
+    for i in 0..network.len() {
+        let mut nodes = nodes.clone();
+        let mut network = network.clone();
+
+        let (src, dst, msg) = network.remove(i);
+        network.extend(deliver(&mut nodes[usize::from(dst)], dst, src, msg));
+
+        explore(nodes, network, visited, outcomes);
+    }
+}
+
+// Mirrors the membership half of `Node::on_msg`.
+fn deliver(membership: &mut Membership, id: Id, src: Id, msg: Msg) -> Vec<Envelope> {
+    let elders = membership.elders();
+    // The scenario runs with the whole stable set embedded in every message.
+    let SyncPayload::StableSet(stable_set, since) = Arc::unwrap_or_clone(msg.sync) else {
+        unreachable!("no digests are sent in this scenario")
+    };
+    let mut nodes_to_sync = membership.merge(*stable_set, since, id, src);
+    let mut o = Out::new();
+
+    match Arc::unwrap_or_clone(msg.action) {
+        Action::Membership(msg) => {
+            if let Ok(additional_members_to_sync) = membership.on_msg(&elders, id, src, msg, &mut o)
+            {
+                nodes_to_sync.extend(additional_members_to_sync);
+            }
+        }
+        Action::Sync {
+            membership_delta, ..
+        } => {
+            nodes_to_sync.extend(membership.apply_delta(id, src, membership_delta));
+        }
+        _ => (),
+    }
+
+    nodes_to_sync.extend(membership.process_pending_actions(id));
+    nodes_to_sync.remove(&id);
+
+    let sync = build_msg(
+        membership,
+        Action::Sync {
+            gen: membership.stable_set.gen(),
+            membership_delta: membership.delta_since(membership.stable_set.gen().saturating_sub(1)),
+            ledger_delta: Default::default(),
+        },
+    );
+
+    let replies = o.into_iter().filter_map(|command| match command {
+        Command::Send(dst, msg) => Some((id, dst, msg)),
+        _ => None,
+    });
+
+    Vec::from_iter(
+        nodes_to_sync
+            .into_iter()
+            .map(|dst| (id, dst, sync.clone()))
+            .chain(replies),
+    )
+}
@@ -1,103 +1,1380 @@
 use std::collections::{BTreeMap, BTreeSet};
 
-use stateright::actor::{Id, Out};
+use stateright::actor::{model_timeout, Id, Out};
 
 use crate::{
     build_msg,
-    fake_crypto::{majority, SigSet},
+    fake_crypto::{hash, majority, Proof, QuorumPolicy, Sig, SigSet},
     membership::{Elders, Membership},
+    section::{dbc_name, Prefix},
+    Rejected,
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 pub enum Msg {
     ReqReissue(Tx),
+    // Independent Txs requested together, elders witness either all of them or none.
+    ReqReissueBatch(BTreeSet<Tx>),
+    // Sent by the aggregation coordinator once it has collected a majority of elder shares.
+    Committed(Tx, BTreeSet<Id>),
+    // Sent by elders that gave up waiting on the coordinator, handled like the symmetric scheme.
+    FallbackReissue(Tx),
+    // Sent by an elder that saw the Tx conflict with a lesser one, promising never to witness it.
+    AbortVote(Tx),
+    // Asks a spentbook node which Tx spent the dbc, answered with the Tx and the elders whose
+    // witnessing committed it, or None if it's unspent as far as that node knows.
+    QuerySpent(DbcId),
+    SpentStatus(DbcId, Option<(Tx, SigSet<Tx>)>),
+    // Sent by an elder to the requester of a Tx it won't witness, with the reason why.
+    ReissueRejected(Tx, TxValidationError),
+    // An elder's receipt for a Tx it saw commit, sent to the Tx's spenders.
+    TxCommitted(Tx, SigSet<Tx>),
+    // Sent by an elder that saw two Txs spend the same dbc, every elder rejects both.
+    DoubleSpend(DoubleSpendProof),
+    // Asks an elder whether the dbc is spent, answered with its signed yes or no. Unlike
+    // QuerySpent the answers of a majority of the elders add up to an answer from the section.
+    IsSpentQuery(DbcId),
+    IsSpentResponse(DbcId, bool, Sig<(DbcId, bool)>),
+    // An elder's spentbook digest, sent to the other elders now and then. An elder holding a
+    // different spentbook answers with every committed Tx it knows and the receipt shares for it.
+    LedgerDigest(u64),
+    LedgerSync(BTreeSet<(Tx, SigSet<Tx>)>),
+    // Sent by an outgoing elder to the incoming ones once handover commits.
+    Custody(Custody),
+}
+
+// Why the ledger won't witness a Tx.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum TxValidationError {
+    BadSums,
+    // An input's owner didn't sign the Tx.
+    Unauthorized,
+    Aborted,
+    DoubleSpend,
+    NotDenominated,
+    DuplicateInput,
+    InvalidInput,
+    // An input belongs to another section's shard.
+    WrongSection,
+    // The Tx that created an input hasn't committed here.
+    UnknownParent,
+    AlreadySpent,
+    ConflictsWithPending,
+    // We promised never to witness it.
+    VotedAbort,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Aggregation {
+    // Every elder re-broadcasts each reissue request to every other elder.
+    #[default]
+    Symmetric,
+    // Elders only send their share to a coordinator elder picked by Tx hash, falling back to the
+    // symmetric scheme if the coordinator doesn't produce a commit before the timer fires.
+    Coordinator,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CoinSelection {
+    #[default]
+    LargestFirst,
+    SmallestFirst,
+    ExactMatch,
+}
+
+impl CoinSelection {
+    // Returns the inputs to spend for the given amount, or None if the dbcs can't cover it.
+    pub fn select(&self, dbcs: &BTreeSet<Dbc>, amount: u64) -> Option<Vec<Dbc>> {
+        let mut candidates = Vec::from_iter(dbcs.iter().cloned());
+
+        match self {
+            Self::LargestFirst => candidates.sort_by_key(|dbc| std::cmp::Reverse(dbc.amount())),
+            Self::SmallestFirst => candidates.sort_by_key(Dbc::amount),
+            Self::ExactMatch => {
+                if let Some(exact) = candidates.iter().find(|dbc| dbc.amount() == amount) {
+                    return Some(vec![exact.clone()]);
+                }
+                return Self::LargestFirst.select(dbcs, amount);
+            }
+        }
+
+        let mut selected = vec![];
+        let mut total = 0;
+        for dbc in candidates {
+            if total >= amount && !selected.is_empty() {
+                break;
+            }
+            total += dbc.amount();
+            selected.push(dbc);
+        }
+
+        if total >= amount && !selected.is_empty() {
+            Some(selected)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Wallet {
+    // Outputs paid to this id are ours to spend.
+    pub owner: Id,
     pub ledger: Ledger,
+    // Dbcs we may spend, outputs paid to us along with the unowned genesis dbc.
+    dbcs: BTreeSet<Dbc>,
+    // Outputs of our Txs that only become spendable once a majority of the elders signed a receipt.
+    unconfirmed: BTreeSet<Dbc>,
+    // Receipt shares for each Tx, and the receipt itself once a majority of the elders signed.
+    // Elders collect them for every Tx they commit, it's what they show for the spend to others.
+    pub receipts: BTreeMap<Tx, SigSet<Tx>>,
+    pub confirmed: BTreeMap<Tx, Proof<Tx>>,
+    pub coin_selection: CoinSelection,
+    pub aggregation: Aggregation,
+    pub fallback_txs: BTreeSet<Tx>,
+    // Our Txs are resubmitted to the current elders up to this many times, until they commit or a
+    // majority of the elders rejects them.
+    pub reissue_retries: Option<usize>,
+    // Our Txs that haven't committed or been rejected yet, with how often they were resubmitted.
+    pub submitted: BTreeMap<Tx, usize>,
+    // Our Txs that neither committed nor got rejected by the time the retries ran out.
+    pub abandoned: BTreeSet<Tx>,
+    // The elders that rejected each of our Txs, and why.
+    pub rejections: BTreeMap<Tx, BTreeMap<Id, TxValidationError>>,
+    pub rejected: BTreeSet<Tx>,
+    // Conflicting Txs are reported with a double spend proof and both rejected, rather than
+    // aborting one of them in favour of the other.
+    pub double_spend_proofs: bool,
+    // How many times we spent an output of our own freshly committed Txs.
+    pub chained_spends: usize,
+    // Once a Tx of ours commits we ask the elders whether its inputs are spent.
+    pub query_spent_inputs: bool,
+    // Elder answers to our spent queries, and the answer a majority of the elders agreed on along
+    // with those elders and whether, as the answer came in, we held a Tx spending the dbc that those
+    // same elders had committed.
+    pub spent_answers: BTreeMap<(DbcId, bool), SigSet<(DbcId, bool)>>,
+    pub spent_verdicts: BTreeMap<DbcId, (bool, Elders, bool)>,
+    // The dbcs we asked the spentbook nodes about and have no answer for yet.
+    pub queried: BTreeSet<DbcId>,
+    // The last spentbook digest each elder sent us, and what ours was when it arrived.
+    pub heard_digests: BTreeMap<Id, (u64, u64)>,
+    // Our reissue requests are held back and sent as one batch once the node is done handling a
+    // message.
+    pub batch_reissues: bool,
+    pub batch: BTreeSet<Tx>,
+    // The supply change our payments make.
+    pub supply: Supply,
+}
+
+pub fn coordinator(elders: &Elders, tx: &Tx) -> Option<Id> {
+    if elders.is_empty() {
+        return None;
+    }
+
+    elders
+        .iter()
+        .nth((hash(tx) % elders.len() as u64) as usize)
+        .copied()
 }
 
 impl Wallet {
-    pub fn new(elders: &Elders) -> Self {
+    pub fn new(
+        owner: Id,
+        elders: &Elders,
+        coin_selection: CoinSelection,
+        aggregation: Aggregation,
+    ) -> Self {
         Self {
+            owner,
             ledger: Ledger::new(elders),
+            dbcs: BTreeSet::from_iter([genesis_dbc()]),
+            unconfirmed: Default::default(),
+            receipts: Default::default(),
+            confirmed: Default::default(),
+            coin_selection,
+            aggregation,
+            fallback_txs: Default::default(),
+            reissue_retries: None,
+            submitted: Default::default(),
+            abandoned: Default::default(),
+            rejections: Default::default(),
+            rejected: Default::default(),
+            double_spend_proofs: false,
+            chained_spends: 0,
+            query_spent_inputs: false,
+            spent_answers: Default::default(),
+            spent_verdicts: Default::default(),
+            queried: Default::default(),
+            heard_digests: Default::default(),
+            batch_reissues: false,
+            batch: Default::default(),
+            supply: Supply::Fixed,
+        }
+    }
+
+    pub fn owned_dbcs(&self) -> &BTreeSet<Dbc> {
+        &self.dbcs
+    }
+
+    pub fn balance(&self) -> u64 {
+        self.dbcs.iter().map(Dbc::amount).sum()
+    }
+
+    // Number of unspent dbcs held by this wallet, used to compare coin selection strategies.
+    pub fn fragmentation(&self) -> usize {
+        self.dbcs.len()
+    }
+
+    pub fn may_spend(&self, dbc: &Dbc) -> bool {
+        dbc.owner().is_none_or(|owner| owner == self.owner)
+    }
+
+    // Picks owned dbcs covering `amount` with our coin selection strategy.
+    pub fn select_inputs(&self, amount: u64) -> Option<Vec<Dbc>> {
+        self.coin_selection.select(&self.dbcs, amount)
+    }
+
+    // Pays `amount` to `to` from the owned dbcs, keeping any change as a second output.
+    // Returns false if the wallet can't cover the amount.
+    // The `elders` passed to the wallet are the handover-signed elders, not the raw stable set ordering.
+    pub fn spend(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        to: Id,
+        amount: u64,
+        o: &mut Out<crate::Node>,
+    ) -> bool {
+        // Minted value goes to our change, burnt value has to be covered by the inputs too.
+        let (minted, burned) = self.supply.minted_and_burned();
+        let inputs = if let Some(inputs) = self.select_inputs(amount + burned) {
+            inputs
+        } else {
+            return false;
+        };
+
+        let change = inputs.iter().map(Dbc::amount).sum::<u64>() + minted - amount - burned;
+
+        let mut outputs = match self.ledger.denomination_base {
+            Some(base) => self.denominated_outputs(to, amount, base),
+            None => vec![Output::new(to, amount)],
+        };
+        match self.ledger.denomination_base {
+            Some(base) => outputs.extend(self.denominated_outputs(self.owner, change, base)),
+            None if change > 0 => outputs.push(Output::new(self.owner, change)),
+            None => (),
+        }
+
+        let tx = Tx {
+            inputs,
+            outputs,
+            supply: self.supply,
+            owner_sigs: Default::default(),
+        };
+        self.submit(membership, elders, tx, o);
+        true
+    }
+
+    // Owned outputs that are committed and still unspent.
+    pub fn combinable_outputs(&self) -> Vec<Dbc> {
+        Vec::from_iter(
+            self.dbcs
+                .iter()
+                .filter(|dbc| self.ledger.is_committed_output(dbc))
+                .filter(|dbc| self.ledger.is_spendable(&dbc.id()))
+                .cloned(),
+        )
+    }
+
+    // Reissues the combinable outputs into a single dbc, a Tx with more than one input. Returns
+    // false if there was nothing to combine.
+    pub fn combine_outputs(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        o: &mut Out<crate::Node>,
+    ) -> bool {
+        let inputs = self.combinable_outputs();
+        if inputs.len() < 2 {
+            return false;
+        }
+
+        let total = inputs.iter().map(Dbc::amount).sum();
+        let outputs = match self.ledger.denomination_base {
+            Some(base) => self.denominated_outputs(self.owner, total, base),
+            None => vec![Output::new(self.owner, total)],
+        };
+        // Denominated outputs that are already as few as they get can't be combined any further.
+        if outputs.len() >= inputs.len() {
+            return false;
+        }
+        self.reissue(membership, elders, inputs, outputs, o);
+        true
+    }
+
+    fn denominated_outputs(&self, to: Id, amount: u64, base: u64) -> Vec<Output> {
+        Vec::from_iter(
+            denominate(amount, base)
+                .into_iter()
+                .map(|denomination| Output::new(to, denomination)),
+        )
+    }
+
+    // An owned output of a Tx that committed, as opposed to the genesis dbc.
+    pub fn chainable_output(&self) -> Option<Dbc> {
+        self.combinable_outputs()
+            .into_iter()
+            .find(|dbc| dbc != &genesis_dbc())
+    }
+
+    // Spends the output whole back to ourselves, the elders may only witness it once they have
+    // committed the Tx it came out of.
+    pub fn spend_chained(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        dbc: Dbc,
+        o: &mut Out<crate::Node>,
+    ) {
+        self.chained_spends += 1;
+        let output = Output::new(self.owner, dbc.amount());
+        self.reissue(membership, elders, vec![dbc], vec![output], o);
+    }
+
+    pub fn read_tx(&self, dbc_id: &DbcId) -> Option<Tx> {
+        self.ledger.spentbook.spent_by(dbc_id).cloned()
+    }
+
+    pub fn query_spent(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        dbc_id: DbcId,
+        o: &mut Out<crate::Node>,
+    ) {
+        self.queried.insert(dbc_id.clone());
+        o.broadcast(elders, &build_msg(membership, Msg::QuerySpent(dbc_id)))
+    }
+
+    // The Tx that spent the dbc, along with the receipt shares we hold for it.
+    fn signed_entry(&self, dbc_id: &DbcId) -> Option<(Tx, SigSet<Tx>)> {
+        let tx = self.ledger.spentbook.spent_by(dbc_id)?.clone();
+        let receipt = self.receipts.get(&tx).cloned().unwrap_or_default();
+        Some((tx, receipt))
+    }
+
+    // Every committed Tx that wasn't pruned, along with the receipt shares we hold for it.
+    pub fn signed_entries(&self) -> BTreeSet<(Tx, SigSet<Tx>)> {
+        BTreeSet::from_iter(self.ledger.spentbook.txs().map(|tx| {
+            let receipt = self.receipts.get(tx).cloned().unwrap_or_default();
+            (tx.clone(), receipt)
+        }))
+    }
+
+    pub fn is_spent_query(
+        &self,
+        membership: &Membership,
+        elders: &Elders,
+        dbc_id: DbcId,
+        o: &mut Out<crate::Node>,
+    ) {
+        o.broadcast(elders, &build_msg(membership, Msg::IsSpentQuery(dbc_id)))
+    }
+
+    pub fn reissue(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        inputs: Vec<Dbc>,
+        outputs: Vec<Output>,
+        o: &mut Out<crate::Node>,
+    ) {
+        let tx = Tx {
+            inputs,
+            outputs,
+            supply: Supply::Fixed,
+            owner_sigs: Default::default(),
+        };
+        self.submit(membership, elders, tx, o);
+    }
+
+    fn submit(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        mut tx: Tx,
+        o: &mut Out<crate::Node>,
+    ) {
+        tx.blind();
+        tx.sign(self.owner);
+
+        for input in tx.inputs.iter() {
+            self.dbcs.remove(input);
+        }
+        let ours = Vec::from_iter(
+            tx.output_dbcs()
+                .into_iter()
+                .filter(|dbc| self.may_spend(dbc)),
+        );
+        self.unconfirmed.extend(ours);
+
+        if self.reissue_retries.is_some() {
+            self.submitted.insert(tx.clone(), 0);
+            o.set_timer(model_timeout());
+        }
+
+        if self.batch_reissues {
+            self.batch.insert(tx);
+        } else {
+            o.broadcast(elders, &build_msg(membership, Msg::ReqReissue(tx)))
+        }
+    }
+
+    // Sends the held back reissue requests, a lone Tx goes out as a plain request.
+    pub fn flush_batch(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        o: &mut Out<crate::Node>,
+    ) {
+        let mut batch = std::mem::take(&mut self.batch);
+        let msg = match batch.len() {
+            0 => return,
+            1 => Msg::ReqReissue(batch.pop_first().unwrap()),
+            _ => Msg::ReqReissueBatch(batch),
+        };
+        o.broadcast(elders, &build_msg(membership, msg));
+    }
+
+    pub fn on_msg(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        src: Id,
+        msg: Msg,
+        o: &mut Out<crate::Node>,
+    ) -> Result<(), Rejected> {
+        match msg {
+            Msg::ReqReissue(tx) if self.ledger.is_conflicting(&tx) => {
+                self.on_conflict(membership, elders, id, tx, src, o);
+            }
+            Msg::ReqReissue(tx) => {
+                if let Err(err) = self.ledger.validate_tx(&tx) {
+                    self.reject_reissue(membership, src, &tx, err, o);
+                    return Err(Rejected::InvalidTx(err));
+                }
+
+                if self.ledger.log_tx_share(id, tx.clone(), src) == Ok(true) {
+                    match (self.aggregation, coordinator(elders, &tx)) {
+                        (Aggregation::Coordinator, Some(coordinator)) if coordinator != id => {
+                            o.send(coordinator, build_msg(membership, Msg::ReqReissue(tx)));
+                            o.set_timer(model_timeout());
+                        }
+                        (Aggregation::Coordinator, Some(_)) => (),
+                        _ => o.broadcast(
+                            elders.iter().filter(|e| e != &&id),
+                            &build_msg(membership, Msg::ReqReissue(tx)),
+                        ),
+                    }
+                }
+            }
+            Msg::ReqReissueBatch(txs) => {
+                // Txs of the batch may not share inputs, with each other or with pending Txs.
+                let mut rejected = vec![];
+                let mut inputs = BTreeSet::new();
+                for tx in txs.iter() {
+                    let independent = tx.inputs.iter().all(|input| inputs.insert(input.clone()));
+                    match self.ledger.validate_tx(tx) {
+                        Err(err) => rejected.push((tx.clone(), err)),
+                        Ok(()) if !independent => {
+                            rejected.push((tx.clone(), TxValidationError::ConflictsWithPending))
+                        }
+                        Ok(()) => (),
+                    }
+                }
+
+                if let Some((_, err)) = rejected.first().cloned() {
+                    for (tx, err) in rejected {
+                        self.reject_reissue(membership, src, &tx, err, o);
+                    }
+                    return Err(Rejected::InvalidTx(err));
+                }
+
+                let mut first_seen = BTreeSet::new();
+                for tx in txs {
+                    if self.ledger.log_tx_share(id, tx.clone(), src) == Ok(true) {
+                        first_seen.insert(tx);
+                    }
+                }
+
+                let others = Vec::from_iter(elders.iter().filter(|e| e != &&id));
+                match first_seen.len() {
+                    0 => (),
+                    1 => o.broadcast(
+                        others,
+                        &build_msg(membership, Msg::ReqReissue(first_seen.pop_first().unwrap())),
+                    ),
+                    _ => o.broadcast(
+                        others,
+                        &build_msg(membership, Msg::ReqReissueBatch(first_seen)),
+                    ),
+                }
+            }
+            Msg::Committed(tx, witnesses) => {
+                if !tx.has_quorum(self.ledger.quorum, &witnesses, elders) {
+                    return Err(Rejected::NoQuorum);
+                }
+
+                for witness in witnesses {
+                    let _ = self.ledger.log_tx_share(id, tx.clone(), witness);
+                }
+            }
+            Msg::FallbackReissue(tx) if self.ledger.is_conflicting(&tx) => {
+                self.on_conflict(membership, elders, id, tx, src, o);
+            }
+            Msg::FallbackReissue(tx) => {
+                if let Err(err) = self.ledger.validate_tx(&tx) {
+                    return Err(Rejected::InvalidTx(err));
+                }
+
+                let _ = self.ledger.log_tx_share(id, tx.clone(), src);
+                self.fallback(membership, elders, id, tx, o);
+            }
+            Msg::AbortVote(tx) => {
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+
+                self.ledger
+                    .abort_votes
+                    .entry(tx.clone())
+                    .or_default()
+                    .insert(src);
+                if self.ledger.is_conflicting(&tx) {
+                    self.dispute(membership, elders, id, tx, None, o);
+                }
+            }
+            Msg::QuerySpent(dbc_id) => {
+                if !elders.contains(&id) {
+                    return Err(Rejected::NotAnElder);
+                }
+                // We can't hand out the Tx of a pruned spend, and it mustn't pass for unspent.
+                if self.ledger.spentbook.is_pruned(&dbc_id) {
+                    return Ok(());
+                }
+
+                let entry = self.signed_entry(&dbc_id);
+                o.send(src, build_msg(membership, Msg::SpentStatus(dbc_id, entry)));
+            }
+            Msg::SpentStatus(_, None) => (),
+            Msg::SpentStatus(dbc_id, Some((tx, receipt))) => {
+                // Only answers to what we asked the elders about count: a dbc we queried, or the
+                // input of a Tx we submitted.
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+                if !self.queried.contains(&dbc_id) && !self.submitted.contains_key(&tx) {
+                    return Err(Rejected::Unsolicited);
+                }
+                if self.ledger.spentbook.spent_by(&dbc_id) == Some(&tx) {
+                    return Ok(());
+                }
+
+                // We don't take the spentbook node's word for it, a quorum of the elders we know
+                // must have signed a receipt for the spend, and the Tx must check out here.
+                if !tx.inputs.iter().any(|input| input.id() == dbc_id) {
+                    return Err(Rejected::InvalidTx(TxValidationError::InvalidInput));
+                }
+                let signers = receipt.valid_signers(elders, &tx);
+                if !tx.has_quorum(self.ledger.quorum, &signers, elders) {
+                    return Err(Rejected::NoQuorum);
+                }
+                if let Err(err) = self.ledger.validate_tx(&tx) {
+                    return Err(Rejected::InvalidTx(err));
+                }
+
+                self.ledger.spentbook.record(&tx, signers);
+                self.receipts.entry(tx).or_default().merge(receipt);
+                self.queried.remove(&dbc_id);
+                self.dbcs.retain(|dbc| dbc.id() != dbc_id);
+            }
+            Msg::TxCommitted(tx, sigs) => {
+                let receipt = self.receipts.entry(tx.clone()).or_default();
+                receipt.merge(sigs);
+                // Other elders only keep the shares, to show for the spend later.
+                let spender = tx.owner_sigs.contains_key(&self.owner);
+                if spender && !self.confirmed.contains_key(&tx) && receipt.verify(elders, &tx) {
+                    if self.query_spent_inputs {
+                        for input in tx.inputs.iter() {
+                            self.is_spent_query(membership, elders, input.id(), o);
+                        }
+                    }
+                    let gen = membership.stable_set.gen();
+                    self.confirm(tx, elders, gen);
+                }
+            }
+            Msg::LedgerDigest(digest) => {
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+
+                let ours = self.ledger.spentbook.digest();
+                self.heard_digests.insert(src, (digest, ours));
+                if digest != ours {
+                    let entries = self.signed_entries();
+                    o.send(src, build_msg(membership, Msg::LedgerSync(entries)));
+                }
+            }
+            Msg::LedgerSync(entries) => {
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+
+                self.merge_ledger_sync(std::slice::from_ref(elders), entries);
+            }
+            Msg::Custody(custody) => {
+                self.take_custody(
+                    membership,
+                    std::slice::from_ref(elders),
+                    id,
+                    src,
+                    custody,
+                    o,
+                )?;
+            }
+            Msg::IsSpentQuery(dbc_id) => {
+                if !elders.contains(&id) {
+                    return Err(Rejected::NotAnElder);
+                }
+
+                let spent = self.ledger.spentbook.is_spent(&dbc_id);
+                let sig = Sig::sign(id, (dbc_id.clone(), spent));
+                o.send(
+                    src,
+                    build_msg(membership, Msg::IsSpentResponse(dbc_id, spent, sig)),
+                );
+            }
+            Msg::IsSpentResponse(dbc_id, spent, sig) => {
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+                let answer = (dbc_id, spent);
+                if !sig.verify(src, &answer) {
+                    return Err(Rejected::BadSignature);
+                }
+
+                let answers = self.spent_answers.entry(answer.clone()).or_default();
+                answers.add_share(src, sig);
+                if answers.verify(elders, &answer) {
+                    let (dbc_id, spent) = answer;
+                    let committed = self.confirmed.iter().any(|(tx, proof)| {
+                        &proof.signers == elders && tx.inputs.iter().any(|i| i.id() == dbc_id)
+                    });
+                    self.spent_verdicts
+                        .entry(dbc_id)
+                        .or_insert_with(|| (spent, elders.clone(), committed));
+                }
+            }
+            Msg::DoubleSpend(proof) => {
+                if !proof.verify(elders) {
+                    return Err(Rejected::BadSignature);
+                }
+
+                self.ledger.reject_double_spend(proof);
+            }
+            Msg::ReissueRejected(tx, err) => {
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+
+                if self.submitted.contains_key(&tx) {
+                    let rejections = self.rejections.entry(tx.clone()).or_default();
+                    rejections.insert(src, err);
+                    let rejected_by = rejections.keys().filter(|e| elders.contains(e)).count();
+                    if majority(rejected_by, elders.len()) {
+                        self.on_abort(&tx);
+                        self.rejected.insert(tx);
+                    }
+                }
+            }
+        }
+
+        for tx in self.ledger.process_aborts(elders, id) {
+            self.on_abort(&tx);
+            o.broadcast(
+                elders.iter().filter(|e| e != &&id),
+                &build_msg(membership, Msg::ReqReissue(tx)),
+            );
+        }
+
+        let committed = self.ledger.process_completed_commitments();
+        self.sign_receipts(membership, elders, id, &committed, o);
+        self.resolve_submitted();
+
+        if self.aggregation == Aggregation::Coordinator {
+            for (tx, witnesses) in committed {
+                if coordinator(elders, &tx) == Some(id) {
+                    o.broadcast(
+                        elders.iter().filter(|e| e != &&id),
+                        &build_msg(membership, Msg::Committed(tx, witnesses)),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Txs gossiped by a peer that witnessed them, lets elders that missed a request catch up.
+    pub fn merge_delta(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        src: Id,
+        txs: BTreeSet<Tx>,
+        o: &mut Out<crate::Node>,
+    ) {
+        for tx in txs {
+            let _ = self.ledger.log_tx_share(id, tx, src);
+        }
+
+        let committed = self.ledger.process_completed_commitments();
+        self.sign_receipts(membership, elders, id, &committed, o);
+        self.resolve_submitted();
+    }
+
+    // Commits the Txs another elder committed that we missed. Each must carry genuine receipt
+    // shares from a quorum of one of the elder sets the section went through, and is committed
+    // once the Txs its inputs came out of are. Spends the other elder pruned can't be caught up on.
+    // A Tx that is still short of a quorum is left out, though its shares are kept towards one: the
+    // other elder may have passed it on while the receipts were still coming in.
+    pub fn merge_ledger_sync(
+        &mut self,
+        elder_chain: &[Elders],
+        entries: BTreeSet<(Tx, SigSet<Tx>)>,
+    ) {
+        let quorum = self.ledger.quorum;
+        let mut missed = vec![];
+        for (tx, shares) in entries {
+            let receipt = self.receipts.entry(tx.clone()).or_default();
+            receipt.merge(shares);
+            let signers = elder_chain.iter().find_map(|elders| {
+                let signers = receipt.valid_signers(elders, &tx);
+                tx.has_quorum(quorum, &signers, elders).then_some(signers)
+            });
+            if let Some(signers) = signers {
+                if !self.ledger.is_committed(&tx) {
+                    missed.push((tx, signers));
+                }
+            }
+        }
+        loop {
+            let before = missed.len();
+            missed.retain(|(tx, signers)| !self.ledger.commit_synced(tx, signers.clone()));
+            if missed.len() == before {
+                break;
+            }
+        }
+
+        self.resolve_submitted();
+    }
+
+    // Stops tracking our Txs once they committed or were rejected, a Tx whose input went to a
+    // different Tx can't commit anymore and counts as rejected.
+    fn resolve_submitted(&mut self) {
+        let spentbook = &self.ledger.spentbook;
+        let mut rejected = vec![];
+        self.submitted.retain(|tx, _| {
+            let spent_by = Vec::from_iter(
+                tx.inputs
+                    .iter()
+                    .filter_map(|input| spentbook.spender(&input.id())),
+            );
+            if spent_by.iter().any(|other| *other != hash(tx)) {
+                rejected.push(tx.clone());
+            }
+            spent_by.len() < tx.inputs.len() && !self.rejected.contains(tx)
+        });
+        self.rejected.extend(rejected);
+    }
+
+    // Tells the requester of a Tx we won't witness where it stands, it's either committed already
+    // or rejected for the given reason.
+    fn reject_reissue(
+        &self,
+        membership: &Membership,
+        src: Id,
+        tx: &Tx,
+        err: TxValidationError,
+        o: &mut Out<crate::Node>,
+    ) {
+        let Some(input) = tx.inputs.first() else {
+            return;
+        };
+
+        let reply = match self.signed_entry(&input.id()) {
+            Some((spent_by, receipt)) if &spent_by == tx => {
+                Msg::SpentStatus(input.id(), Some((spent_by, receipt)))
+            }
+            _ => Msg::ReissueRejected(tx.clone(), err),
+        };
+        o.send(src, build_msg(membership, reply));
+    }
+
+    // Sends every Tx that hasn't committed yet to the elders we know now.
+    pub fn resubmit(&self, membership: &Membership, elders: &Elders, o: &mut Out<crate::Node>) {
+        for tx in self.submitted.keys() {
+            o.broadcast(elders, &build_msg(membership, Msg::ReqReissue(tx.clone())));
+        }
+    }
+
+    // Resubmits the Txs that haven't committed yet to the elders we know now, they may not be the
+    // ones we first sent them to. A Tx still unresolved a timeout after its last retry is given up.
+    fn retry_submitted(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        max_retries: usize,
+        o: &mut Out<crate::Node>,
+    ) {
+        self.resolve_submitted();
+
+        let mut abandoned = vec![];
+        self.submitted.retain(|tx, retries| {
+            if *retries >= max_retries {
+                abandoned.push(tx.clone());
+                return false;
+            }
+            *retries += 1;
+            o.broadcast(elders, &build_msg(membership, Msg::ReqReissue(tx.clone())));
+            true
+        });
+        self.abandoned.extend(abandoned);
+
+        if !self.submitted.is_empty() {
+            o.set_timer(model_timeout());
+        }
+    }
+
+    // Elders sign a receipt share for every Tx they see commit, keep it with the shares of the
+    // other elders and send it to them and to whoever signed the Tx.
+    fn sign_receipts(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        committed: &[(Tx, BTreeSet<Id>)],
+        o: &mut Out<crate::Node>,
+    ) {
+        if !elders.contains(&id) {
+            return;
+        }
+
+        for (tx, _) in committed {
+            let mut share = SigSet::new();
+            share.add_share(id, Sig::sign(id, tx.clone()));
+            self.receipts
+                .entry(tx.clone())
+                .or_default()
+                .merge(share.clone());
+
+            let receipt = build_msg(membership, Msg::TxCommitted(tx.clone(), share));
+            let recipients = BTreeSet::from_iter(
+                tx.owner_sigs
+                    .keys()
+                    .chain(elders.iter())
+                    .filter(|recipient| **recipient != id),
+            );
+            o.broadcast(recipients, &receipt);
+        }
+    }
+
+    // A majority of the elders vouched for the Tx, its outputs paid to us are ours to spend.
+    fn confirm(&mut self, tx: Tx, elders: &Elders, gen: u64) {
+        let sig = self.receipts[&tx].for_voters(elders.clone(), &tx);
+        let signers = sig.signers();
+        if tx
+            .inputs
+            .iter()
+            .all(|input| !self.ledger.spentbook.is_spent(&input.id()))
+        {
+            self.ledger.spentbook.record(&tx, signers);
+        }
+
+        for dbc in tx.output_dbcs() {
+            if dbc.owner() == Some(self.owner) {
+                self.unconfirmed.remove(&dbc);
+                if self.ledger.is_spendable(&dbc.id()) {
+                    self.dbcs.insert(dbc);
+                }
+            }
+        }
+
+        self.confirmed.insert(tx.clone(), Proof::new(tx, sig, gen));
+        self.resolve_submitted();
+    }
+
+    // Hands the pending Txs over to the elders that took over before they committed. New elders
+    // that haven't witnessed a Tx yet are sent the request along with our share.
+    pub fn hand_over_pending(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        o: &mut Out<crate::Node>,
+    ) {
+        for tx in self.ledger.hand_over_pending(elders, id) {
+            if !elders.contains(&id) {
+                continue;
+            }
+
+            let witnesses = &self.ledger.pending_commitments[&tx];
+            o.broadcast(
+                elders
+                    .iter()
+                    .filter(|e| e != &&id && !witnesses.contains(e)),
+                &build_msg(membership, Msg::ReqReissue(tx)),
+            );
+        }
+
+        let committed = self.ledger.process_completed_commitments();
+        self.sign_receipts(membership, elders, id, &committed, o);
+        self.resolve_submitted();
+    }
+
+    // Once handover commits, outgoing elders pass their ledger on to the incoming elders.
+    pub fn hand_over_custody(
+        &self,
+        membership: &Membership,
+        outgoing: &Elders,
+        incoming: &Elders,
+        id: Id,
+        o: &mut Out<crate::Node>,
+    ) {
+        if !outgoing.contains(&id) {
+            return;
+        }
+
+        o.broadcast(
+            incoming.iter().filter(|e| e != &&id),
+            &build_msg(membership, Msg::Custody(Custody::new(id, self))),
+        );
+    }
+
+    // Takes over the ledger of an elder of any generation, the last of `elder_sets` are the current
+    // elders. Committed Txs are checked like a ledger sync, pending ones are witnessed afresh as if
+    // their spenders had asked us.
+    pub fn take_custody(
+        &mut self,
+        membership: &Membership,
+        elder_sets: &[Elders],
+        id: Id,
+        src: Id,
+        custody: Custody,
+        o: &mut Out<crate::Node>,
+    ) -> Result<(), Rejected> {
+        if !elder_sets.iter().any(|elders| elders.contains(&src)) {
+            return Err(Rejected::NotAnElder);
+        }
+        if !custody.verify(src) {
+            return Err(Rejected::BadSignature);
+        }
+
+        self.merge_ledger_sync(elder_sets, custody.committed);
+
+        let elders = elder_sets.last().cloned().unwrap_or_default();
+        if elders.contains(&id) {
+            for tx in custody.pending {
+                if !self.ledger.is_committed(&tx) && !self.ledger.is_dead(&tx) {
+                    let _ = self.on_msg(membership, &elders, id, src, Msg::ReqReissue(tx), o);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The coordinator didn't deliver in time, revert to the symmetric scheme for pending Txs.
+    pub fn on_timeout(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        o: &mut Out<crate::Node>,
+    ) {
+        if self.aggregation == Aggregation::Coordinator {
+            let stuck = Vec::from_iter(self.ledger.pending_commitments.keys().cloned());
+            for tx in stuck {
+                self.fallback(membership, elders, id, tx, o);
+            }
+        }
+
+        if let Some(max_retries) = self.reissue_retries {
+            self.retry_submitted(membership, elders, max_retries, o);
+        }
+    }
+
+    fn on_conflict(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        tx: Tx,
+        src: Id,
+        o: &mut Out<crate::Node>,
+    ) {
+        if !self.double_spend_proofs {
+            self.dispute(membership, elders, id, tx, Some(src), o);
+            return;
+        }
+
+        if !elders.contains(&id) {
+            return;
+        }
+
+        for pending in self.ledger.conflicts_with(&tx) {
+            let Some(dbc_id) = tx
+                .inputs
+                .iter()
+                .map(Dbc::id)
+                .find(|dbc_id| pending.inputs.iter().any(|input| &input.id() == dbc_id))
+            else {
+                continue;
+            };
+
+            let proof = DoubleSpendProof::new(id, dbc_id, tx.clone(), pending);
+            self.ledger.reject_double_spend(proof.clone());
+            o.broadcast(
+                elders.iter().filter(|e| e != &&id),
+                &build_msg(membership, Msg::DoubleSpend(proof)),
+            );
+        }
+    }
+
+    // Two conflicting Txs can each hold enough witnesses to block the other forever. Elders that
+    // haven't witnessed the lex-greater one vote to abort it, freeing its inputs for the other.
+    fn dispute(
+        &mut self,
+        membership: &Membership,
+        elders: &Elders,
+        id: Id,
+        tx: Tx,
+        witness: Option<Id>,
+        o: &mut Out<crate::Node>,
+    ) {
+        let to_abort = Vec::from_iter(
+            self.ledger
+                .conflicts_with(&tx)
+                .into_iter()
+                .map(|pending| std::cmp::max(pending, tx.clone())),
+        );
+
+        if let Some(witness) = witness {
+            self.ledger
+                .conflicting
+                .entry(tx)
+                .or_default()
+                .insert(witness);
+        }
+
+        for tx in to_abort {
+            if self.ledger.vote_abort(id, &tx) {
+                o.broadcast(
+                    elders.iter().filter(|e| e != &&id),
+                    &build_msg(membership, Msg::AbortVote(tx)),
+                );
+            }
         }
     }
 
-    pub fn read_tx(&self, dbc_id: &DbcId) -> Option<Tx> {
-        self.ledger.commitments.get(dbc_id).cloned()
+    // Takes back the inputs of our own aborted Tx that are still spendable.
+    fn on_abort(&mut self, aborted: &Tx) {
+        let outputs = aborted.output_dbcs();
+        if !outputs
+            .iter()
+            .any(|dbc| self.dbcs.contains(dbc) || self.unconfirmed.contains(dbc))
+        {
+            return;
+        }
+
+        for dbc in outputs {
+            self.dbcs.remove(&dbc);
+            self.unconfirmed.remove(&dbc);
+        }
+        self.dbcs.extend(
+            aborted
+                .inputs
+                .iter()
+                .filter(|input| self.ledger.is_spendable(&input.id()))
+                .cloned(),
+        );
     }
 
-    pub fn reissue(
+    fn fallback(
         &mut self,
         membership: &Membership,
-        inputs: Vec<Dbc>,
-        outputs: Vec<u64>,
+        elders: &Elders,
+        id: Id,
+        tx: Tx,
         o: &mut Out<crate::Node>,
     ) {
-        let tx = Tx { inputs, outputs };
+        if self.fallback_txs.insert(tx.clone()) {
+            o.broadcast(
+                elders.iter().filter(|e| e != &&id),
+                &build_msg(membership, Msg::FallbackReissue(tx)),
+            );
+        }
+    }
+}
 
-        o.broadcast(
-            &membership.elders(),
-            &build_msg(membership, Msg::ReqReissue(tx)),
-        )
+// An outgoing elder's ledger, signed over its digest. Committed Txs come with their receipt shares
+// to be checked like a ledger sync. Only the pending Txs are passed on, the shares of outgoing
+// elders don't count towards the quorum of the incoming ones.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct Custody {
+    pub committed: BTreeSet<(Tx, SigSet<Tx>)>,
+    pub pending: BTreeSet<Tx>,
+    pub sig: Sig<u64>,
+}
+
+impl Custody {
+    pub fn new(id: Id, wallet: &Wallet) -> Self {
+        let committed = wallet.signed_entries();
+        let pending = BTreeSet::from_iter(wallet.ledger.pending_commitments.keys().cloned());
+        let sig = Sig::sign(id, hash(&(&committed, &pending)));
+        Self {
+            committed,
+            pending,
+            sig,
+        }
     }
 
-    pub fn on_msg(
-        &mut self,
-        membership: &Membership,
-        id: Id,
-        src: Id,
-        msg: Msg,
-        o: &mut Out<crate::Node>,
-    ) {
-        let elders = membership.elders();
+    pub fn verify(&self, src: Id) -> bool {
+        self.sig
+            .verify(src, &hash(&(&self.committed, &self.pending)))
+    }
+}
 
-        match msg {
-            Msg::ReqReissue(tx) => {
-                if self.ledger.log_tx_share(id, tx.clone(), src) {
-                    o.broadcast(
-                        elders.iter().filter(|e| e != &&id),
-                        &build_msg(membership, Msg::ReqReissue(tx)),
-                    )
-                }
-            }
+// Two different Txs spending the same dbc. Each carries its owners' signatures and the elder that
+// caught them signs the pair, so any elder can check the dbc was spent twice.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct DoubleSpendProof {
+    pub dbc_id: DbcId,
+    pub txs: (Tx, Tx),
+    pub reporter: Id,
+    pub sig: Sig<(Tx, Tx)>,
+}
+
+impl DoubleSpendProof {
+    pub fn new(reporter: Id, dbc_id: DbcId, a: Tx, b: Tx) -> Self {
+        let txs = (std::cmp::min(a.clone(), b.clone()), std::cmp::max(a, b));
+        Self {
+            dbc_id,
+            sig: Sig::sign(reporter, txs.clone()),
+            txs,
+            reporter,
         }
+    }
+
+    pub fn verify(&self, elders: &Elders) -> bool {
+        let (a, b) = &self.txs;
+        let spends_dbc = |tx: &Tx| tx.inputs.iter().any(|input| input.id() == self.dbc_id);
 
-        self.ledger.process_completed_commitments(membership)
+        a != b
+            && spends_dbc(a)
+            && spends_dbc(b)
+            && a.is_authorized()
+            && b.is_authorized()
+            && elders.contains(&self.reporter)
+            && self.sig.verify(self.reporter, &self.txs)
+    }
+
+    pub fn contains(&self, tx: &Tx) -> bool {
+        &self.txs.0 == tx || &self.txs.1 == tx
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 pub struct DbcId {
-    inputs: Vec<Dbc>,
+    // Hash of the ids of the inputs the Tx creating the dbc spent. Those hash their own inputs in
+    // turn, so the id commits to the dbc's whole ancestry while staying the same size however long
+    // the spend chain grows.
+    tx: u64,
     output_index: u64,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+impl DbcId {
+    fn new(inputs: &[Dbc], output_index: u64) -> Self {
+        Self {
+            tx: hash(&Vec::from_iter(inputs.iter().map(Dbc::id))),
+            output_index,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct Output {
+    // None only for the genesis dbc, which anyone may spend.
+    pub owner: Option<Id>,
+    pub commitment: Commitment,
+}
+
+impl Output {
+    // The output is unblinded until the Tx holding it is blinded.
+    pub fn new(owner: Id, amount: u64) -> Self {
+        Self {
+            owner: Some(owner),
+            commitment: Commitment::new(amount, 0),
+        }
+    }
+}
+
+// HACK: stands in for a Pedersen commitment to `amount` under `blinding`. A real commitment hides
+// both, here they are in the clear and the tag is what binds them, so a commitment whose parts were
+// tampered with no longer matches its tag.
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct Commitment {
+    pub amount: u64,
+    pub blinding: u64,
+    pub tag: u64,
+}
+
+impl Commitment {
+    pub fn new(amount: u64, blinding: u64) -> Self {
+        Self {
+            amount,
+            blinding,
+            tag: hash(&(amount, blinding)),
+        }
+    }
+
+    pub fn is_well_formed(&self) -> bool {
+        self.tag == hash(&(self.amount, self.blinding))
+    }
+
+    // Commitments add up homomorphically, amounts and blindings alike.
+    pub fn sum<'a>(commitments: impl IntoIterator<Item = &'a Commitment>) -> Commitment {
+        let (amount, blinding) = commitments
+            .into_iter()
+            .fold((0u64, 0u64), |(amount, blinding), c| {
+                (amount + c.amount, blinding.wrapping_add(c.blinding))
+            });
+        Commitment::new(amount, blinding)
+    }
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 pub struct Tx {
     pub inputs: Vec<Dbc>,
-    pub outputs: Vec<u64>,
+    pub outputs: Vec<Output>,
+    pub supply: Supply,
+    // Every owner of an input signs the Tx without these signatures.
+    pub owner_sigs: BTreeMap<Id, Sig<Tx>>,
 }
 
 impl Tx {
+    pub fn unsigned(&self) -> Tx {
+        Tx {
+            owner_sigs: Default::default(),
+            ..self.clone()
+        }
+    }
+
+    pub fn sign(&mut self, owner: Id) {
+        let sig = Sig::sign(owner, self.unsigned());
+        self.owner_sigs.insert(owner, sig);
+    }
+
+    // Each owned input carries a signature from its owner, unowned inputs need none.
+    pub fn is_authorized(&self) -> bool {
+        let unsigned = self.unsigned();
+        self.inputs
+            .iter()
+            .filter_map(Dbc::owner)
+            .all(|owner| match self.owner_sigs.get(&owner) {
+                Some(sig) => sig.verify(owner, &unsigned),
+                None => false,
+            })
+    }
+
+    // Picks the output blindings so they add up to the blindings of the inputs, the last output
+    // taking whatever is left over.
+    pub fn blind(&mut self) {
+        let mut remaining = Commitment::sum(self.inputs.iter().map(Dbc::commitment)).blinding;
+        let last = self.outputs.len().saturating_sub(1);
+        for (i, output) in self.outputs.iter_mut().enumerate() {
+            let blinding = if i == last {
+                remaining
+            } else {
+                hash(&(&self.inputs, i))
+            };
+            remaining = remaining.wrapping_sub(blinding);
+            output.commitment = Commitment::new(output.commitment.amount, blinding);
+        }
+    }
+
+    // Only the commitments are checked, every output must be well formed and the outputs must
+    // commit to the same total as the inputs, give or take the supply change. Minted and burnt
+    // amounts are public, they are committed to without a blinding.
     pub fn verify_sums(&self) -> bool {
-        self.inputs.iter().map(Dbc::amount).sum::<u64>() == self.outputs.iter().sum::<u64>()
+        let (minted, burned) = self.supply.minted_and_burned();
+        let minted = Commitment::new(minted, 0);
+        let burned = Commitment::new(burned, 0);
+        self.outputs
+            .iter()
+            .all(|output| output.commitment.is_well_formed())
+            && Commitment::sum(self.inputs.iter().map(Dbc::commitment).chain([&minted]))
+                == Commitment::sum(
+                    self.outputs
+                        .iter()
+                        .map(|output| &output.commitment)
+                        .chain([&burned]),
+                )
+    }
+
+    pub fn has_quorum(
+        &self,
+        quorum: QuorumPolicy,
+        witnesses: &BTreeSet<Id>,
+        elders: &Elders,
+    ) -> bool {
+        let count = witnesses.intersection(elders).count();
+        match self.supply {
+            Supply::Fixed => quorum.ledger.reached(count, elders.len()),
+            Supply::Mint(_) | Supply::Burn(_) => quorum.supply.reached(count, elders.len()),
+        }
     }
 
-    pub fn output_dbc_ids_and_amounts(&self) -> Vec<(DbcId, u64)> {
+    pub fn output_dbc_ids_and_commitments(&self) -> Vec<(DbcId, Commitment)> {
+        let first = DbcId::new(&self.inputs, 0);
         Vec::from_iter(
             self.outputs
                 .iter()
                 .enumerate()
-                .map(|(output_index, amount)| {
+                .map(|(output_index, output)| {
                     (
                         DbcId {
-                            inputs: self.inputs.clone(),
                             output_index: output_index as u64,
+                            ..first.clone()
                         },
-                        *amount,
+                        output.commitment.clone(),
                     )
                 }),
         )
@@ -111,7 +1388,9 @@ impl Tx {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
 pub struct Dbc {
     pub output_index: u64,
     pub tx: Tx,
@@ -119,14 +1398,19 @@ pub struct Dbc {
 
 impl Dbc {
     pub fn id(&self) -> DbcId {
-        DbcId {
-            inputs: self.tx.inputs.clone(),
-            output_index: self.output_index,
-        }
+        DbcId::new(&self.tx.inputs, self.output_index)
     }
 
     pub fn amount(&self) -> u64 {
-        self.tx.outputs[self.output_index as usize]
+        self.commitment().amount
+    }
+
+    pub fn commitment(&self) -> &Commitment {
+        &self.tx.outputs[self.output_index as usize].commitment
+    }
+
+    pub fn owner(&self) -> Option<Id> {
+        self.tx.outputs[self.output_index as usize].owner
     }
 
     pub fn verify(&self) -> bool {
@@ -134,91 +1418,425 @@ impl Dbc {
     }
 }
 
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Supply {
+    // The outputs add up to the inputs.
+    #[default]
+    Fixed,
+    // New value on top of the inputs, e.g. a farming reward.
+    Mint(u64),
+    // Value taken out of circulation, e.g. a fee.
+    Burn(u64),
+}
+
+impl Supply {
+    pub fn minted_and_burned(&self) -> (u64, u64) {
+        match self {
+            Supply::Fixed => (0, 0),
+            Supply::Mint(minted) => (*minted, 0),
+            Supply::Burn(burned) => (0, *burned),
+        }
+    }
+}
+
+pub fn is_denomination(amount: u64, base: u64) -> bool {
+    let mut denomination = 1;
+    while denomination < amount {
+        denomination *= base;
+    }
+    denomination == amount
+}
+
+// Splits the amount into the fewest powers of the base, one per unit of each digit of the amount
+// written in that base.
+pub fn denominate(mut amount: u64, base: u64) -> Vec<u64> {
+    let mut denominations = vec![];
+    let mut denomination = 1;
+    while amount > 0 {
+        let digit = amount % base;
+        denominations.extend(std::iter::repeat_n(denomination, digit as usize));
+        amount /= base;
+        denomination *= base;
+    }
+    denominations
+}
+
 pub fn genesis_dbc() -> Dbc {
     Dbc {
         output_index: 0,
         tx: Tx {
             inputs: vec![],
-            outputs: vec![100],
+            outputs: vec![Output {
+                owner: None,
+                commitment: Commitment::new(100, 0),
+            }],
+            supply: Supply::Fixed,
+            owner_sigs: Default::default(),
         },
     }
 }
 
+// Records which Tx spent each dbc, elders act as the spentbook nodes. Every entry keeps the elders
+// whose witnessing committed the spend as its proof.
+#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Spentbook {
+    spent: BTreeMap<DbcId, Tx>,
+    proofs: BTreeMap<DbcId, BTreeSet<Id>>,
+    // Spends of Txs whose outputs were all spent in turn, keyed by the hash of the dbc id. Only the
+    // hash of the Tx and the elders that committed it are kept, nobody can present its outputs as
+    // inputs again so the Tx itself is no longer needed.
+    pruned: BTreeMap<u64, (u64, BTreeSet<Id>)>,
+    // Txs are pruned as soon as all of their outputs are spent.
+    pub prune: bool,
+    // Running totals of the supply changes committed, pruning keeps them.
+    minted: u64,
+    burned: u64,
+}
+
+impl Spentbook {
+    pub fn is_spent(&self, dbc_id: &DbcId) -> bool {
+        self.spent.contains_key(dbc_id) || self.pruned.contains_key(&hash(dbc_id))
+    }
+
+    pub fn is_pruned(&self, dbc_id: &DbcId) -> bool {
+        self.pruned.contains_key(&hash(dbc_id))
+    }
+
+    // The hash of the Tx that spent the dbc, whether or not it was pruned since.
+    pub fn spender(&self, dbc_id: &DbcId) -> Option<u64> {
+        match self.spent.get(dbc_id) {
+            Some(tx) => Some(hash(tx)),
+            None => self.pruned.get(&hash(dbc_id)).map(|(tx_hash, _)| *tx_hash),
+        }
+    }
+
+    pub fn spend(&mut self, dbc_id: DbcId, tx: Tx, proof: BTreeSet<Id>) {
+        self.proofs.insert(dbc_id.clone(), proof);
+        self.spent.insert(dbc_id, tx);
+    }
+
+    // Spends every input of the Tx.
+    pub fn record(&mut self, tx: &Tx, proof: BTreeSet<Id>) {
+        if tx.inputs.iter().any(|input| !self.is_spent(&input.id())) {
+            let (minted, burned) = tx.supply.minted_and_burned();
+            self.minted += minted;
+            self.burned += burned;
+        }
+        for input in tx.inputs.iter() {
+            self.spend(input.id(), tx.clone(), proof.clone());
+        }
+
+        if self.prune {
+            self.prune_spent();
+        }
+    }
+
+    // Swaps the spends of every Tx whose outputs are all spent for their summary. Pruning doesn't
+    // change what is spent, so a single pass catches every such Tx.
+    fn prune_spent(&mut self) {
+        let fully_spent = BTreeSet::from_iter(
+            self.spent
+                .values()
+                .filter(|tx| {
+                    tx.output_dbc_ids_and_commitments()
+                        .iter()
+                        .all(|(dbc_id, _)| self.is_spent(dbc_id))
+                })
+                .cloned(),
+        );
+
+        for tx in fully_spent {
+            for input in tx.inputs.iter() {
+                let dbc_id = input.id();
+                self.spent.remove(&dbc_id);
+                let proof = self.proofs.remove(&dbc_id).unwrap_or_default();
+                self.pruned.insert(hash(&dbc_id), (hash(&tx), proof));
+            }
+        }
+    }
+
+    pub fn spent_by(&self, dbc_id: &DbcId) -> Option<&Tx> {
+        self.spent.get(dbc_id)
+    }
+
+    pub fn entry(&self, dbc_id: &DbcId) -> Option<(Tx, BTreeSet<Id>)> {
+        let tx = self.spent.get(dbc_id)?.clone();
+        let proof = self.proofs.get(dbc_id).cloned().unwrap_or_default();
+        Some((tx, proof))
+    }
+
+    pub fn txs(&self) -> impl Iterator<Item = &Tx> {
+        self.spent.values()
+    }
+
+    // Every committed Tx that wasn't pruned, along with the elders that committed it.
+    pub fn entries(&self) -> BTreeSet<(Tx, BTreeSet<Id>)> {
+        BTreeSet::from_iter(self.spent.iter().map(|(dbc_id, tx)| {
+            let proof = self.proofs.get(dbc_id).cloned().unwrap_or_default();
+            (tx.clone(), proof)
+        }))
+    }
+
+    // Counts the pruned Txs too.
+    pub fn tx_count(&self) -> usize {
+        let pruned = self.pruned.values().map(|(tx_hash, _)| *tx_hash);
+        BTreeSet::from_iter(self.spent.values().map(hash).chain(pruned)).len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.spent.len() + self.pruned.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spent.is_empty() && self.pruned.is_empty()
+    }
+
+    // Elders collect different witnesses for the same spend, so proofs are left out.
+    pub fn digest(&self) -> u64 {
+        let pruned = BTreeMap::from_iter(self.pruned.iter().map(|(k, (tx_hash, _))| (k, tx_hash)));
+        hash(&(&self.spent, pruned))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Ledger {
-    pub commitments: BTreeMap<DbcId, Tx>,
+    pub spentbook: Spentbook,
     pub pending_commitments: BTreeMap<Tx, BTreeSet<Id>>,
+    // Only dbcs under this prefix can be spent through this ledger.
+    pub prefix: Prefix,
+    // Txs we couldn't witness because they conflict with a pending one, and who witnessed them.
+    pub conflicting: BTreeMap<Tx, BTreeSet<Id>>,
+    // Elders that promised never to witness the Tx.
+    pub abort_votes: BTreeMap<Tx, BTreeSet<Id>>,
+    pub aborted: BTreeSet<Tx>,
+    // Both Txs of every double spend we know of are rejected.
+    pub double_spends: BTreeSet<DoubleSpendProof>,
+    // Set once we intend to leave, we keep learning commitments but no longer witness Txs.
+    pub read_only: bool,
+    // The elders pending commitments were last handed over to.
+    pub elders: Elders,
+    // The elders signed in by the latest SAP we hold, only their witnessing commits a Tx. The
+    // elders computed from our own stable set can be stale or mid-churn.
+    pub sap_elders: Elders,
+    // The share of the elders whose witnessing commits a Tx, for moving value around and for
+    // changing the supply.
+    pub quorum: QuorumPolicy,
+    // Outputs may only hold powers of this base. The amounts are public, so this is what dbcs
+    // with hidden amounts would have to give up for it.
+    pub denomination_base: Option<u64>,
+    // Pending Txs dropped on handover as they could never commit anymore.
+    pub dropped: BTreeSet<Tx>,
+    // A Byzantine elder witnesses every Tx that's valid on its own, conflicting ones included.
+    pub equivocate: bool,
 }
 
 impl Ledger {
     pub fn new(elders: &Elders) -> Self {
         Self {
-            commitments: Default::default(),
+            spentbook: Default::default(),
             pending_commitments: Default::default(),
+            prefix: Prefix::default(),
+            conflicting: Default::default(),
+            abort_votes: Default::default(),
+            aborted: Default::default(),
+            double_spends: Default::default(),
+            read_only: false,
+            elders: elders.clone(),
+            sap_elders: elders.clone(),
+            quorum: QuorumPolicy::default(),
+            dropped: Default::default(),
+            denomination_base: None,
+            equivocate: false,
         }
     }
 
-    pub fn sum_unspent_outputs(&self) -> u64 {
-        let mut sum = 0;
-        for (dbc_id, amount) in std::iter::once(&genesis_dbc().tx)
-            .chain(self.commitments.values())
-            .flat_map(|tx| tx.output_dbc_ids_and_amounts())
-        {
-            if !self.commitments.contains_key(&dbc_id) {
-                sum += amount
+    // Keeps the shard of the ledger that falls under our new prefix, pending spends of dbcs that now
+    // belong to the other section are dropped.
+    pub fn split(&mut self, prefix: Prefix) {
+        self.prefix = prefix;
+        self.pending_commitments.retain(|tx, _| {
+            tx.inputs
+                .iter()
+                .all(|input| prefix.matches(dbc_name(&input.id())))
+        });
+    }
+
+    pub fn is_spendable(&self, dbc_id: &DbcId) -> bool {
+        self.prefix.matches(dbc_name(dbc_id)) && !self.spentbook.is_spent(dbc_id)
+    }
+
+    // The Tx that created the dbc was committed, so the dbc exists.
+    pub fn is_committed_output(&self, dbc: &Dbc) -> bool {
+        dbc == &genesis_dbc()
+            || dbc
+                .tx
+                .inputs
+                .iter()
+                .all(|input| self.spentbook.spender(&input.id()) == Some(hash(&dbc.tx)))
+    }
+
+    pub fn witnessed_pending(&self, id: Id) -> BTreeSet<Tx> {
+        self.pending_commitments
+            .iter()
+            .filter(|(_, witnesses)| witnesses.contains(&id))
+            .map(|(tx, _)| tx.clone())
+            .collect()
+    }
+
+    // What the unspent outputs should add up to, the genesis amount with every committed supply
+    // change applied.
+    pub fn expected_supply(&self) -> Commitment {
+        let genesis = genesis_dbc().amount();
+        Commitment::new(genesis + self.spentbook.minted - self.spentbook.burned, 0)
+    }
+
+    pub fn sum_unspent_outputs(&self) -> Commitment {
+        Commitment::sum(self.unspent_outputs().values())
+    }
+
+    // The spentbook holds a Tx once per input it spent, keying the outputs by id counts each once.
+    fn unspent_outputs(&self) -> BTreeMap<DbcId, Commitment> {
+        BTreeMap::from_iter(
+            std::iter::once(&genesis_dbc().tx)
+                .chain(self.spentbook.txs())
+                .flat_map(|tx| tx.output_dbc_ids_and_commitments())
+                .filter(|(dbc_id, _)| !self.spentbook.is_spent(dbc_id)),
+        )
+    }
+
+    // The supply and what the unspent outputs add up to, as if our pending Txs all committed on top
+    // of the spentbook. They're taken in order and any spending an output an earlier one took is left
+    // out, of two conflicting Txs only one can ever commit.
+    pub fn supply_with_pending(&self) -> (Commitment, Commitment) {
+        let mut unspent = self.unspent_outputs();
+        let (mut minted, mut burned) = (self.spentbook.minted, self.spentbook.burned);
+
+        for tx in self.pending_commitments.keys() {
+            if !tx
+                .inputs
+                .iter()
+                .all(|input| unspent.contains_key(&input.id()))
+            {
+                continue;
             }
+            for input in tx.inputs.iter() {
+                unspent.remove(&input.id());
+            }
+            unspent.extend(tx.output_dbc_ids_and_commitments());
+
+            let (tx_minted, tx_burned) = tx.supply.minted_and_burned();
+            minted += tx_minted;
+            burned += tx_burned;
         }
 
-        sum
+        let supply = Commitment::new(genesis_dbc().amount() + minted - burned, 0);
+        (supply, Commitment::sum(unspent.values()))
+    }
+
+    pub fn validate_tx(&self, tx: &Tx) -> Result<(), TxValidationError> {
+        self.validate_inputs(tx)?;
+        if !self.equivocate && !self.conflicts_with(tx).is_empty() {
+            return Err(TxValidationError::ConflictsWithPending);
+        }
+        Ok(())
+    }
+
+    // Valid on its own, but shares an input with a different pending Tx.
+    pub fn is_conflicting(&self, tx: &Tx) -> bool {
+        !self.equivocate && self.validate_inputs(tx).is_ok() && !self.conflicts_with(tx).is_empty()
+    }
+
+    pub fn conflicts_with(&self, tx: &Tx) -> Vec<Tx> {
+        Vec::from_iter(
+            self.pending_commitments
+                .keys()
+                .filter(|pending_tx| *pending_tx != tx)
+                .filter(|pending_tx| {
+                    pending_tx
+                        .inputs
+                        .iter()
+                        .any(|pending_dbc| tx.inputs.contains(pending_dbc))
+                })
+                .cloned(),
+        )
     }
 
-    pub fn validate_tx(&self, tx: &Tx) -> bool {
+    fn validate_inputs(&self, tx: &Tx) -> Result<(), TxValidationError> {
         if !tx.verify_sums() {
-            return false;
+            return Err(TxValidationError::BadSums);
+        }
+        if !tx.is_authorized() {
+            return Err(TxValidationError::Unauthorized);
+        }
+        if self.aborted.contains(tx) {
+            return Err(TxValidationError::Aborted);
+        }
+        if self.is_double_spend(tx) {
+            return Err(TxValidationError::DoubleSpend);
+        }
+
+        if let Some(base) = self.denomination_base {
+            if !tx
+                .outputs
+                .iter()
+                .all(|output| is_denomination(output.commitment.amount, base))
+            {
+                return Err(TxValidationError::NotDenominated);
+            }
+        }
+
+        // Spending the same input twice would count its amount twice.
+        if BTreeSet::from_iter(tx.inputs.iter()).len() != tx.inputs.len() {
+            return Err(TxValidationError::DuplicateInput);
         }
 
         for input_dbc in tx.inputs.iter() {
             if !(input_dbc.verify() || input_dbc == &genesis_dbc()) {
-                return false;
+                return Err(TxValidationError::InvalidInput);
             }
 
-            // Check that the DBC's used to create this input were all committed to the dbc's TX
-            for input_dbc_parent in input_dbc.tx.inputs.iter() {
-                let parent_tx = if let Some(tx) = self.commitments.get(&input_dbc_parent.id()) {
-                    tx
-                } else {
-                    return false;
-                };
-                if parent_tx != &input_dbc.tx {
-                    return false;
-                }
-            }
+            // Hashing the id walks the input's ancestry, do it once.
+            let input_id = input_dbc.id();
 
-            // Check that this input DBC isn't already committed to a tx.
-            if self.commitments.contains_key(&input_dbc.id()) {
-                return false;
+            // Belongs to another section's shard.
+            if !self.prefix.matches(dbc_name(&input_id)) {
+                return Err(TxValidationError::WrongSection);
             }
 
-            // Check that this input DBC isn't already in a pending commitment
-            for pending_tx in self.pending_commitments.keys() {
-                let input_dbc_in_pending_tx = pending_tx
-                    .inputs
-                    .iter()
-                    .any(|pending_dbc| pending_dbc == input_dbc);
+            // Check that this input DBC isn't already committed to a tx. Checked ahead of the parent
+            // as the parent's spend may have been pruned since.
+            if self.spentbook.is_spent(&input_id) {
+                return Err(TxValidationError::AlreadySpent);
+            }
 
-                if input_dbc_in_pending_tx && pending_tx != tx {
-                    return false;
+            // Check that the DBC's used to create this input were all committed to the dbc's TX
+            for input_dbc_parent in input_dbc.tx.inputs.iter() {
+                if self.spentbook.spent_by(&input_dbc_parent.id()) != Some(&input_dbc.tx) {
+                    return Err(TxValidationError::UnknownParent);
                 }
             }
         }
 
-        true
+        Ok(())
     }
 
-    // Returns true if this is the first time we've seen this tx and it was valid, false otherwise
-    pub fn log_tx_share(&mut self, id: Id, tx: Tx, witness: Id) -> bool {
-        if !self.validate_tx(&tx) {
-            return false;
+    // Returns true if this is the first time we've seen this tx.
+    pub fn log_tx_share(&mut self, id: Id, tx: Tx, witness: Id) -> Result<bool, TxValidationError> {
+        self.validate_tx(&tx)?;
+        if self.has_voted_abort(id, &tx) {
+            return Err(TxValidationError::VotedAbort);
         }
 
         let first_time_seeing_tx = !self.pending_commitments.contains_key(&tx);
@@ -226,30 +1844,189 @@ impl Ledger {
         // If all input dbc's are valid, then we add the Tx to the pending commitments.
         let witnesses = self.pending_commitments.entry(tx).or_default();
         witnesses.insert(witness);
-        witnesses.insert(id);
+        if !self.read_only {
+            witnesses.insert(id);
+        }
 
-        first_time_seeing_tx
+        Ok(first_time_seeing_tx)
     }
 
-    pub fn process_completed_commitments(&mut self, membership: &Membership) {
-        let elders = membership.elders();
+    pub fn is_committed(&self, tx: &Tx) -> bool {
+        tx.inputs
+            .iter()
+            .all(|input| self.spentbook.spender(&input.id()) == Some(hash(tx)))
+    }
 
-        let ready_commitments = Vec::from_iter(
-            self.pending_commitments
+    // Commits a Tx another elder saw commit, as long as its inputs check out here. Returns false if
+    // they don't, the Txs they came out of may not have committed here yet.
+    pub fn commit_synced(&mut self, tx: &Tx, proof: BTreeSet<Id>) -> bool {
+        if self.validate_inputs(tx).is_err() {
+            return false;
+        }
+
+        self.pending_commitments.remove(tx);
+        self.conflicting.remove(tx);
+        self.spentbook.record(tx, proof);
+        true
+    }
+
+    // Aborted, caught double spending or beaten to one of its inputs, the Tx can't ever commit.
+    pub fn is_dead(&self, tx: &Tx) -> bool {
+        self.aborted.contains(tx)
+            || self.is_double_spend(tx)
+            || tx.inputs.iter().any(|input| {
+                self.spentbook
+                    .spender(&input.id())
+                    .map(|spender| spender != hash(tx))
+                    .unwrap_or(false)
+            })
+    }
+
+    // The elders changed while Txs were pending. Shares from witnesses that are no longer elders
+    // don't count towards the quorum of the new elders, so they are dropped and the Tx is witnessed
+    // afresh: a Tx commits under one set of elders or not at all. Txs that can't commit anymore are
+    // dropped whole. Returns the Txs left pending.
+    pub fn hand_over_pending(&mut self, elders: &Elders, id: Id) -> Vec<Tx> {
+        self.elders = elders.clone();
+
+        for (tx, mut witnesses) in std::mem::take(&mut self.pending_commitments) {
+            if self.is_dead(&tx) {
+                self.dropped.insert(tx);
+                continue;
+            }
+            witnesses.retain(|witness| elders.contains(witness));
+            self.pending_commitments.insert(tx, witnesses);
+        }
+
+        // As a new elder we witness what doesn't conflict, the same as on a fresh request.
+        let pending = Vec::from_iter(self.pending_commitments.keys().cloned());
+        if elders.contains(&id) && !self.read_only {
+            for tx in pending.iter() {
+                if self.validate_tx(tx).is_ok() && !self.has_voted_abort(id, tx) {
+                    self.pending_commitments
+                        .entry(tx.clone())
+                        .or_default()
+                        .insert(id);
+                }
+            }
+        }
+
+        pending
+    }
+
+    pub fn is_double_spend(&self, tx: &Tx) -> bool {
+        self.double_spends.iter().any(|proof| proof.contains(tx))
+    }
+
+    // Drops both Txs from the pending ones, neither will be witnessed again.
+    pub fn reject_double_spend(&mut self, proof: DoubleSpendProof) {
+        let (a, b) = &proof.txs;
+        for tx in [a, b] {
+            self.pending_commitments.remove(tx);
+            self.conflicting.remove(tx);
+        }
+        self.double_spends.insert(proof);
+    }
+
+    pub fn has_voted_abort(&self, id: Id, tx: &Tx) -> bool {
+        self.abort_votes
+            .get(tx)
+            .map(|voters| voters.contains(&id))
+            .unwrap_or(false)
+    }
+
+    // Only an elder that never witnessed the Tx may vote to abort it. Returns true on a new vote.
+    pub fn vote_abort(&mut self, id: Id, tx: &Tx) -> bool {
+        let witnessed = self
+            .pending_commitments
+            .get(tx)
+            .map(|witnesses| witnesses.contains(&id))
+            .unwrap_or(false);
+
+        !witnessed && self.abort_votes.entry(tx.clone()).or_default().insert(id)
+    }
+
+    // A Tx is aborted once the elders that promised not to witness it leave too few to commit it.
+    // Conflicting Txs it was blocking are witnessed in its place, these are returned so that our
+    // share can be sent out.
+    pub fn process_aborts(&mut self, elders: &Elders, id: Id) -> Vec<Tx> {
+        let to_abort = Vec::from_iter(
+            self.abort_votes
                 .iter()
-                .filter(|(_, witnesses)| {
-                    majority(witnesses.intersection(&elders).count(), elders.len())
+                .filter(|(tx, _)| !self.aborted.contains(*tx))
+                .filter(|(_, voters)| {
+                    let votes = voters.intersection(elders).count();
+                    !majority(elders.len() - votes, elders.len())
                 })
-                .map(|(tx, _)| tx)
-                .cloned(),
+                .map(|(tx, _)| tx.clone()),
         );
 
-        for tx in ready_commitments {
-            for input_dbc in tx.inputs.iter() {
-                self.commitments.insert(input_dbc.id(), tx.clone());
+        for tx in to_abort.iter() {
+            self.aborted.insert(tx.clone());
+            self.pending_commitments.remove(tx);
+            self.conflicting.remove(tx);
+        }
+
+        let mut unblocked = vec![];
+        for (tx, witnesses) in self.conflicting.clone() {
+            if self.validate_tx(&tx).is_ok() && !self.has_voted_abort(id, &tx) {
+                self.conflicting.remove(&tx);
+                let entry = self.pending_commitments.entry(tx.clone()).or_default();
+                entry.extend(witnesses);
+                if !self.read_only {
+                    entry.insert(id);
+                }
+                unblocked.push(tx);
             }
+        }
+
+        unblocked
+    }
+
+    // Returns the newly committed Txs along with the witnesses that made up their quorum.
+    pub fn process_completed_commitments(&mut self) -> Vec<(Tx, BTreeSet<Id>)> {
+        let ready_commitments = Vec::from_iter(
+            self.pending_commitments
+                .iter()
+                .filter(|(tx, witnesses)| tx.has_quorum(self.quorum, witnesses, &self.sap_elders))
+                .map(|(tx, witnesses)| (tx.clone(), witnesses.clone())),
+        );
 
+        // Txs sharing inputs can reach a quorum together through witnesses learned from peers, only
+        // the first one to claim all of its inputs commits, the others are dropped whole.
+        let mut committed = vec![];
+        for (tx, witnesses) in ready_commitments {
             self.pending_commitments.remove(&tx);
+            if tx
+                .inputs
+                .iter()
+                .any(|input_dbc| self.spentbook.is_spent(&input_dbc.id()))
+            {
+                continue;
+            }
+
+            let proof = BTreeSet::from_iter(witnesses.intersection(&self.sap_elders).copied());
+            self.spentbook.record(&tx, proof);
+            committed.push((tx, witnesses));
+        }
+
+        committed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Any amount the genesis could pay splits into denominations adding up to it.
+    #[test]
+    fn amounts_are_representable_in_denominations() {
+        for base in 2..=10 {
+            for amount in 0..=genesis_dbc().amount() {
+                let denominations = denominate(amount, base);
+                assert_eq!(denominations.iter().sum::<u64>(), amount);
+                assert!(denominations.iter().all(|d| is_denomination(*d, base)));
+            }
         }
     }
 }
@@ -0,0 +1,3270 @@
+pub mod adversary;
+pub mod bandwidth;
+pub mod codec;
+pub mod differential;
+pub mod dkg;
+pub mod event_log;
+pub mod fake_crypto;
+pub mod handover;
+pub mod health;
+#[cfg(test)]
+mod interleavings;
+pub mod ledger;
+pub mod membership;
+pub mod metrics;
+pub mod progress;
+pub mod property_cache;
+#[cfg(feature = "real-crypto")]
+pub mod real_crypto;
+pub mod section;
+pub mod simulation;
+pub mod soak;
+pub mod stable_set;
+pub mod trace;
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Debug,
+    sync::Arc,
+};
+
+use adversary::MutatingNetwork;
+use bandwidth::Traffic;
+use dkg::Dkg;
+use fake_crypto::{hash, majority, total_weight, Evidence, PublicKey, Quorum, QuorumPolicy, Sig};
+use handover::Handover;
+use health::{Health, HealthRecord};
+use ledger::{genesis_dbc, Aggregation, CoinSelection, Dbc, Spentbook, Supply, Tx, Wallet};
+use membership::{
+    Elders, Membership, MembershipDelta, MembershipEvent, Reason, SyncPayload, Weighting,
+};
+use metrics::Metrics;
+use property_cache::PropertyCache;
+use section::{id_name, Prefix};
+use simulation::LinkDropRates;
+use stable_set::{Member, StableSet};
+use stateright::{
+    actor::{model_timeout, Actor, ActorModel, ActorModelState, Id, LossyNetwork, Network, Out},
+    Expectation,
+};
+
+const ELDER_COUNT: usize = 4;
+
+// Keyed on each node's spentbook and pending Txs.
+static LEDGER_BALANCES: PropertyCache<(Spentbook, Vec<Tx>)> = PropertyCache::new();
+// Keyed on each node's elders and the Tx it holds spending the genesis dbc.
+static DOUBLE_SPENDS: PropertyCache<Vec<(Elders, Option<Tx>)>> = PropertyCache::new();
+
+pub fn build_msg(membership: &Membership, action: impl Into<Action>) -> Msg {
+    let mut sync = membership.sync_payload();
+
+    // Join signatures travel with the stable set, they are what lets peers verify the decision.
+
+    if let SyncPayload::StableSet(stable_set, _) = &mut sync {
+        for (_, witnesses) in stable_set.leaving_members.iter_mut() {
+            witnesses.clear()
+        }
+    }
+
+    Msg {
+        sync: Arc::new(sync),
+        action: Arc::new(action.into()),
+        hops: 0,
+    }
+}
+
+// Carries the whole stable set even in delta or hash-only sync mode, for peers that fell behind.
+pub fn build_full_msg(membership: &Membership, action: impl Into<Action>) -> Msg {
+    let mut full = membership.clone();
+    full.delta_sync = false;
+    full.hash_only_sync = false;
+    build_msg(&full, action)
+}
+
+// The membership, wallet and handover state are shared between the states the checker explores
+// until a handler changes them, cloning a state only copies what it touches.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct State {
+    pub membership: Arc<Membership>,
+    is_leaving: bool,
+    rejoined: bool,
+    pub wallet: Arc<Wallet>,
+    pub handover: Arc<Handover>,
+    pub dkg: Dkg,
+    pub health: Health,
+    pub elder_history: Vec<Elders>,
+    pub pending_sync: BTreeSet<Id>,
+    // Heartbeat rounds in a row each member has been silent for, as seen by an elder.
+    pub missed_heartbeats: BTreeMap<Id, usize>,
+    // Set when the last message delivered to this node was rejected by its handler.
+    pub last_rejection: Option<Rejection>,
+    // Messages after which we had witnessed something new despite having intended to leave.
+    pub witnessed_while_leaving: usize,
+    // Set once this node dropped its stable set for the snapshot it held.
+    pub restored_from_snapshot: bool,
+    // Timer ticks this node resent its stable set on.
+    pub resends: usize,
+    // Clients only hold dbcs, they follow the stable set to find the elders but never join.
+    pub is_client: bool,
+}
+
+impl State {
+    fn elders(&self) -> BTreeSet<Id> {
+        self.membership.elders()
+    }
+
+    // The elders the previous elders signed off on through handover, these hold the ledger quorum.
+    // Clients take no part in handover and go by the decided elders instead.
+    fn signed_elders(&self) -> Elders {
+        if self.is_client {
+            self.membership.elders()
+        } else {
+            self.handover.elders()
+        }
+    }
+
+    // Nodes that haven't set out to leave, clients are never part of the section.
+    fn in_section(&self) -> bool {
+        !self.is_leaving && !self.is_client
+    }
+
+    fn build_msg(&self, action: Action) -> Msg {
+        build_msg(&self.membership, action)
+    }
+
+    pub fn membership_mut(&mut self) -> &mut Membership {
+        Arc::make_mut(&mut self.membership)
+    }
+
+    pub fn wallet_mut(&mut self) -> &mut Wallet {
+        Arc::make_mut(&mut self.wallet)
+    }
+
+    pub fn handover_mut(&mut self) -> &mut Handover {
+        Arc::make_mut(&mut self.handover)
+    }
+
+    // Covers everything a message handler may touch.
+    pub fn fingerprint(&self) -> u64 {
+        hash(&(
+            &self.membership,
+            &self.wallet,
+            &self.handover,
+            &self.dkg,
+            &self.health,
+        ))
+    }
+
+    // Goes on as the half of our section the elders signed in once we learn of the split, the ledger
+    // keeps the dbcs that fall under it. Returns who to sync, the members of our half.
+    fn take_on_split(&mut self, id: Id, prefix: Prefix) -> BTreeSet<Id> {
+        let is_half = self
+            .membership
+            .prefix
+            .children()
+            .is_some_and(|halves| halves.contains(&prefix));
+        if !is_half {
+            return BTreeSet::new();
+        }
+
+        self.membership_mut().split(prefix);
+        self.wallet_mut().ledger.split(prefix);
+        let mut nodes_to_sync = BTreeSet::from_iter(self.membership.stable_set.ids());
+        // Dropping the other half frees space for queued joiners.
+        nodes_to_sync.extend(self.membership_mut().process_pending_actions(id));
+        nodes_to_sync
+    }
+
+    // Pending changes this node has put its name to, other than its own leave.
+    fn witnessing(&self, id: Id) -> (BTreeSet<Member>, BTreeSet<Member>, BTreeSet<Tx>) {
+        let stable_set = &self.membership.stable_set;
+        let joins = BTreeSet::from_iter(
+            stable_set
+                .joining_members
+                .iter()
+                .filter(|(_, sig)| sig.signers().contains(&id))
+                .map(|(m, _)| m.clone()),
+        );
+        let leaves = BTreeSet::from_iter(
+            stable_set
+                .leaving_members
+                .iter()
+                .filter(|(m, witnesses)| m.id != id && witnesses.contains(&id))
+                .map(|(m, _)| m.clone()),
+        );
+
+        (joins, leaves, self.wallet.ledger.witnessed_pending(id))
+    }
+
+    // In digest sync mode the stable set is left out and peers that disagree ask for it, Txs we
+    // witnessed still need gossiping so those go out as a regular sync.
+    fn sync_or_digest_msg(&self, id: Id, digest_sync: bool) -> Msg {
+        let since_gen = self.membership.stable_set.gen().saturating_sub(1);
+        if !digest_sync || !self.wallet.ledger.witnessed_pending(id).is_empty() {
+            return self.sync_msg(id, since_gen);
+        }
+
+        Msg {
+            sync: Arc::new(SyncPayload::StableSet(Box::default(), None)),
+            action: Arc::new(Action::Membership(membership::Msg::MembershipDigest(
+                self.membership.digest(),
+                self.membership.stable_set.gen(),
+            ))),
+            hops: 0,
+        }
+    }
+
+    fn sync_msg(&self, id: Id, since_gen: u64) -> Msg {
+        self.build_msg(Action::Sync {
+            gen: self.membership.stable_set.gen(),
+            membership_delta: self.membership.delta_since(since_gen),
+            ledger_delta: self.wallet.ledger.witnessed_pending(id),
+        })
+    }
+}
+
+// Why a handler refused a message, it must leave the node untouched when it does.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Rejected {
+    NotAnElder,
+    Blacklisted,
+    InvalidTx(ledger::TxValidationError),
+    NoQuorum,
+    BadSignature,
+    BadResourceProof,
+    // A SAP that doesn't extend our chain, or contradicts it.
+    UnlinkedSap,
+    NotACandidate,
+    IncompleteKeySet,
+    // We already signed different candidates for the generation.
+    ConflictingCandidates,
+    // A share signed with a key of a different generation than the one being collected.
+    WrongKeyGen,
+    // Evidence of misbehavior that doesn't hold a forged share of the accused.
+    BadEvidence,
+    // An answer to a question we never asked.
+    Unsolicited,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Rejection {
+    pub kind: &'static str,
+    pub reason: Rejected,
+    pub fingerprint_before: u64,
+    pub fingerprint_after: u64,
+}
+
+#[derive(Clone)]
+pub struct Node {
+    pub genesis_nodes: BTreeSet<Id>,
+    pub peers: Vec<Id>,
+    pub coin_selection: CoinSelection,
+    pub adversary: Option<MutatingNetwork>,
+    pub identity_keys: BTreeMap<Id, PublicKey>,
+    pub evicted_key: Option<PublicKey>,
+    // One-way link failures: messages from `.0` to `.1` are never delivered.
+    pub severed_links: BTreeSet<(Id, Id)>,
+    pub metrics: Option<Arc<Metrics>>,
+    pub sync_ttl: Option<usize>,
+    pub aggregation: Aggregation,
+    // Crashed nodes never send or handle any message.
+    pub crashed: BTreeSet<Id>,
+    // Batch syncs and send them when the timer fires rather than after every message.
+    pub deferred_sync: bool,
+    // Nodes that have left ask to join again, as a new member with a higher ord_idx.
+    pub rejoin_after_leave: bool,
+    // Nodes heartbeat the elders on every timer tick, elders propose removing members that missed
+    // this many heartbeats in a row.
+    pub heartbeat_misses: Option<usize>,
+    // Sections split in two once they grow past this many members.
+    pub split_threshold: Option<usize>,
+    // Elders co-sign a health statement every this many stable set generations.
+    pub health_period: Option<u64>,
+    // Elders queue join requests once the section holds this many members.
+    pub max_members: Option<usize>,
+    // Syncs send a digest of the membership, the full stable set only goes to peers that differ.
+    pub digest_sync: bool,
+    // Nodes stop witnessing joins, leaves and Txs as soon as they decide to leave.
+    pub read_only_on_leave: bool,
+    // Elders challenge every candidate, these candidates never answer.
+    pub resource_proof: bool,
+    pub lazy_candidates: BTreeSet<Id>,
+    // Pending joins and leaves are abandoned after this many timer ticks without completing.
+    pub pending_ttl: Option<u64>,
+    // Elders witness a single join or leave at a time rather than all of them concurrently.
+    pub serialize_churn: bool,
+    // Messages carry the latest decisions instead of the whole stable set.
+    pub delta_sync: bool,
+    // Elders sign a snapshot every this many generations, these nodes restart from the first one
+    // they hold at its generation.
+    pub snapshot_period: Option<u64>,
+    pub restore_from_snapshot: BTreeSet<Id>,
+    // Elders agree on every candidate's ord_idx rather than each picking the next one locally.
+    pub agreed_ord_idx: bool,
+    // Nodes reissue the committed outputs they own into a single dbc, spending several inputs in one
+    // Tx.
+    pub combine_outputs: bool,
+    // Spenders resubmit Txs that haven't committed when their timer fires, up to this many times.
+    pub reissue_retries: Option<usize>,
+    // Dbcs are spent by these client actors instead of the nodes.
+    pub clients: BTreeSet<Id>,
+    // Elders reject both Txs of a double spend rather than aborting one of them.
+    pub double_spend_proofs: bool,
+    // Pending Txs are handed over to the new elders when the elders change, rather than waiting on
+    // shares from elders that may be gone.
+    pub hand_over_pending: bool,
+    // Spentbook entries of Txs whose outputs were all spent are pruned down to a summary.
+    pub prune_spentbook: bool,
+    // Nodes spend an output of their own freshly committed Tx this many times over, each Tx in the
+    // chain spending the output of the one before.
+    pub spend_chain_depth: usize,
+    // Wallets ask the elders whether the inputs of their committed Txs are spent.
+    pub query_spent_inputs: bool,
+    // Outputs are restricted to powers of this base, at least 2, wallets split payments and change
+    // into them.
+    pub denomination_base: Option<u64>,
+    // Wallets send the Txs they reissue while handling a message as a single batch.
+    pub batch_reissues: bool,
+    // Elders periodically compare spentbook digests and send theirs to elders that differ, so an
+    // elder that missed some reissues, or was just promoted, catches up.
+    pub ledger_anti_entropy: bool,
+    // Every payment mints or burns this much on top, supply changes need more elders to commit.
+    pub supply_change: Supply,
+    // New elders run a DKG among themselves before the current elders sign them in.
+    pub dkg: bool,
+    // Holds back its DKG contribution until its timer fires.
+    pub slow_dkg_participant: Option<Id>,
+    // Outgoing elders send their ledger to the incoming elders once handover commits.
+    pub ledger_custody: bool,
+    // Nodes whose SAP chain lags behind the decided elders ask them for the SAPs they missed.
+    pub sap_catch_up: bool,
+    // The share of the elders it takes to decide joins, leaves, Tx commits and handover.
+    pub quorum: QuorumPolicy,
+    // Colluding nodes that try to hand the section over to themselves and a node that doesn't
+    // exist, forging the handover shares of the honest elders.
+    pub byzantine: BTreeSet<Id>,
+    // What each elder's share counts for when deciding membership changes.
+    pub weighting: Weighting,
+    // Elders vote to remove whoever sends them a forged share. Byzantine elders accuse an honest
+    // elder with evidence of their own making.
+    pub report_misbehavior: bool,
+    // Nodes resend what a lossy network may have dropped on each of this many timer ticks: their
+    // stable set to the section, and their join or leave request to the elders.
+    pub resend_rounds: Option<usize>,
+    // Byzantine nodes also send what each honest node could have sent, but never all of it: their
+    // elders witness conflicting Txs alike and vote to remove the honest elders.
+    pub equivocate: bool,
+    // Messages carry the stable set generation and a digest of the membership instead of the stable
+    // set, peers that see they're missing something ask for it with `ReqFullSync`.
+    pub hash_only_sync: bool,
+}
+
+#[derive(Clone, Eq, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Msg {
+    // Shared between the copies of a broadcast and the states holding them, the stable set and a
+    // ledger Tx's ancestry make up most of a message.
+    sync: Arc<SyncPayload>,
+    action: Arc<Action>,
+    // Number of sync re-broadcasts separating this message from the message that started it.
+    hops: usize,
+}
+
+impl Debug for Msg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &*self.sync {
+            SyncPayload::StableSet(stable_set, since) => {
+                write!(f, "Msg({stable_set:?}, {:?}", self.action)?;
+                if let Some(since) = since {
+                    write!(f, ", since:{since}")?;
+                }
+            }
+            SyncPayload::Digest(gen, digest) => {
+                write!(f, "Msg(gen:{gen}, digest:{digest:x}, {:?}", self.action)?
+            }
+        }
+
+        if self.hops > 0 {
+            write!(f, ", hops:{}", self.hops)?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+impl Msg {
+    // Every forged share the message carries, as evidence against whoever sent it.
+    fn forged_shares(&self, src: Id) -> Vec<Evidence> {
+        let mut evidence = vec![];
+        if let SyncPayload::StableSet(stable_set, _) = &*self.sync {
+            evidence.extend(
+                stable_set
+                    .joining_members
+                    .values()
+                    .flat_map(|sig| sig.forged_shares(src)),
+            );
+        }
+        match &*self.action {
+            Action::Handover(handover::Msg::Handover(sap)) => {
+                evidence.extend(sap.forged_shares(src))
+            }
+            Action::Handover(handover::Msg::HandoverShare(.., sig)) if !sig.is_genuine(src) => {
+                evidence.push(Evidence::new(src, src, sig))
+            }
+            Action::Wallet(ledger::Msg::TxCommitted(_, receipt)) => {
+                evidence.extend(receipt.forged_shares(src))
+            }
+            _ => (),
+        }
+        evidence
+    }
+}
+
+#[derive(Clone, Eq, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Action {
+    Membership(membership::Msg),
+    Wallet(ledger::Msg),
+    Handover(handover::Msg),
+    Dkg(dkg::Msg),
+    Health(health::Msg),
+    Sync {
+        gen: u64,
+        membership_delta: MembershipDelta,
+        ledger_delta: BTreeSet<Tx>,
+    },
+    // Ask a peer that is ahead of us for every membership change after the given generation.
+    ReqSync(u64),
+    // Ask a peer for its whole stable set, sent when a delta doesn't apply on top of ours.
+    ReqFullSync,
+    StartReissue,
+    TriggerLeave,
+    Heartbeat,
+}
+
+impl Action {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Membership(membership::Msg::ReqJoin(_)) => "req_join",
+            Self::Membership(membership::Msg::ReqLeave(_)) => "req_leave",
+            Self::Membership(membership::Msg::JoinShare(_)) => "join_share",
+            Self::Membership(membership::Msg::ProposeRemove(..)) => "propose_remove",
+            Self::Membership(membership::Msg::Misbehavior(..)) => "misbehavior",
+            Self::Membership(membership::Msg::Challenge(_)) => "challenge",
+            Self::Membership(membership::Msg::ChallengeResponse(_)) => "challenge_response",
+            Self::Membership(membership::Msg::MembershipDigest(..)) => "membership_digest",
+            Self::Wallet(ledger::Msg::ReqReissue(_)) => "req_reissue",
+            Self::Wallet(ledger::Msg::ReqReissueBatch(_)) => "req_reissue_batch",
+            Self::Wallet(ledger::Msg::Committed(..)) => "committed",
+            Self::Wallet(ledger::Msg::FallbackReissue(_)) => "fallback_reissue",
+            Self::Wallet(ledger::Msg::AbortVote(_)) => "abort_vote",
+            Self::Wallet(ledger::Msg::QuerySpent(_)) => "query_spent",
+            Self::Wallet(ledger::Msg::SpentStatus(..)) => "spent_status",
+            Self::Wallet(ledger::Msg::ReissueRejected(..)) => "reissue_rejected",
+            Self::Wallet(ledger::Msg::TxCommitted(..)) => "tx_committed",
+            Self::Wallet(ledger::Msg::DoubleSpend(_)) => "double_spend",
+            Self::Wallet(ledger::Msg::IsSpentQuery(_)) => "is_spent_query",
+            Self::Wallet(ledger::Msg::LedgerDigest(_)) => "ledger_digest",
+            Self::Wallet(ledger::Msg::LedgerSync(_)) => "ledger_sync",
+            Self::Wallet(ledger::Msg::Custody(_)) => "custody",
+            Self::Wallet(ledger::Msg::IsSpentResponse(..)) => "is_spent_response",
+            Self::Handover(handover::Msg::ReqHandoverShare(..)) => "req_handover_share",
+            Self::Handover(handover::Msg::HandoverShare(..)) => "handover_share",
+            Self::Handover(handover::Msg::Handover(_)) => "handover",
+            Self::Handover(handover::Msg::ReqSapsSince(_)) => "req_saps_since",
+            Self::Handover(handover::Msg::SapChainSegment(_)) => "sap_chain_segment",
+            Self::Handover(handover::Msg::VotingAt(..)) => "voting_at",
+            Self::Dkg(dkg::Msg::Part(..)) => "dkg_part",
+            Self::Dkg(dkg::Msg::Ack(..)) => "dkg_ack",
+            Self::Health(health::Msg::Share(..)) => "health_share",
+            Self::Health(health::Msg::Query(_)) => "health_query",
+            Self::Health(health::Msg::Statement(..)) => "health_statement",
+            Self::Sync { .. } => "sync",
+            Self::ReqSync(_) => "req_sync",
+            Self::ReqFullSync => "req_full_sync",
+            Self::StartReissue => "start_reissue",
+            Self::TriggerLeave => "trigger_leave",
+            Self::Heartbeat => "heartbeat",
+        }
+    }
+}
+
+impl Debug for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Membership(m) => write!(f, "{m:?}"),
+            Self::Wallet(m) => write!(f, "{m:?}"),
+            Self::Handover(m) => write!(f, "{m:?}"),
+            Self::Dkg(m) => write!(f, "{m:?}"),
+            Self::Health(m) => write!(f, "{m:?}"),
+            Self::Sync {
+                gen,
+                membership_delta,
+                ledger_delta,
+            } => {
+                write!(f, "Sync(gen:{gen}")?;
+                if !membership_delta.is_empty() {
+                    write!(f, ", {membership_delta:?}")?;
+                }
+                if !ledger_delta.is_empty() {
+                    write!(f, ", {ledger_delta:?}")?;
+                }
+                write!(f, ")")
+            }
+            Self::ReqSync(gen) => write!(f, "ReqSync({gen})"),
+            Self::ReqFullSync => write!(f, "ReqFullSync"),
+            Self::StartReissue => write!(f, "StartReissue"),
+            Self::TriggerLeave => write!(f, "TriggerLeave"),
+            Self::Heartbeat => write!(f, "Heartbeat"),
+        }
+    }
+}
+
+impl From<membership::Msg> for Action {
+    fn from(msg: membership::Msg) -> Self {
+        Self::Membership(msg)
+    }
+}
+
+impl From<ledger::Msg> for Action {
+    fn from(msg: ledger::Msg) -> Self {
+        Self::Wallet(msg)
+    }
+}
+
+impl From<handover::Msg> for Action {
+    fn from(msg: handover::Msg) -> Self {
+        Self::Handover(msg)
+    }
+}
+
+impl From<dkg::Msg> for Action {
+    fn from(msg: dkg::Msg) -> Self {
+        Self::Dkg(msg)
+    }
+}
+
+impl From<health::Msg> for Action {
+    fn from(msg: health::Msg) -> Self {
+        Self::Health(msg)
+    }
+}
+
+impl Actor for Node {
+    type Msg = Msg;
+    type State = State;
+
+    fn on_start(&self, id: Id, o: &mut Out<Self>) -> Self::State {
+        let mut membership = Membership::new(&self.genesis_nodes, self.identity_keys.clone());
+        membership.split_threshold = self.split_threshold;
+        membership.max_members = self.max_members;
+        membership.resource_proof = self.resource_proof;
+        membership.pending_ttl = self.pending_ttl;
+        membership.serialize_churn = self.serialize_churn;
+        membership.delta_sync = self.delta_sync;
+        membership.hash_only_sync = self.hash_only_sync;
+        membership.snapshot_period = self.snapshot_period;
+        membership.agreed_ord_idx = self.agreed_ord_idx;
+        membership.quorum = self.quorum;
+        membership.weighting = self.weighting.clone();
+        let mut wallet = Wallet::new(
+            id,
+            &self.genesis_nodes,
+            self.coin_selection,
+            self.aggregation,
+        );
+        wallet.reissue_retries = self.reissue_retries;
+        wallet.double_spend_proofs = self.double_spend_proofs;
+        wallet.ledger.spentbook.prune = self.prune_spentbook;
+        wallet.query_spent_inputs = self.query_spent_inputs;
+        wallet.ledger.denomination_base = self.denomination_base;
+        wallet.batch_reissues = self.batch_reissues;
+        wallet.supply = self.supply_change;
+        wallet.ledger.quorum = self.quorum;
+        wallet.ledger.equivocate = self.equivocate && self.byzantine.contains(&id);
+        let mut handover = Handover::new(self.genesis_nodes.clone());
+        handover.require_dkg = self.dkg;
+        handover.quorum = self.quorum.handover;
+        let dkg = Dkg::new(self.slow_dkg_participant == Some(id));
+
+        let is_client = self.clients.contains(&id);
+        if is_client {
+            membership.read_only = true;
+            // Clients always track their Txs so they can resubmit them when the elders change.
+            wallet.reissue_retries = Some(self.reissue_retries.unwrap_or(0));
+        }
+
+        let mut state = State {
+            elder_history: vec![membership.elders()],
+            membership: Arc::new(membership),
+            wallet: Arc::new(wallet),
+            handover: Arc::new(handover),
+            dkg,
+            health: Health::default(),
+            is_leaving: false,
+            rejoined: false,
+            pending_sync: BTreeSet::new(),
+            missed_heartbeats: BTreeMap::new(),
+            last_rejection: None,
+            witnessed_while_leaving: 0,
+            restored_from_snapshot: false,
+            resends: 0,
+            is_client,
+        };
+
+        if self.crashed.contains(&id) {
+            return state;
+        }
+
+        if is_client {
+            self.spend(id, &mut state, o);
+            let (membership, elders) = (state.membership.clone(), state.signed_elders());
+            state.wallet_mut().flush_batch(&membership, &elders, o);
+            return state;
+        }
+
+        if !self.genesis_nodes.contains(&id) {
+            o.broadcast(&self.genesis_nodes, &state.membership.req_join(id));
+        }
+
+        // One of the colluders is enough to spread the forged SAP.
+        if self.byzantine.first() == Some(&id) {
+            let fabricated = Id::from(self.peers.len() + self.clients.len());
+            let candidates =
+                BTreeSet::from_iter(self.byzantine.iter().copied().chain([fabricated]));
+            let sap = state.handover.forge_sap(&self.byzantine, candidates);
+            o.broadcast(
+                self.peers.iter().filter(|p| **p != id),
+                &state.build_msg(handover::Msg::Handover(sap).into()),
+            );
+        }
+
+        // Each colluding elder accuses the first honest elder of forging a share.
+        let colluding_elder = self.byzantine.contains(&id) && self.genesis_nodes.contains(&id);
+        if self.report_misbehavior && colluding_elder {
+            let victim = self
+                .genesis_nodes
+                .iter()
+                .find(|e| !self.byzantine.contains(e))
+                .and_then(|e| state.membership.stable_set.member_by_id(*e));
+            if let Some(victim) = victim {
+                let forged = Sig::forge(victim.id, 0, victim.clone());
+                let evidence = Evidence::new(victim.id, victim.id, &forged);
+                let report = state
+                    .membership_mut()
+                    .report_misbehavior(id, victim, evidence);
+                o.broadcast(self.genesis_nodes.iter().filter(|e| **e != id), &report);
+            }
+        }
+
+        if self.equivocate && colluding_elder {
+            let honest_elders = Vec::from_iter(
+                self.genesis_nodes
+                    .iter()
+                    .filter(|e| !self.byzantine.contains(e))
+                    .filter_map(|e| state.membership.stable_set.member_by_id(*e)),
+            );
+            for member in honest_elders {
+                let proposal =
+                    state
+                        .membership_mut()
+                        .propose_remove(id, member, Reason::Unresponsive);
+                o.broadcast(self.genesis_nodes.iter().filter(|e| **e != id), &proposal);
+            }
+        }
+
+        if self.heartbeat_misses.is_some()
+            || self.ledger_anti_entropy
+            || self.dkg
+            || self.sap_catch_up
+            || self.resend_rounds.is_some()
+        {
+            o.set_timer(model_timeout());
+        }
+
+        // Every node tries to spend the genesis, unless clients do the spending.
+        if self.clients.is_empty() {
+            o.send(id, state.build_msg(Action::StartReissue));
+        }
+
+        state
+    }
+
+    fn on_msg(
+        &self,
+        id: Id,
+        state: &mut Cow<Self::State>,
+        src: Id,
+        msg: Self::Msg,
+        o: &mut Out<Self>,
+    ) {
+        if self.severed_links.contains(&(src, id)) || self.crashed.contains(&id) {
+            return;
+        }
+
+        // Any message is a sign of life.
+        if state.missed_heartbeats.contains_key(&src) {
+            state.to_mut().missed_heartbeats.remove(&src);
+        }
+
+        let elders = state.elders();
+        let msg = match &self.adversary {
+            Some(adversary) => adversary.tamper(src, id, msg),
+            None => msg,
+        };
+
+        if state.is_client {
+            self.on_client_msg(id, state, src, msg, o);
+            return;
+        }
+        if self.report_misbehavior && elders.contains(&id) {
+            self.report_misbehavior(id, state, src, &msg, o);
+        }
+        let Msg { sync, action, hops } = msg;
+
+        let kind = action.kind();
+        let decided_before = self.metrics.as_ref().map(|metrics| {
+            metrics.record_msg(id, kind);
+            (
+                state.membership.stable_set.digest(),
+                state.wallet.ledger.spentbook.len(),
+            )
+        });
+
+        let witnessing_before =
+            (self.read_only_on_leave && state.is_leaving).then(|| state.witnessing(id));
+
+        let mut nodes_to_sync = match Arc::unwrap_or_clone(sync) {
+            // A delta only applies on top of every decision before it, otherwise ask for the lot.
+            SyncPayload::StableSet(_, Some(since)) if since > state.membership.stable_set.gen() => {
+                o.send(src, state.build_msg(Action::ReqFullSync));
+                BTreeSet::new()
+            }
+            SyncPayload::StableSet(stable_set, None) if stable_set.is_empty() => BTreeSet::new(),
+            SyncPayload::StableSet(stable_set, since) => {
+                state
+                    .to_mut()
+                    .membership_mut()
+                    .merge(*stable_set, since, id, src)
+            }
+            SyncPayload::Digest(gen, digest) => {
+                if state.membership.wants_full_sync(gen, digest) {
+                    o.send(src, state.build_msg(Action::ReqFullSync));
+                }
+                BTreeSet::new()
+            }
+        };
+
+        let fingerprint_before = state.fingerprint();
+        let handled = match Arc::unwrap_or_clone(action) {
+            Action::Sync {
+                gen,
+                membership_delta,
+                ledger_delta,
+            } => {
+                let own_gen = state.membership.stable_set.gen();
+                nodes_to_sync.extend(state.to_mut().membership_mut().apply_delta(
+                    id,
+                    src,
+                    membership_delta,
+                ));
+
+                let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+                state.to_mut().wallet_mut().merge_delta(
+                    &membership,
+                    &signed_elders,
+                    id,
+                    src,
+                    ledger_delta,
+                    o,
+                );
+
+                // We missed some generations, ask for them rather than relying on the blind merge.
+                if gen > own_gen + 1 {
+                    o.send(src, state.build_msg(Action::ReqSync(own_gen)));
+                }
+                Ok(())
+            }
+            Action::ReqSync(since_gen) => {
+                o.send(src, state.sync_msg(id, since_gen));
+                Ok(())
+            }
+            Action::ReqFullSync => {
+                let sync_action = Arc::unwrap_or_clone(
+                    state.sync_msg(id, state.membership.stable_set.gen()).action,
+                );
+                o.send(src, build_full_msg(&state.membership, sync_action));
+                Ok(())
+            }
+            Action::Membership(membership::Msg::Challenge(_))
+                if self.lazy_candidates.contains(&id) =>
+            {
+                Ok(())
+            }
+            Action::Wallet(ledger::Msg::LedgerSync(_)) if !state.signed_elders().contains(&src) => {
+                Err(Rejected::NotAnElder)
+            }
+            Action::Wallet(ledger::Msg::LedgerSync(entries)) => {
+                // Catching up can go back further than the current elders.
+                let elder_sets = Vec::from_iter(state.handover.elder_sets().cloned());
+                state
+                    .to_mut()
+                    .wallet_mut()
+                    .merge_ledger_sync(&elder_sets, entries);
+                Ok(())
+            }
+            Action::Wallet(ledger::Msg::Custody(custody)) => {
+                let membership = state.membership.clone();
+                let elder_sets = Vec::from_iter(state.handover.elder_sets().cloned());
+                state.to_mut().wallet_mut().take_custody(
+                    &membership,
+                    &elder_sets,
+                    id,
+                    src,
+                    custody,
+                    o,
+                )
+            }
+            Action::Membership(msg) => state
+                .to_mut()
+                .membership_mut()
+                .on_msg(&elders, id, src, msg, o)
+                .map(|additional_members_to_sync| nodes_to_sync.extend(additional_members_to_sync)),
+            Action::Wallet(msg) => {
+                let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+                state
+                    .to_mut()
+                    .wallet_mut()
+                    .on_msg(&membership, &signed_elders, id, src, msg, o)
+            }
+            Action::Handover(msg) => {
+                let membership = state.membership.clone();
+                let gen_before = state.handover.gen();
+                let handled = state
+                    .to_mut()
+                    .handover_mut()
+                    .on_msg(&membership, id, src, msg, o);
+                if state.handover.gen() > gen_before {
+                    state.to_mut().wallet_mut().ledger.sap_elders = state.handover.elders();
+                }
+                if self.ledger_custody && state.handover.gen() > gen_before {
+                    // A chain segment can take us over several generations, only the last counts.
+                    let elder_sets = Vec::from_iter(state.handover.elder_sets().cloned());
+                    let (outgoing, incoming) = (
+                        &elder_sets[elder_sets.len() - 2],
+                        &elder_sets[elder_sets.len() - 1],
+                    );
+                    state
+                        .wallet
+                        .hand_over_custody(&membership, outgoing, incoming, id, o);
+                }
+                if let Ok(Some(prefix)) = handled {
+                    nodes_to_sync.extend(state.to_mut().take_on_split(id, prefix));
+                }
+                handled.map(|_| ())
+            }
+            Action::Dkg(msg) => {
+                let membership = state.membership.clone();
+                state.to_mut().dkg.on_msg(&membership, id, src, msg, o)
+            }
+            Action::Health(msg) => {
+                let membership = state.membership.clone();
+                let elder_sets = Vec::from_iter(state.handover.elder_sets().cloned());
+                state
+                    .to_mut()
+                    .health
+                    .on_msg(&membership, &elder_sets, src, msg, o)
+            }
+            Action::StartReissue => {
+                self.spend(id, state.to_mut(), o);
+                Ok(())
+            }
+            Action::TriggerLeave => {
+                o.broadcast(&elders, &state.to_mut().membership_mut().req_leave(id));
+                Ok(())
+            }
+            Action::Heartbeat => Ok(()),
+        };
+
+        let rejection = handled.err().map(|reason| Rejection {
+            kind,
+            reason,
+            fingerprint_before,
+            fingerprint_after: state.fingerprint(),
+        });
+        if let (Some(metrics), Some(_)) = (&self.metrics, &rejection) {
+            metrics.record_rejection(id);
+        }
+        if state.last_rejection != rejection {
+            state.to_mut().last_rejection = rejection;
+        }
+
+        if self.combine_outputs && state.wallet.combinable_outputs().len() > 1 {
+            let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+            state
+                .to_mut()
+                .wallet_mut()
+                .combine_outputs(&membership, &signed_elders, o);
+        }
+
+        self.extend_spend_chain(state, o);
+        self.flush_reissue_batch(state, o);
+
+        if self.peer_index(id) > (self.peers.len() * 2) / 3
+            && state.membership.is_member(id)
+            && !state.is_leaving
+            && !state.rejoined
+        {
+            state.to_mut().is_leaving = true;
+            if self.read_only_on_leave {
+                state.to_mut().membership_mut().read_only = true;
+                state.to_mut().wallet_mut().ledger.read_only = true;
+            }
+            o.send(id, state.build_msg(Action::TriggerLeave));
+        }
+
+        if let Some(key) = self.evicted_key {
+            nodes_to_sync.extend(state.to_mut().membership_mut().blacklist(id, key));
+        }
+
+        nodes_to_sync.extend(state.to_mut().membership_mut().process_pending_actions(id));
+
+        let stable_set = &state.membership.stable_set;
+        let at_snapshot =
+            stable_set.snapshot.is_some() && stable_set.snapshot_gen() == stable_set.gen();
+        if self.restore_from_snapshot.contains(&id) && !state.restored_from_snapshot && at_snapshot
+        {
+            state.to_mut().membership_mut().restore_snapshot();
+            state.to_mut().restored_from_snapshot = true;
+        }
+
+        if let Some(period) = self.health_period {
+            let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+            state
+                .to_mut()
+                .health
+                .on_gen(&membership, &signed_elders, id, period, o);
+        }
+
+        nodes_to_sync.remove(&id);
+
+        // Keep ticking while anything is pending so stale entries get collected.
+        if self.pending_ttl.is_some() && state.membership.has_expiring() {
+            o.set_timer(model_timeout());
+        }
+
+        if let Some((joins, leaves, txs)) = witnessing_before {
+            let (joins_after, leaves_after, txs_after) = state.witnessing(id);
+            if !joins_after.is_subset(&joins)
+                || !leaves_after.is_subset(&leaves)
+                || !txs_after.is_subset(&txs)
+            {
+                state.to_mut().witnessed_while_leaving += 1;
+            }
+        }
+
+        let has_left = state.membership.stable_set.dead().any(|m| m.id == id)
+            && !state.membership.is_member(id);
+        if self.rejoin_after_leave && state.is_leaving && has_left {
+            state.to_mut().is_leaving = false;
+            state.to_mut().rejoined = true;
+            state.to_mut().membership_mut().read_only = false;
+            state.to_mut().wallet_mut().ledger.read_only = false;
+            o.broadcast(&state.elders(), &state.membership.req_join(id));
+        }
+
+        let decided_elders = state.elders();
+        if state.elder_history.last() != Some(&decided_elders) {
+            state.to_mut().elder_history.push(decided_elders.clone());
+        }
+
+        if self.sap_catch_up && state.handover.elders() != decided_elders {
+            o.set_timer(model_timeout());
+        }
+
+        // Elders decided by the stable set only take over once the current elders sign them in, the
+        // elders of our half once the section is due to split.
+        let signed_section = (state.handover.prefix(), state.signed_elders());
+        let next_section = state.membership.next_section(id);
+        if let Some((_, next_elders)) =
+            next_section.filter(|next| !state.is_client && next != &signed_section)
+        {
+            let membership = state.membership.clone();
+            if self.dkg {
+                state.to_mut().dkg.start(&membership, id, &next_elders, o);
+                if state.dkg.is_pending(id, &next_elders) {
+                    o.set_timer(model_timeout());
+                }
+            }
+            let key_set = state.dkg.key_set(&next_elders);
+            state
+                .to_mut()
+                .handover_mut()
+                .try_trigger_handover(id, &membership, key_set, o);
+        }
+
+        let signed_elders = state.signed_elders();
+        if self.hand_over_pending && state.wallet.ledger.elders != signed_elders {
+            let membership = state.membership.clone();
+            state
+                .to_mut()
+                .wallet_mut()
+                .hand_over_pending(&membership, &signed_elders, id, o);
+        }
+
+        if let (Some(metrics), Some(decided_before)) = (&self.metrics, decided_before) {
+            let decided_after = (
+                state.membership.stable_set.digest(),
+                state.wallet.ledger.spentbook.len(),
+            );
+            if decided_before != decided_after {
+                metrics.record_decision(id);
+            }
+            metrics.observe(id, state);
+        }
+
+        if self.sync_ttl.map(|ttl| hops >= ttl).unwrap_or(false) {
+            return;
+        }
+
+        if self.deferred_sync {
+            if !nodes_to_sync.is_subset(&state.pending_sync) {
+                if state.pending_sync.is_empty() {
+                    o.set_timer(model_timeout());
+                }
+                state.to_mut().pending_sync.extend(nodes_to_sync);
+            }
+            return;
+        }
+
+        let sync = Msg {
+            hops: hops + 1,
+            ..state.sync_or_digest_msg(id, self.digest_sync)
+        };
+        o.broadcast(&nodes_to_sync, &sync)
+    }
+
+    fn on_timeout(&self, id: Id, state: &mut Cow<Self::State>, o: &mut Out<Self>) {
+        if self.crashed.contains(&id) {
+            return;
+        }
+
+        if !state.pending_sync.is_empty() {
+            let nodes_to_sync = std::mem::take(&mut state.to_mut().pending_sync);
+            let sync = state.sync_or_digest_msg(id, self.digest_sync);
+            o.broadcast(&nodes_to_sync, &sync);
+        }
+
+        let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+        state
+            .to_mut()
+            .wallet_mut()
+            .on_timeout(&membership, &signed_elders, id, o);
+
+        if self.pending_ttl.is_some() && state.to_mut().membership_mut().expire_pending() {
+            o.set_timer(model_timeout());
+        }
+
+        if let Some(max_missed) = self.heartbeat_misses {
+            self.heartbeat(id, max_missed, state, o);
+            o.set_timer(model_timeout());
+        }
+
+        if self.dkg {
+            let membership = state.membership.clone();
+            state.to_mut().dkg.on_timeout(&membership, id, o);
+            // Sessions of candidates that lost out never complete, only the decided elders' one
+            // keeps the timer going.
+            if state.dkg.is_pending(id, &state.elders()) {
+                o.set_timer(model_timeout());
+            }
+        }
+
+        // Only a lagging node keeps asking, one that falls behind later is woken by the message
+        // that shows it.
+        if self.sap_catch_up && state.handover.elders() != state.elders() {
+            state.handover.catch_up(&state.membership, id, o);
+            o.set_timer(model_timeout());
+        }
+
+        if self.ledger_anti_entropy {
+            let elders = state.signed_elders();
+            if elders.contains(&id) {
+                let digest = state.wallet.ledger.spentbook.digest();
+                o.broadcast(
+                    elders.iter().filter(|e| e != &&id),
+                    &state.build_msg(Action::Wallet(ledger::Msg::LedgerDigest(digest))),
+                );
+            }
+            o.set_timer(model_timeout());
+        }
+
+        if let Some(rounds) = self.resend_rounds {
+            if state.resends < rounds && !state.is_client {
+                self.resend(id, state, o);
+                o.set_timer(model_timeout());
+            }
+        }
+    }
+}
+
+impl Node {
+    // Spenders pay themselves, so both the payment and the change are theirs to spend.
+    fn spend(&self, id: Id, state: &mut State, o: &mut Out<Self>) {
+        let amount = match self.clients.iter().position(|c| c == &id) {
+            Some(client_index) => client_index + 1,
+            None => self.peer_index(id),
+        };
+
+        let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+        state
+            .wallet_mut()
+            .spend(&membership, &signed_elders, id, amount as u64, o);
+    }
+
+    // Clients follow the stable set carried by every message to know the current elders, and
+    // resubmit their pending Txs whenever the elders change. Only wallet messages are handled.
+    fn on_client_msg(&self, id: Id, state: &mut Cow<State>, src: Id, msg: Msg, o: &mut Out<Self>) {
+        let Msg { sync, action, .. } = msg;
+
+        let elders_before = state.signed_elders();
+        match Arc::unwrap_or_clone(sync) {
+            SyncPayload::StableSet(_, Some(since)) if since > state.membership.stable_set.gen() => {
+                o.send(src, state.build_msg(Action::ReqFullSync));
+            }
+            SyncPayload::StableSet(stable_set, None) if stable_set.is_empty() => (),
+            SyncPayload::StableSet(stable_set, since) => {
+                let membership = &mut state.to_mut().membership_mut();
+                membership.merge(*stable_set, since, id, src);
+                membership.process_pending_actions(id);
+            }
+            SyncPayload::Digest(gen, digest) => {
+                if state.membership.wants_full_sync(gen, digest) {
+                    o.send(src, state.build_msg(Action::ReqFullSync));
+                }
+            }
+        }
+
+        let (membership, elders) = (state.membership.clone(), state.signed_elders());
+        if state.wallet.ledger.sap_elders != elders {
+            state.to_mut().wallet_mut().ledger.sap_elders = elders.clone();
+        }
+        match Arc::unwrap_or_clone(action) {
+            Action::Wallet(msg) => {
+                let kind = Action::Wallet(msg.clone()).kind();
+                let fingerprint_before = state.fingerprint();
+                let handled =
+                    state
+                        .to_mut()
+                        .wallet_mut()
+                        .on_msg(&membership, &elders, id, src, msg, o);
+                let rejection = handled.err().map(|reason| Rejection {
+                    kind,
+                    reason,
+                    fingerprint_before,
+                    fingerprint_after: state.fingerprint(),
+                });
+                if state.last_rejection != rejection {
+                    state.to_mut().last_rejection = rejection;
+                }
+            }
+            Action::StartReissue => self.spend(id, state.to_mut(), o),
+            _ => (),
+        }
+
+        if elders != elders_before && !state.wallet.submitted.is_empty() {
+            state.wallet.resubmit(&membership, &elders, o);
+        }
+
+        self.extend_spend_chain(state, o);
+        self.flush_reissue_batch(state, o);
+    }
+
+    // Spends every freshly committed output we hold, until the chain is as deep as configured.
+    fn extend_spend_chain(&self, state: &mut Cow<State>, o: &mut Out<Self>) {
+        while state.wallet.chained_spends < self.spend_chain_depth {
+            let Some(dbc) = state.wallet.chainable_output() else {
+                return;
+            };
+            let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+            state
+                .to_mut()
+                .wallet_mut()
+                .spend_chained(&membership, &signed_elders, dbc, o);
+        }
+    }
+
+    fn flush_reissue_batch(&self, state: &mut Cow<State>, o: &mut Out<Self>) {
+        if !state.wallet.batch.is_empty() {
+            let (membership, signed_elders) = (state.membership.clone(), state.signed_elders());
+            state
+                .to_mut()
+                .wallet_mut()
+                .flush_batch(&membership, &signed_elders, o);
+        }
+    }
+
+    fn heartbeat(&self, id: Id, max_missed: usize, state: &mut Cow<State>, o: &mut Out<Self>) {
+        let elders = state.elders();
+        let heartbeat = state.build_msg(Action::Heartbeat);
+        o.broadcast(elders.iter().filter(|e| e != &&id), &heartbeat);
+
+        if !elders.contains(&id) {
+            return;
+        }
+
+        let suspects = Vec::from_iter(
+            state
+                .membership
+                .members()
+                .iter()
+                .filter(|m| m.id != id && !state.membership.proposed_removals.contains(m))
+                .cloned(),
+        );
+
+        for member in suspects {
+            let missed = state
+                .to_mut()
+                .missed_heartbeats
+                .entry(member.id)
+                .or_default();
+            *missed += 1;
+
+            if *missed >= max_missed {
+                let msg = state.to_mut().membership_mut().propose_remove(
+                    id,
+                    member,
+                    Reason::Unresponsive,
+                );
+                o.broadcast(&elders, &msg);
+            }
+        }
+    }
+
+    // Votes to remove the sender of a forged share and shows the other elders the share, once per
+    // member.
+    fn report_misbehavior(
+        &self,
+        id: Id,
+        state: &mut Cow<State>,
+        src: Id,
+        msg: &Msg,
+        o: &mut Out<Self>,
+    ) {
+        let Some(evidence) = msg.forged_shares(src).into_iter().next() else {
+            return;
+        };
+        let Some(member) = state.membership.stable_set.member_by_id(src) else {
+            return;
+        };
+        if state.membership.proposed_removals.contains(&member) {
+            return;
+        }
+
+        let report = state
+            .to_mut()
+            .membership_mut()
+            .report_misbehavior(id, member, evidence);
+        o.broadcast(state.elders().iter().filter(|e| **e != id), &report);
+    }
+
+    // Makes up for messages a lossy network dropped, the whole stable set goes out so peers that
+    // missed any number of decisions catch up from a single resend.
+    fn resend(&self, id: Id, state: &mut Cow<State>, o: &mut Out<Self>) {
+        state.to_mut().resends += 1;
+
+        let elders = state.elders();
+        let is_member = state.membership.is_member(id);
+        if !is_member && !state.is_leaving {
+            o.broadcast(&elders, &state.membership.req_join(id));
+        }
+        if is_member && state.is_leaving {
+            o.broadcast(&elders, &state.to_mut().membership_mut().req_leave(id));
+        }
+
+        let sync_action =
+            Arc::unwrap_or_clone(state.sync_msg(id, state.membership.stable_set.gen()).action);
+        let sync = build_full_msg(&state.membership, sync_action);
+        let peers = BTreeSet::from_iter(state.membership.stable_set.ids().filter(|p| *p != id));
+        o.broadcast(&peers, &sync);
+    }
+
+    fn peer_index(&self, id: Id) -> usize {
+        self.peers.iter().position(|p| p == &id).unwrap()
+    }
+}
+
+#[derive(Clone)]
+pub struct ModelCfg {
+    pub elder_count: usize,
+    pub server_count: usize,
+    pub coin_selection: CoinSelection,
+    pub max_unspent_dbcs: usize,
+    pub adversary: Option<MutatingNetwork>,
+    pub identity_keys: BTreeMap<Id, PublicKey>,
+    pub evicted_key: Option<PublicKey>,
+    pub link_drop_rates: Option<LinkDropRates>,
+    pub severed_links: BTreeSet<(Id, Id)>,
+    pub sync_ttl: Option<usize>,
+    pub max_sync_hops: usize,
+    pub record_traffic: bool,
+    pub record_msg_log: bool,
+    pub max_bytes_per_change: usize,
+    pub aggregation: Aggregation,
+    pub crashed: BTreeSet<Id>,
+    pub deferred_sync: bool,
+    pub max_syncs_per_change: usize,
+    pub rejoin_after_leave: bool,
+    pub heartbeat_misses: Option<usize>,
+    pub split_threshold: Option<usize>,
+    pub health_period: Option<u64>,
+    pub max_members: Option<usize>,
+    pub resource_proof: bool,
+    pub lazy_candidates: BTreeSet<Id>,
+    pub read_only_on_leave: bool,
+    pub digest_sync: bool,
+    pub pending_ttl: Option<u64>,
+    pub serialize_churn: bool,
+    pub delta_sync: bool,
+    pub snapshot_period: Option<u64>,
+    pub restore_from_snapshot: BTreeSet<Id>,
+    pub agreed_ord_idx: bool,
+    pub combine_outputs: bool,
+    pub reissue_retries: Option<usize>,
+    // Number of client actors, added after the servers.
+    pub client_count: usize,
+    pub double_spend_proofs: bool,
+    pub hand_over_pending: bool,
+    pub prune_spentbook: bool,
+    pub spend_chain_depth: usize,
+    pub query_spent_inputs: bool,
+    pub denomination_base: Option<u64>,
+    pub batch_reissues: bool,
+    pub ledger_anti_entropy: bool,
+    pub supply_change: Supply,
+    pub dkg: bool,
+    pub slow_dkg_participant: Option<Id>,
+    pub ledger_custody: bool,
+    pub sap_catch_up: bool,
+    pub quorum: QuorumPolicy,
+    pub byzantine: BTreeSet<Id>,
+    pub weighting: Weighting,
+    pub report_misbehavior: bool,
+    pub resend_rounds: Option<usize>,
+    pub equivocate: bool,
+    pub hash_only_sync: bool,
+    // States with more decided joins, leaves or committed reissues than these are left unexplored.
+    pub max_joins: Option<usize>,
+    pub max_leaves: Option<usize>,
+    pub max_reissues: Option<usize>,
+    pub network: Network<<Node as Actor>::Msg>,
+}
+
+fn reference_stable_set(state: &ActorModelState<Node, Traffic>) -> StableSet {
+    state
+        .actor_states
+        .iter()
+        .find(|s| s.in_section())
+        .map(|s| s.membership.stable_set.clone())
+        .unwrap_or_default()
+}
+
+// Fair links deliver a message that is resent often enough. The bounded stand-in: a path stays fair
+// while the network dropped no more messages than each node resends, membership liveness is only
+// claimed on fair paths. Without resends that leaves the paths where nothing was dropped.
+fn fair_links(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    cfg.link_drop_rates.is_none()
+        || state.history.dropped(state.network.len()) <= cfg.resend_rounds.unwrap_or(0)
+}
+
+// Nodes that joined late never saw who left before them, so only the members are compared.
+fn prop_stable_set_converged(state: &ActorModelState<Node, Traffic>) -> bool {
+    let reference_members = reference_stable_set(state).members_digest();
+
+    state
+        .actor_states
+        .iter()
+        .filter(|s| s.in_section())
+        .all(|actor| actor.membership.stable_set.members_digest() == reference_members)
+}
+
+// Nothing is in flight and no timer is left to fire, the run can't go any further.
+fn is_terminal(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.network.len() == 0 && state.is_timer_set.iter().all(|set| !set)
+}
+
+// A later joiner decides everything before it joined in one go, only the changes it logged first
+// hand are compared. Wherever two nodes logged the same generation they must have decided the same
+// changes, concurrent changes decided in a different order show up here. Serializing churn should
+// rule that out.
+fn prop_event_logs_are_prefixes(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    if !cfg.serialize_churn || !prop_stable_set_converged(state) {
+        return true;
+    }
+
+    let logs = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .filter(|s| s.in_section())
+            .filter_map(|s| {
+                let first_hand_gen = s.membership.first_hand_gen?;
+                let mut by_gen: BTreeMap<u64, Vec<&MembershipEvent>> = BTreeMap::new();
+                for event in s.membership.events.iter() {
+                    if event.gen() > first_hand_gen {
+                        by_gen.entry(event.gen()).or_default().push(event);
+                    }
+                }
+                Some(by_gen)
+            }),
+    );
+
+    logs.iter().all(|a| {
+        logs.iter().all(|b| {
+            a.iter().all(|(gen, x)| {
+                b.get(gen).is_none_or(|y| {
+                    x.len() == y.len() && x.iter().zip(y).all(|(x, y)| x.same_decision(y))
+                })
+            })
+        })
+    })
+}
+
+// Elders are cached between decisions, they must never name someone that isn't a live member.
+fn prop_elders_are_members(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|s| {
+        let stable_set = &s.membership.stable_set;
+        s.membership.elders().iter().all(|e| {
+            stable_set
+                .member_by_id(*e)
+                .is_some_and(|m| !stable_set.is_dead(&m))
+        })
+    })
+}
+
+// `process_ready_actions` replaces a member when a higher proposal for its id is decided, this
+// checks nothing slips past that, wherever the members came from.
+fn prop_ids_are_unique_members(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|actor| {
+        let members = actor.membership.members();
+        BTreeSet::from_iter(members.iter().map(|m| m.id)).len() == members.len()
+    })
+}
+
+// No two members with different ids share an ord_idx. Genesis members all sit at ord_idx 0, every
+// member voted in after them has an ord_idx of its own. Elders that each pick the next ord_idx
+// locally can hand the same one to concurrent joiners, this is what catches it.
+fn prop_ord_idx_unique(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|actor| {
+        let mut ids_by_ord_idx: BTreeMap<u64, Id> = BTreeMap::new();
+        actor
+            .membership
+            .members()
+            .iter()
+            .filter(|m| m.ord_idx > 0)
+            .all(|m| *ids_by_ord_idx.entry(m.ord_idx).or_insert(m.id) == m.id)
+    })
+}
+
+fn prop_converged_nodes_agree_on_gen(state: &ActorModelState<Node, Traffic>) -> bool {
+    let reference = reference_stable_set(state);
+    let reference = (reference.gen(), reference.members_digest());
+
+    state
+        .actor_states
+        .iter()
+        .filter(|s| s.in_section())
+        .all(|actor| {
+            let stable_set = &actor.membership.stable_set;
+            (stable_set.gen(), stable_set.members_digest()) == reference
+        })
+}
+
+// A member a node once decided had joined only drops out of its stable set, while still in the
+// reference one, if it asked to leave or some node decided its leave on a quorum of witnesses. A merge
+// silently losing members breaks this.
+fn prop_no_unwitnessed_removals(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    let reference = reference_stable_set(state);
+    let left_on_quorum = |member: &Member| {
+        state.actor_states.iter().any(|a| {
+            let weights = a.membership.elder_weights();
+            a.membership.events.iter().any(|event| match event {
+                MembershipEvent::Left {
+                    member: m,
+                    witnesses,
+                    ..
+                } => {
+                    m == member
+                        && a.elder_history.iter().any(|elders| {
+                            cfg.quorum.leaves.reached(
+                                total_weight(witnesses.intersection(elders), &weights),
+                                total_weight(elders, &weights),
+                            )
+                        })
+                }
+                MembershipEvent::Joined { .. } => false,
+            })
+        })
+    };
+    let left_voluntarily = |member: &Member| {
+        state
+            .actor_states
+            .get(usize::from(member.id))
+            .is_some_and(|departed| departed.is_leaving || departed.rejoined)
+    };
+
+    state
+        .actor_states
+        .iter()
+        .filter(|a| a.in_section())
+        .all(|a| {
+            let stable_set = &a.membership.stable_set;
+            let once_joined =
+                BTreeSet::from_iter(a.membership.events.iter().filter_map(|event| match event {
+                    MembershipEvent::Joined { member, .. } => Some(member),
+                    MembershipEvent::Left { .. } => None,
+                }));
+
+            reference
+                .members()
+                .iter()
+                .filter(|m| once_joined.contains(m) && !stable_set.is_member(m))
+                .all(|m| left_voluntarily(m) || left_on_quorum(m))
+        })
+}
+
+// The ledger quorum relies on every node working out the same elders, which converging on the
+// members only gives as long as every node picks the elders from them the same way.
+fn prop_converged_nodes_agree_on_elders(state: &ActorModelState<Node, Traffic>) -> bool {
+    let mut elders = state
+        .actor_states
+        .iter()
+        .filter(|s| s.in_section())
+        .map(|s| s.elders());
+    let reference = elders.next();
+
+    prop_stable_set_converged(state) && elders.all(|e| Some(e) == reference)
+}
+
+fn prop_all_nodes_joined_who_havent_left(
+    lazy_candidates: &BTreeSet<Id>,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let reference_stable_set = reference_stable_set(state);
+    state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(_, actor)| actor.in_section())
+        // Candidates that never prove their resources are never let in.
+        .filter(|(id, _)| !lazy_candidates.contains(&Id::from(*id)))
+        .all(|(id, _)| reference_stable_set.contains(id.into()))
+}
+
+fn prop_all_nodes_who_are_leaving_eventually_left(state: &ActorModelState<Node, Traffic>) -> bool {
+    let reference_stable_set = reference_stable_set(state);
+
+    state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(_, actor)| actor.is_leaving)
+        .all(|(id, _)| !reference_stable_set.contains(id.into()))
+}
+
+// Elders that know themselves to be elders hold the same spentbook once anti-entropy has nothing
+// left to do: nothing is in flight and each of them heard every other's current digest since its
+// own spentbook last changed. Whichever held a Tx the other lacked then has answered with it.
+fn prop_elder_ledgers_converge(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    let elders = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(i, a)| a.in_section() && a.signed_elders().contains(&Id::from(*i)))
+            .map(|(i, a)| (Id::from(i), &a.wallet)),
+    );
+    let digest = |wallet: &ledger::Wallet| wallet.ledger.spentbook.digest();
+    let quiescent = state.network.iter_all().next().is_none()
+        && elders.iter().all(|(id, wallet)| {
+            elders
+                .iter()
+                .filter(|(peer, _)| peer != id)
+                .all(|(peer, other)| {
+                    wallet.heard_digests.get(peer) == Some(&(digest(other), digest(wallet)))
+                })
+        });
+    let digests = BTreeSet::from_iter(elders.iter().map(|(_, wallet)| digest(wallet)));
+
+    !cfg.ledger_anti_entropy || !quiescent || digests.len() <= 1
+}
+
+// Once no message is left in flight, every current elder holds the same spent state. Unlike the
+// check above this doesn't count on anti-entropy, so it shows whether committing alone gets the
+// elders there.
+fn prop_quiesced_elders_agree_on_spent_state(state: &ActorModelState<Node, Traffic>) -> bool {
+    let digests = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(i, a)| a.in_section() && a.signed_elders().contains(&Id::from(*i)))
+            .map(|(_, a)| a.wallet.ledger.spentbook.digest()),
+    );
+
+    state.network.iter_all().next().is_none() && digests.len() <= 1
+}
+
+// Once no message is left in flight nothing is left half done either: no join or leave waits on
+// witnesses and no Tx on commitments. Matching stable sets can hide either kind of leftover.
+fn prop_quiesced_nodes_have_no_pending(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.network.iter_all().next().is_none()
+        && prop_churn_completed_or_abandoned(state)
+        && state
+            .actor_states
+            .iter()
+            .filter(|s| !s.is_client)
+            .all(|s| s.wallet.ledger.pending_commitments.is_empty())
+}
+
+// Once the stable set settles and every custody message arrived, each current elder holds every Tx
+// a quorum of earlier elders committed anywhere.
+fn prop_no_tx_lost_in_handover(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    if !cfg.ledger_custody
+        || state.network.iter_all().next().is_some()
+        || !prop_stable_set_converged(state)
+    {
+        return true;
+    }
+
+    let committed = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.wallet.ledger.spentbook.entries()),
+    );
+
+    state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(i, a)| a.in_section() && a.signed_elders().contains(&Id::from(*i)))
+        .all(|(_, a)| {
+            let earlier = Vec::from_iter(a.handover.elder_sets());
+            let earlier = &earlier[..earlier.len() - 1];
+            committed
+                .iter()
+                .filter(|(tx, proof)| {
+                    earlier
+                        .iter()
+                        .any(|elders| tx.has_quorum(cfg.quorum, proof, elders))
+                })
+                .all(|(tx, _)| a.wallet.ledger.is_committed(tx))
+        })
+}
+
+// Once the stable set settles and the run can't go any further, every member holds a SAP chain
+// ending at the decided elders. Lagging nodes keep their timer set, so members that missed SAPs
+// while cut off fetched them by then.
+fn prop_lagging_sap_chains_catch_up(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    if !cfg.sap_catch_up || !is_terminal(state) || !prop_stable_set_converged(state) {
+        return true;
+    }
+
+    state
+        .actor_states
+        .iter()
+        .filter(|s| s.in_section() && !s.is_client)
+        .all(|s| s.handover.elders() == s.elders())
+}
+
+// Every node that rejoined is a member again on every node, under a newer ord_idx than the one it
+// left with.
+fn prop_rejoined_nodes_converge(state: &ActorModelState<Node, Traffic>) -> bool {
+    let rejoined = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(_, actor)| actor.rejoined)
+            .map(|(id, _)| Id::from(id)),
+    );
+
+    state
+        .actor_states
+        .iter()
+        .filter(|s| s.in_section())
+        .all(|actor| {
+            let stable_set = &actor.membership.stable_set;
+            rejoined.iter().all(|id| {
+                let departed_ord_idx = stable_set
+                    .dead()
+                    .filter(|m| &m.id == id)
+                    .map(|m| m.ord_idx)
+                    .max();
+
+                match (stable_set.member_by_id(*id), departed_ord_idx) {
+                    (Some(member), Some(departed)) => member.ord_idx > departed,
+                    _ => false,
+                }
+            })
+        })
+}
+
+// Once the run can't go any further on a fair path, no live node still counts a member some node
+// saw a quorum decide has left.
+fn prop_dead_members_gone_everywhere(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    if !fair_links(cfg, state) || !is_terminal(state) {
+        return true;
+    }
+
+    let dead = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|actor| actor.membership.stable_set.dead()),
+    );
+
+    state
+        .actor_states
+        .iter()
+        .filter(|s| s.in_section())
+        .all(|actor| actor.membership.members().iter().all(|m| !dead.contains(m)))
+}
+
+fn prop_churn_completed_or_abandoned(state: &ActorModelState<Node, Traffic>) -> bool {
+    state
+        .actor_states
+        .iter()
+        .filter(|s| s.in_section())
+        .all(|actor| {
+            let stable_set = &actor.membership.stable_set;
+            stable_set.joining_members.is_empty() && stable_set.leaving_members.is_empty()
+        })
+}
+
+// A join or leave a node let expire stays gone, merging peers that still hold it pending mustn't
+// bring it back.
+fn prop_abandoned_stays_abandoned(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|actor| {
+        let membership = &actor.membership;
+        membership.abandoned.iter().all(|m| {
+            !membership.stable_set.joining_members.contains_key(m)
+                && !membership.stable_set.leaving_members.contains_key(m)
+        })
+    })
+}
+
+// With nothing in flight, nodes restored from a snapshot agree with the nodes that kept every
+// departed member on who is in and who has left, whenever they are at the same generation.
+fn prop_restored_nodes_agree_with_full_history(state: &ActorModelState<Node, Traffic>) -> bool {
+    let stable_sets = |restored: bool| {
+        Vec::from_iter(
+            state
+                .actor_states
+                .iter()
+                .filter(|actor| actor.restored_from_snapshot == restored)
+                .map(|actor| &actor.membership.stable_set),
+        )
+    };
+    let full_history = stable_sets(false);
+
+    state.network.len() > 0
+        || stable_sets(true).into_iter().all(|restored| {
+            full_history
+                .iter()
+                .filter(|full| full.gen() == restored.gen())
+                .all(|full| {
+                    full.members() == restored.members() && full.dead().all(|m| restored.is_dead(m))
+                })
+        })
+}
+
+// Every proof an honest node hands out checks out against elders the section actually decided on,
+// not just the signers the proof names itself.
+fn prop_membership_proofs_verify(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    let honest = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !cfg.byzantine.contains(&Id::from(*i)))
+            .map(|(_, s)| s),
+    );
+    let decided = BTreeSet::from_iter(honest.iter().flat_map(|s| s.elder_history.iter()));
+
+    honest.iter().all(|actor| {
+        let stable_set = &actor.membership.stable_set;
+        stable_set
+            .ids()
+            .filter_map(|id| stable_set.membership_proof(id))
+            .all(|proof| decided.iter().any(|elders| proof.verify(elders)))
+    })
+}
+
+fn prop_sync_hops_bounded(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    state
+        .network
+        .iter_all()
+        .all(|envelope| envelope.msg.hops <= cfg.max_sync_hops)
+}
+
+fn joined_count(state: &ActorModelState<Node, Traffic>) -> usize {
+    let genesis_count = state
+        .actor_states
+        .first()
+        .map(|s| s.elder_history[0].len())
+        .unwrap_or_default();
+
+    reference_stable_set(state)
+        .members()
+        .len()
+        .saturating_sub(genesis_count)
+}
+
+fn reissued_count(state: &ActorModelState<Node, Traffic>) -> usize {
+    state
+        .actor_states
+        .iter()
+        .map(|s| s.wallet.ledger.spentbook.tx_count())
+        .max()
+        .unwrap_or_default()
+}
+
+// Counts every join, leave and reissue any node decided on, so a bounded check still goes through
+// every interleaving of the ones that fit the bound.
+fn within_boundary(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    let stable_sets = Vec::from_iter(state.actor_states.iter().map(|s| &s.membership.stable_set));
+    let joined = BTreeSet::from_iter(
+        stable_sets
+            .iter()
+            .flat_map(|s| s.members().iter().chain(s.dead()))
+            .filter(|m| m.ord_idx > 0),
+    );
+    let left = BTreeSet::from_iter(stable_sets.iter().flat_map(|s| s.dead()));
+    let reissued = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|s| s.wallet.ledger.spentbook.txs()),
+    );
+
+    let within = |count: usize, max: Option<usize>| max.is_none_or(|max| count <= max);
+    within(joined.len(), cfg.max_joins)
+        && within(left.len(), cfg.max_leaves)
+        && within(reissued.len(), cfg.max_reissues)
+}
+
+fn membership_change_count(state: &ActorModelState<Node, Traffic>) -> usize {
+    let reference_stable_set = reference_stable_set(state);
+    let left_count = state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(id, actor)| actor.is_leaving && !reference_stable_set.contains(Id::from(*id)))
+        .count();
+
+    joined_count(state) + left_count
+}
+
+// Only meaningful when traffic is being recorded.
+fn prop_syncs_linear_in_membership_changes(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let syncs = state.history.msgs_by_kind.get("sync").copied().unwrap_or(0);
+    let changes = membership_change_count(state) + 1;
+
+    syncs <= cfg.max_syncs_per_change * changes
+}
+
+fn prop_bytes_per_change_bounded(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    let changes = joined_count(state) + reissued_count(state);
+    changes == 0 || state.history.bytes_per(changes) <= cfg.max_bytes_per_change
+}
+
+// Spenders that retry never hang on a Tx, it either commits or a majority of the elders rejects it.
+// Once the run can't go any further, every Tx a node submitted committed or was rejected before its
+// retries ran out.
+fn prop_submitted_txs_resolved(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    cfg.reissue_retries.is_none()
+        || !is_terminal(state)
+        || state
+            .actor_states
+            .iter()
+            .all(|actor| actor.wallet.submitted.is_empty() && actor.wallet.abandoned.is_empty())
+}
+
+// Once the run can't go any further, every live node fell back to the symmetric scheme for the Txs
+// it holds pending whose coordinator crashed.
+fn prop_crashed_coordinator_falls_back(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    cfg.aggregation != Aggregation::Coordinator
+        || !is_terminal(state)
+        || state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(id, _)| !cfg.crashed.contains(&Id::from(*id)))
+            .all(|(_, actor)| {
+                let elders = actor.signed_elders();
+                actor.wallet.ledger.pending_commitments.keys().all(|tx| {
+                    let coordinator_crashed = ledger::coordinator(&elders, tx)
+                        .map(|c| cfg.crashed.contains(&c))
+                        .unwrap_or(false);
+
+                    !coordinator_crashed || actor.wallet.fallback_txs.contains(tx)
+                })
+            })
+}
+
+// Each node's own view conserves money: its unspent outputs add up to the supply, and still do once
+// the Txs it holds pending commit on top.
+fn prop_unspent_outputs_equal_supply(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|actor| {
+        let ledger = &actor.wallet.ledger;
+        let pending = Vec::from_iter(ledger.pending_commitments.keys().cloned());
+        LEDGER_BALANCES.check((ledger.spentbook.clone(), pending), || {
+            let (supply, unspent) = ledger.supply_with_pending();
+            ledger.expected_supply() == ledger.sum_unspent_outputs() && supply == unspent
+        })
+    })
+}
+
+fn prop_wallet_fragmentation_bounded(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    state
+        .actor_states
+        .iter()
+        .all(|actor| actor.wallet.fragmentation() <= cfg.max_unspent_dbcs)
+}
+
+fn prop_handover_chain_matches_decided_elders(state: &ActorModelState<Node, Traffic>) -> bool {
+    let decided_elder_sets = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.elder_history.iter()),
+    );
+
+    state.actor_states.iter().all(|actor| {
+        let chain = Vec::from_iter(actor.handover.elder_sets());
+
+        let no_duplicate_generations = chain.windows(2).all(|w| w[0] != w[1]);
+        let no_skipped_generations = chain.iter().all(|e| decided_elder_sets.contains(e));
+
+        no_duplicate_generations && no_skipped_generations
+    })
+}
+
+// No node's SAP chain runs longer than the elder set changed in the run, one that does signed or
+// accepted some generation twice. Elder sets can only come back when nodes rejoin under their id.
+fn prop_handover_gen_bounded(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    let decided_elder_sets = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.elder_history.iter()),
+    );
+    let changes = decided_elder_sets.len().saturating_sub(1);
+
+    cfg.rejoin_after_leave
+        || state
+            .actor_states
+            .iter()
+            .all(|a| a.handover.gen() <= changes)
+}
+
+// Once the stable set settles and the run can't go any further, every decided elder holds a SAP
+// chain ending at the decided elders, the elders before them signed them in. A dropped handover
+// share is never resent, so only fair paths count.
+fn prop_sap_chain_reaches_decided_elders(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    if !fair_links(cfg, state) || !is_terminal(state) || !prop_stable_set_converged(state) {
+        return true;
+    }
+
+    state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.in_section() && !s.is_client)
+        .filter(|(id, s)| s.elders().contains(&Id::from(*id)))
+        .all(|(_, s)| s.handover.elders() == s.elders())
+}
+
+// Every SAP chain verifies back to genesis, and any two chains hold the same sections up to the
+// shorter one, or up to where they went on with different halves of a split.
+fn prop_sap_chains_never_diverge(state: &ActorModelState<Node, Traffic>) -> bool {
+    let chains = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .map(|s| Vec::from_iter(s.handover.chain_sections())),
+    );
+
+    state.actor_states.iter().all(|s| s.handover.verify_chain())
+        && chains.iter().all(|a| {
+            chains.iter().all(|b| {
+                a.iter()
+                    .zip(b)
+                    .take_while(|((x, _), (y, _))| x.overlaps(y))
+                    .all(|(x, y)| x == y)
+            })
+        })
+}
+
+// The elders in an honest node's SAP chain are always ones some honest node decided on. Colluders
+// holding a majority of the elders can sign in whoever they like, so this breaks exactly there.
+// Honest nodes never send forged shares, so any evidence against one was fabricated, and short of a
+// quorum of colluding elders it never gets an honest node removed. Nodes leaving by themselves and
+// those dropped for missing heartbeats go regardless.
+fn prop_no_removal_on_fabricated_evidence(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let is_honest =
+        |id: Id| usize::from(id) < state.actor_states.len() && !cfg.byzantine.contains(&id);
+
+    cfg.heartbeat_misses.is_some()
+        || state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| is_honest(Id::from(*i)))
+            .all(|(_, a)| {
+                a.membership.accused.keys().all(|m| {
+                    !is_honest(m.id)
+                        || state.actor_states[usize::from(m.id)].is_leaving
+                        || !a.membership.stable_set.is_dead(m)
+                })
+            })
+}
+
+fn prop_honest_saps_sign_in_decided_elders(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let honest = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !cfg.byzantine.contains(&Id::from(*i)))
+            .map(|(_, s)| s),
+    );
+    let decided = BTreeSet::from_iter(honest.iter().flat_map(|s| s.elder_history.iter()));
+
+    honest.iter().all(|s| {
+        s.handover
+            .chain_elders()
+            .all(|elders| decided.contains(elders))
+    })
+}
+
+// Only meaningful when the message log is recorded. A node's own generation is at least the latest it
+// sent out, a ledger message carrying a stable set more than one generation behind that was handled
+// by a node that should have known better.
+fn prop_ledger_msgs_never_stale(state: &ActorModelState<Node, Traffic>) -> bool {
+    let mut sent_gen = BTreeMap::<Id, u64>::new();
+    state.history.log.iter().all(|record| {
+        if !record.delivered {
+            let gen = sent_gen.entry(record.src).or_default();
+            *gen = std::cmp::max(*gen, record.gen);
+            return true;
+        }
+
+        !record.ledger || record.gen + 1 >= sent_gen.get(&record.dst).copied().unwrap_or(0)
+    })
+}
+
+// Every elder set an honest node went through held too few Byzantine elders to push two conflicting
+// decisions of `quorum` through.
+fn within_byzantine_margin(
+    cfg: &ModelCfg,
+    quorum: Quorum,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(i, s)| !cfg.byzantine.contains(&Id::from(*i)) && !s.is_client)
+        .flat_map(|(_, s)| s.handover.elder_sets())
+        .all(|elders| quorum.tolerates(elders.intersection(&cfg.byzantine).count(), elders.len()))
+}
+
+// Equivocating elders witness both sides of every double spend, honest nodes still never commit
+// different Txs for the same dbc while the colluders are within the ledger quorum's margin.
+fn prop_no_double_spends_within_margin(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    if !cfg.equivocate || !within_byzantine_margin(cfg, cfg.quorum.ledger, state) {
+        return true;
+    }
+
+    let spentbooks = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !cfg.byzantine.contains(&Id::from(*i)))
+            .map(|(_, s)| &s.wallet.ledger.spentbook),
+    );
+
+    spentbooks.iter().all(|a| {
+        a.txs().all(|tx| {
+            tx.inputs.iter().all(|input| {
+                spentbooks
+                    .iter()
+                    .all(|b| b.spender(&input.id()).is_none_or(|s| s == hash(tx)))
+            })
+        })
+    })
+}
+
+// Equivocating elders vote every honest elder out, none of them goes while the colluders are within
+// the leave quorum's margin. Members dropped for missing heartbeats or an evicted key go regardless.
+fn prop_honest_members_kept_within_margin(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    if !cfg.equivocate
+        || cfg.heartbeat_misses.is_some()
+        || cfg.evicted_key.is_some()
+        || !within_byzantine_margin(cfg, cfg.quorum.leaves, state)
+    {
+        return true;
+    }
+
+    let is_honest =
+        |id: Id| usize::from(id) < state.actor_states.len() && !cfg.byzantine.contains(&id);
+
+    state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| is_honest(Id::from(*i)))
+        .all(|(_, a)| {
+            a.membership.stable_set.dead().all(|m| {
+                let node = &state.actor_states[usize::from(m.id)];
+                !is_honest(m.id) || node.is_leaving || node.rejoined
+            })
+        })
+}
+
+// Forged shares never verify, so a SAP for elders no honest node decided on only gets into an honest
+// chain when the colluders alone are a majority of the elders before it.
+fn prop_undecided_saps_need_byzantine_majority(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let honest = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !cfg.byzantine.contains(&Id::from(*i)))
+            .map(|(_, s)| s),
+    );
+    let decided = BTreeSet::from_iter(honest.iter().flat_map(|s| s.elder_history.iter()));
+
+    honest.iter().all(|s| {
+        s.handover
+            .elder_sets()
+            .zip(s.handover.chain_elders())
+            .all(|(prev, elders)| {
+                decided.contains(elders)
+                    || cfg
+                        .quorum
+                        .handover
+                        .reached(prev.intersection(&cfg.byzantine).count(), prev.len())
+            })
+    })
+}
+
+// Every Tx a node committed carries a quorum of elders some SAP it holds signed in, or of the genesis
+// elders. Clients hold no SAPs.
+fn prop_committed_txs_have_sap_quorum(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().filter(|s| !s.is_client).all(|s| {
+        s.wallet
+            .ledger
+            .spentbook
+            .entries()
+            .iter()
+            .all(|(tx, proof)| {
+                s.handover
+                    .elder_sets()
+                    .any(|elders| tx.has_quorum(s.wallet.ledger.quorum, proof, elders))
+            })
+    })
+}
+
+// A split signs in the elders of both halves at the same generation, otherwise every generation
+// signs in one set of elders.
+fn prop_one_sap_per_generation(state: &ActorModelState<Node, Traffic>) -> bool {
+    let mut sections_by_gen: BTreeMap<usize, BTreeSet<(Prefix, Elders)>> = BTreeMap::new();
+    for sap in state
+        .actor_states
+        .iter()
+        .flat_map(|s| s.handover.verified_saps())
+    {
+        sections_by_gen
+            .entry(sap.gen())
+            .or_default()
+            .insert((sap.prefix(), sap.elders().clone()));
+    }
+
+    sections_by_gen.values().all(|sections| {
+        sections
+            .iter()
+            .all(|(a, _)| sections.iter().filter(|(b, _)| a.overlaps(b)).count() == 1)
+    })
+}
+
+fn prop_saps_carry_complete_key_sets(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    !cfg.dkg
+        || state.actor_states.iter().all(|s| {
+            s.handover
+                .key_sets()
+                .all(|key_set| key_set.is_some_and(dkg::KeySet::is_complete))
+        })
+}
+
+// Once the stable set settles and the run can't go any further, every decided elder holds a
+// complete key set for the decided elders, unless they are still the genesis elders. Elders keep
+// their timer set while their DKG is pending.
+fn prop_dkg_completes(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    if !cfg.dkg || !is_terminal(state) || !prop_stable_set_converged(state) {
+        return true;
+    }
+
+    state
+        .actor_states
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.in_section() && !s.is_client)
+        .filter(|(id, s)| s.elders().contains(&Id::from(*id)))
+        .all(|(_, s)| s.elders() == s.elder_history[0] || s.dkg.key_set(&s.elders()).is_some())
+}
+
+fn prop_no_fabricated_members_admitted(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let fabricated_ids = cfg
+        .adversary
+        .as_ref()
+        .map(MutatingNetwork::fabricated_ids)
+        .unwrap_or_default();
+
+    state.actor_states.iter().all(|actor| {
+        actor
+            .membership
+            .stable_set
+            .ids()
+            .all(|id| usize::from(id) < state.actor_states.len() && !fabricated_ids.contains(&id))
+    })
+}
+
+fn prop_blacklisted_keys_never_rejoin(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|actor| {
+        let membership = &actor.membership;
+        membership.stable_set.blacklisted().all(|entry| {
+            membership
+                .members()
+                .iter()
+                .filter(|m| membership.identity_key(m.id) == entry.key)
+                .all(|m| m == &entry.member)
+        })
+    })
+}
+
+fn prop_removals_backed_by_elder_majority(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let proposers = |member: &Member| {
+        BTreeSet::from_iter(
+            state
+                .actor_states
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| a.membership.proposed_removals.contains(member))
+                .map(|(id, _)| Id::from(id)),
+        )
+    };
+
+    state.actor_states.iter().all(|actor| {
+        actor.membership.stable_set.dead().all(|member| {
+            let asked_to_leave = state
+                .actor_states
+                .get(usize::from(member.id))
+                .map(|departed| departed.is_leaving || departed.rejoined)
+                .unwrap_or(false);
+            let evicted = actor
+                .membership
+                .stable_set
+                .blacklisted()
+                .any(|entry| &entry.member == member);
+            let proposers = proposers(member);
+
+            asked_to_leave
+                || evicted
+                || actor.elder_history.iter().any(|elders| {
+                    cfg.quorum
+                        .leaves
+                        .reached(proposers.intersection(elders).count(), elders.len())
+                })
+        })
+    })
+}
+
+fn prop_rejections_leave_state_untouched(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|actor| {
+        actor
+            .last_rejection
+            .as_ref()
+            .map(|r| r.fingerprint_before == r.fingerprint_after)
+            .unwrap_or(true)
+    })
+}
+
+fn prop_split_sections_partition_members(state: &ActorModelState<Node, Traffic>) -> bool {
+    let sections = Vec::from_iter(state.actor_states.iter().map(|a| &a.membership));
+
+    let members_within_prefix = sections
+        .iter()
+        .all(|a| a.stable_set.ids().all(|id| a.prefix.matches(id_name(id))));
+
+    let no_member_in_two_sections = sections.iter().all(|a| {
+        sections
+            .iter()
+            .filter(|b| !a.prefix.overlaps(&b.prefix))
+            .all(|b| a.stable_set.ids().all(|id| !b.stable_set.contains(id)))
+    });
+
+    members_within_prefix && no_member_in_two_sections
+}
+
+// Once split, sections only spend dbcs under their own prefix, so no two of them ever commit
+// different Txs spending the same dbc. Spends both halves inherited from before the split agree.
+fn prop_no_dbc_spent_differently_in_two_sections(state: &ActorModelState<Node, Traffic>) -> bool {
+    let ledgers = Vec::from_iter(state.actor_states.iter().map(|a| &a.wallet.ledger));
+
+    ledgers.iter().all(|a| {
+        ledgers
+            .iter()
+            .filter(|b| !a.prefix.overlaps(&b.prefix))
+            .all(|b| {
+                a.spentbook.txs().all(|tx| {
+                    tx.inputs.iter().all(|input| {
+                        b.spentbook
+                            .spender(&input.id())
+                            .is_none_or(|spender| spender == hash(tx))
+                    })
+                })
+            })
+    })
+}
+
+// Every statement a node holds was signed by the elders of one of its SAPs, and matches what every
+// node at the statement's generation sees.
+fn prop_health_statements_match_decided_state(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|actor| {
+        actor.health.chain().all(|(record, section_sig)| {
+            let signed = actor
+                .handover
+                .elder_sets()
+                .any(|elders| section_sig.verify(elders, record));
+            let same_gen_agrees = state
+                .actor_states
+                .iter()
+                .filter(|other| !other.is_client && other.membership.stable_set.gen() == record.gen)
+                .filter(|other| other.membership.prefix.overlaps(&actor.membership.prefix))
+                .all(|other| HealthRecord::observe(&other.membership) == *record);
+
+            signed && same_gen_agrees
+        })
+    })
+}
+
+// Once sections have a maximum size only the leader takes candidates off its queue, so no node has
+// voted in a candidate the leader queued behind one that's still waiting.
+fn prop_join_queue_is_fifo(state: &ActorModelState<Node, Traffic>) -> bool {
+    let voted_in = BTreeSet::from_iter(state.actor_states.iter().flat_map(|actor| {
+        let stable_set = &actor.membership.stable_set;
+        Vec::from_iter(stable_set.ids().chain(stable_set.joining().map(|m| m.id)))
+    }));
+
+    state.actor_states.iter().enumerate().all(|(i, actor)| {
+        let membership = &actor.membership;
+        if membership.max_members.is_none() || !membership.admits_from_queue(Id::from(i)) {
+            return true;
+        }
+        membership
+            .join_queue
+            .iter()
+            .skip_while(|c| voted_in.contains(c))
+            .all(|c| !voted_in.contains(c))
+    })
+}
+
+fn prop_lazy_candidates_never_joining(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    state.actor_states.iter().all(|actor| {
+        actor
+            .membership
+            .stable_set
+            .joining()
+            .chain(actor.membership.members().iter().cloned())
+            .all(|m| !cfg.lazy_candidates.contains(&m.id))
+    })
+}
+
+// Challenges a candidate never answers expire rather than piling up, with nothing left in flight
+// every node has dropped them.
+fn prop_challenges_drain(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    !cfg.resource_proof
+        || cfg.pending_ttl.is_none()
+        || !fair_links(cfg, state)
+        || !is_terminal(state)
+        || state
+            .actor_states
+            .iter()
+            .all(|actor| actor.membership.challenges.is_empty())
+}
+
+fn prop_read_only_leavers_never_witness(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    !cfg.read_only_on_leave
+        || state
+            .actor_states
+            .iter()
+            .all(|actor| actor.witnessed_while_leaving == 0)
+}
+
+// Every input of a committed Tx is committed to that Tx, never some inputs to one Tx and the rest to
+// another.
+fn prop_committed_txs_hold_all_inputs(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|a| {
+        let spentbook = &a.wallet.ledger.spentbook;
+        spentbook.txs().all(|tx| {
+            tx.inputs
+                .iter()
+                .all(|input| spentbook.spent_by(&input.id()) == Some(tx))
+        })
+    })
+}
+
+fn prop_committed_txs_are_authorized(state: &ActorModelState<Node, Traffic>) -> bool {
+    state
+        .actor_states
+        .iter()
+        .all(|a| a.wallet.ledger.spentbook.txs().all(Tx::is_authorized))
+}
+
+// Every dbc a wallet holds, other than the genesis, came out of a Tx whose receipt verifies against
+// elders the section decided on.
+fn prop_spendable_outputs_have_receipts(state: &ActorModelState<Node, Traffic>) -> bool {
+    let decided_elders = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.elder_history.iter()),
+    );
+
+    state.actor_states.iter().all(|a| {
+        let wallet = &a.wallet;
+        wallet
+            .owned_dbcs()
+            .iter()
+            .filter(|dbc| **dbc != genesis_dbc())
+            .all(|dbc| match wallet.confirmed.get(&dbc.tx) {
+                Some(receipt) => {
+                    decided_elders.contains(&receipt.signers)
+                        && receipt.msg == dbc.tx
+                        && receipt.verify(&receipt.signers)
+                }
+                None => false,
+            })
+    })
+}
+
+// Reissues with a malformed commitment are rejected before any elder signs them.
+fn prop_committed_commitments_well_formed(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|a| {
+        a.wallet
+            .ledger
+            .spentbook
+            .txs()
+            .flat_map(|tx| tx.outputs.iter())
+            .all(|output| output.commitment.is_well_formed())
+    })
+}
+
+fn prop_double_spends_never_commit(cfg: &ModelCfg, state: &ActorModelState<Node, Traffic>) -> bool {
+    let proofs = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.wallet.ledger.double_spends.iter()),
+    );
+
+    !cfg.double_spend_proofs
+        || state.actor_states.iter().all(|a| {
+            a.wallet
+                .ledger
+                .spentbook
+                .txs()
+                .all(|tx| proofs.iter().all(|proof| !proof.contains(tx)))
+        })
+}
+
+// A Tx commits under a quorum of elders the section decided on, and a Tx some node dropped on
+// handover never commits anywhere.
+fn prop_txs_committed_whole_or_dropped(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let decided_elders = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.elder_history.iter()),
+    );
+    let dropped = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.wallet.ledger.dropped.iter()),
+    );
+
+    state.actor_states.iter().all(|a| {
+        let spentbook = &a.wallet.ledger.spentbook;
+        spentbook.txs().all(|tx| {
+            let proof = tx
+                .inputs
+                .first()
+                .and_then(|input| spentbook.entry(&input.id()))
+                .map(|(_, proof)| proof)
+                .unwrap_or_default();
+
+            !dropped.contains(tx)
+                && decided_elders
+                    .iter()
+                    .any(|elders| tx.has_quorum(cfg.quorum, &proof, elders))
+        })
+    })
+}
+
+// Elders only commit a Tx once they committed the Txs its inputs came out of.
+fn prop_parents_commit_before_children(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().enumerate().all(|(i, a)| {
+        let ledger = &a.wallet.ledger;
+        !a.signed_elders().contains(&Id::from(i))
+            || ledger
+                .spentbook
+                .txs()
+                .flat_map(|tx| tx.inputs.iter())
+                .all(|input| ledger.is_committed_output(input))
+    })
+}
+
+// A majority of the elders never agree that a dbc is unspent once they committed its spend. Each
+// verdict is checked against what was committed when it came in, elders committing the spend only
+// afterwards don't make an earlier verdict wrong.
+fn prop_no_unspent_verdict_for_committed_dbcs(state: &ActorModelState<Node, Traffic>) -> bool {
+    state.actor_states.iter().all(|a| {
+        a.wallet
+            .spent_verdicts
+            .values()
+            .all(|(spent, _, committed)| *spent || !committed)
+    })
+}
+
+// Every committed output holds a denomination.
+fn prop_amounts_representable_in_denominations(
+    cfg: &ModelCfg,
+    state: &ActorModelState<Node, Traffic>,
+) -> bool {
+    let Some(base) = cfg.denomination_base else {
+        return true;
+    };
+
+    state.actor_states.iter().all(|a| {
+        a.wallet
+            .ledger
+            .spentbook
+            .txs()
+            .flat_map(|tx| tx.outputs.iter())
+            .all(|output| ledger::is_denomination(output.commitment.amount, base))
+    })
+}
+
+fn prop_aborted_txs_never_commit(state: &ActorModelState<Node, Traffic>) -> bool {
+    let aborted = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.wallet.ledger.aborted.iter()),
+    );
+
+    state.actor_states.iter().all(|a| {
+        a.wallet
+            .ledger
+            .spentbook
+            .txs()
+            .all(|tx| !aborted.contains(tx))
+    })
+}
+
+fn prop_aborted_inputs_respent_at_most_once(state: &ActorModelState<Node, Traffic>) -> bool {
+    let ledgers = Vec::from_iter(state.actor_states.iter().map(|a| &a.wallet.ledger));
+
+    ledgers
+        .iter()
+        .flat_map(|l| l.aborted.iter())
+        .flat_map(|tx| tx.inputs.iter().map(Dbc::id))
+        .all(|dbc_id| {
+            let spent_by =
+                BTreeSet::from_iter(ledgers.iter().filter_map(|l| l.spentbook.spender(&dbc_id)));
+            spent_by.len() <= 1
+        })
+}
+
+fn prop_no_double_spends(state: &ActorModelState<Node, Traffic>) -> bool {
+    let key = Vec::from_iter(
+        state
+            .actor_states
+            .iter()
+            .map(|a| (a.membership.elders(), a.wallet.read_tx(&genesis_dbc().id()))),
+    );
+
+    DOUBLE_SPENDS.check(key, || no_double_spends(state))
+}
+
+fn no_double_spends(state: &ActorModelState<Node, Traffic>) -> bool {
+    let actor_by_id = BTreeMap::from_iter(
+        state
+            .actor_states
+            .iter()
+            .enumerate()
+            .map(|(id, s)| (Id::from(id), s)),
+    );
+
+    let concurrent_txs = BTreeSet::from_iter(state.actor_states.iter().flat_map(|a| {
+        let mut transactions: BTreeMap<Tx, usize> = Default::default();
+
+        let elders = a.membership.elders();
+
+        for elder in &elders {
+            if let Some(tx) = actor_by_id
+                .get(elder)
+                .unwrap()
+                .wallet
+                .read_tx(&genesis_dbc().id())
+            {
+                let tx_count = transactions.entry(tx).or_default();
+                *tx_count += 1;
+            }
+        }
+
+        transactions
+            .into_iter()
+            .filter(move |(_, count)| majority(*count, elders.len()))
+            .map(|(tx, _)| tx)
+    }));
+
+    concurrent_txs.len() <= 1
+}
+
+// Some node decided on an elder that wasn't among the genesis elders, so the handover paths the
+// other properties cover actually run.
+fn prop_non_genesis_elder_promoted(state: &ActorModelState<Node, Traffic>) -> bool {
+    state
+        .actor_states
+        .iter()
+        .filter(|s| !s.is_client)
+        .any(|s| !s.elders().is_subset(&s.elder_history[0]))
+}
+
+// Two Txs spending the same dbc are pending at once, with the same elder or with different ones,
+// so the double spend properties are not passing on Txs that never raced.
+fn prop_conflicting_txs_pending(state: &ActorModelState<Node, Traffic>) -> bool {
+    let pending = BTreeSet::from_iter(
+        state
+            .actor_states
+            .iter()
+            .flat_map(|a| a.wallet.ledger.pending_commitments.keys()),
+    );
+
+    pending.iter().any(|a| {
+        pending
+            .iter()
+            .any(|b| a != b && a.inputs.iter().any(|input| b.inputs.contains(input)))
+    })
+}
+
+impl Default for ModelCfg {
+    fn default() -> Self {
+        Self {
+            elder_count: 1,
+            server_count: 5,
+            coin_selection: CoinSelection::default(),
+            max_unspent_dbcs: 2,
+            adversary: None,
+            identity_keys: BTreeMap::new(),
+            evicted_key: None,
+            link_drop_rates: None,
+            severed_links: BTreeSet::new(),
+            sync_ttl: None,
+            max_sync_hops: 8,
+            record_traffic: false,
+            record_msg_log: false,
+            max_bytes_per_change: 64 * 1024,
+            aggregation: Aggregation::default(),
+            crashed: BTreeSet::new(),
+            deferred_sync: false,
+            // Every node syncing every other node once per change.
+            max_syncs_per_change: 5 * 5,
+            rejoin_after_leave: false,
+            heartbeat_misses: None,
+            split_threshold: None,
+            health_period: None,
+            max_members: None,
+            resource_proof: false,
+            lazy_candidates: BTreeSet::new(),
+            read_only_on_leave: false,
+            digest_sync: false,
+            pending_ttl: None,
+            serialize_churn: false,
+            delta_sync: false,
+            snapshot_period: None,
+            restore_from_snapshot: BTreeSet::new(),
+            agreed_ord_idx: false,
+            combine_outputs: false,
+            reissue_retries: None,
+            client_count: 0,
+            double_spend_proofs: false,
+            hand_over_pending: false,
+            prune_spentbook: false,
+            spend_chain_depth: 0,
+            query_spent_inputs: false,
+            denomination_base: None,
+            batch_reissues: false,
+            ledger_anti_entropy: false,
+            supply_change: Supply::Fixed,
+            dkg: false,
+            slow_dkg_participant: None,
+            ledger_custody: false,
+            sap_catch_up: false,
+            quorum: QuorumPolicy::uniform(Quorum::TwoThirds),
+            byzantine: BTreeSet::new(),
+            weighting: Weighting::Equal,
+            report_misbehavior: false,
+            resend_rounds: None,
+            equivocate: false,
+            hash_only_sync: false,
+            max_joins: None,
+            max_leaves: None,
+            max_reissues: None,
+            network: Network::new_unordered_nonduplicating([]),
+        }
+    }
+}
+
+impl ModelCfg {
+    // The first `elder_count` peers form the genesis section.
+    pub fn node(&self, peers: Vec<Id>, metrics: Option<Arc<Metrics>>) -> Node {
+        // Powers of 0 or 1 can't make up every amount.
+        assert!(
+            self.denomination_base.is_none_or(|base| base >= 2),
+            "denomination base must be at least 2"
+        );
+        assert_ne!(
+            self.health_period,
+            Some(0),
+            "health period must be at least 1"
+        );
+        let clients =
+            BTreeSet::from_iter((peers.len()..peers.len() + self.client_count).map(Id::from));
+        Node {
+            genesis_nodes: BTreeSet::from_iter(peers.iter().take(self.elder_count).copied()),
+            peers,
+            coin_selection: self.coin_selection,
+            adversary: self.adversary.clone(),
+            identity_keys: self.identity_keys.clone(),
+            evicted_key: self.evicted_key,
+            severed_links: self.severed_links.clone(),
+            metrics,
+            sync_ttl: self.sync_ttl,
+            aggregation: self.aggregation,
+            crashed: self.crashed.clone(),
+            deferred_sync: self.deferred_sync,
+            rejoin_after_leave: self.rejoin_after_leave,
+            heartbeat_misses: self.heartbeat_misses,
+            split_threshold: self.split_threshold,
+            health_period: self.health_period,
+            max_members: self.max_members,
+            resource_proof: self.resource_proof,
+            lazy_candidates: self.lazy_candidates.clone(),
+            read_only_on_leave: self.read_only_on_leave,
+            digest_sync: self.digest_sync,
+            pending_ttl: self.pending_ttl,
+            serialize_churn: self.serialize_churn,
+            delta_sync: self.delta_sync,
+            snapshot_period: self.snapshot_period,
+            restore_from_snapshot: self.restore_from_snapshot.clone(),
+            agreed_ord_idx: self.agreed_ord_idx,
+            combine_outputs: self.combine_outputs,
+            reissue_retries: self.reissue_retries,
+            clients,
+            double_spend_proofs: self.double_spend_proofs,
+            hand_over_pending: self.hand_over_pending,
+            prune_spentbook: self.prune_spentbook,
+            spend_chain_depth: self.spend_chain_depth,
+            query_spent_inputs: self.query_spent_inputs,
+            denomination_base: self.denomination_base,
+            batch_reissues: self.batch_reissues,
+            ledger_anti_entropy: self.ledger_anti_entropy,
+            supply_change: self.supply_change,
+            dkg: self.dkg,
+            slow_dkg_participant: self.slow_dkg_participant,
+            ledger_custody: self.ledger_custody,
+            sap_catch_up: self.sap_catch_up,
+            quorum: self.quorum,
+            byzantine: self.byzantine.clone(),
+            weighting: self.weighting.clone(),
+            report_misbehavior: self.report_misbehavior,
+            resend_rounds: self.resend_rounds,
+            equivocate: self.equivocate,
+            hash_only_sync: self.hash_only_sync,
+        }
+    }
+
+    // Elder to elder links are reliable, every link involving a joining node is flaky.
+    pub fn flaky_joiner_links(&self) -> LinkDropRates {
+        let elders = Vec::from_iter((0..self.elder_count).map(Id::from));
+
+        LinkDropRates {
+            default: 0.2,
+            links: BTreeMap::from_iter(
+                elders
+                    .iter()
+                    .flat_map(|a| elders.iter().map(move |b| ((*a, *b), 0.0))),
+            ),
+        }
+    }
+
+    pub fn into_model(self) -> ActorModel<Node, Self, Traffic> {
+        let peers = Vec::from_iter((0..self.server_count).map(Id::from));
+
+        ActorModel::new(self.clone(), Traffic::default())
+            .actors(
+                (0..self.server_count + self.client_count).map(|_| self.node(peers.clone(), None)),
+            )
+            .init_network(self.network)
+            .record_msg_out(|cfg, history, envelope| {
+                let tallied = if cfg.record_traffic {
+                    Some(history.record(envelope.msg))
+                } else if cfg.link_drop_rates.is_some() {
+                    Some(history.count_sent())
+                } else {
+                    None
+                };
+                if cfg.record_msg_log {
+                    Some(tallied.as_ref().unwrap_or(history).log(false, envelope))
+                } else {
+                    tallied
+                }
+            })
+            .record_msg_in(|cfg, history, envelope| {
+                let tallied = cfg
+                    .link_drop_rates
+                    .is_some()
+                    .then(|| history.count_delivered());
+                if cfg.record_msg_log {
+                    Some(tallied.as_ref().unwrap_or(history).log(true, envelope))
+                } else {
+                    tallied
+                }
+            })
+            .within_boundary(within_boundary)
+            .lossy_network(if self.link_drop_rates.is_some() {
+                LossyNetwork::Yes
+            } else {
+                LossyNetwork::No
+            })
+            .property(
+                Expectation::Eventually,
+                "everyone who hasn't left converges on the same stable set",
+                |model, state| !fair_links(&model.cfg, state) || prop_stable_set_converged(state),
+            )
+            .property(
+                Expectation::Eventually,
+                "converged nodes agree on the stable set generation",
+                |model, state| {
+                    !fair_links(&model.cfg, state) || prop_converged_nodes_agree_on_gen(state)
+                },
+            )
+            .property(
+                Expectation::Eventually,
+                "converged nodes agree on the elders",
+                |model, state| {
+                    !fair_links(&model.cfg, state) || prop_converged_nodes_agree_on_elders(state)
+                },
+            )
+            .property(
+                Expectation::Always,
+                "members only drop out of a stable set when they leave or are witnessed out",
+                |model, state| prop_no_unwitnessed_removals(&model.cfg, state),
+            )
+            .property(
+                Expectation::Eventually,
+                "everyone who hasn't left is part of the final stable set",
+                |model, state| {
+                    !fair_links(&model.cfg, state)
+                        || prop_all_nodes_joined_who_havent_left(&model.cfg.lazy_candidates, state)
+                },
+            )
+            .property(
+                Expectation::Eventually,
+                "everyone who started leaving, will leave",
+                |model, state| {
+                    !fair_links(&model.cfg, state)
+                        || prop_all_nodes_who_are_leaving_eventually_left(state)
+                },
+            )
+            .property(
+                Expectation::Always,
+                "every join and leave completed or was abandoned once the run ends",
+                |model, state| {
+                    !fair_links(&model.cfg, state)
+                        || !is_terminal(state)
+                        || prop_churn_completed_or_abandoned(state)
+                },
+            )
+            .property(
+                Expectation::Always,
+                "members a quorum decided have left are gone from every live node once the run ends",
+                |model, state| prop_dead_members_gone_everywhere(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "nodes that rejoin are in everyone's stable set once the run ends",
+                |model, state| {
+                    !fair_links(&model.cfg, state)
+                        || !is_terminal(state)
+                        || prop_rejoined_nodes_converge(state)
+                },
+            )
+            .property(
+                Expectation::Always,
+                "no stable set holds two members with the same id",
+                |_, state| prop_ids_are_unique_members(state),
+            )
+            .property(
+                Expectation::Always,
+                "every elder is a current member",
+                |_, state| prop_elders_are_members(state),
+            )
+            .property(
+                Expectation::Always,
+                "sync messages never propagate beyond the hop bound",
+                |model, state| prop_sync_hops_bounded(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "bytes exchanged per join and reissue stay within budget",
+                |model, state| prop_bytes_per_change_bounded(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "reissues fell back to the symmetric scheme when the coordinator crashed once the run ends",
+                |model, state| prop_crashed_coordinator_falls_back(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "every submitted tx was committed or rejected once the run ends",
+                |model, state| prop_submitted_txs_resolved(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "sync messages grow linearly with membership changes",
+                |model, state| prop_syncs_linear_in_membership_changes(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "no node handles a ledger message more than a generation behind its stable set",
+                |_, state| prop_ledger_msgs_never_stale(state),
+            )
+            .property(Expectation::Always, "Ledger balances", |_, state| {
+                prop_unspent_outputs_equal_supply(state)
+            })
+            .property(
+                Expectation::Always,
+                "Never two nodes aggregate a double spend",
+                |_, state| prop_no_double_spends(state),
+            )
+            .property(
+                Expectation::Always,
+                "wallet fragmentation stays bounded",
+                |model, state| prop_wallet_fragmentation_bounded(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "handover chain has one entry per decided elder set",
+                |_, state| prop_handover_chain_matches_decided_elders(state),
+            )
+            .property(
+                Expectation::Always,
+                "no SAP chain is longer than the elder set changes in the run",
+                |model, state| prop_handover_gen_bounded(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "decided elders are signed in through handover once the run ends",
+                |model, state| prop_sap_chain_reaches_decided_elders(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "no two nodes hold diverging SAP chains",
+                |_, state| prop_sap_chains_never_diverge(state),
+            )
+            .property(
+                Expectation::Always,
+                "at most one verifiable SAP exists per generation",
+                |_, state| prop_one_sap_per_generation(state),
+            )
+            .property(
+                Expectation::Always,
+                "committed txs carry a quorum of SAP-certified elders",
+                |_, state| prop_committed_txs_have_sap_quorum(state),
+            )
+            .property(
+                Expectation::Always,
+                "no SAP is accepted with an incomplete key set",
+                |model, state| prop_saps_carry_complete_key_sets(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "DKG has completed for the decided elders despite a slow participant once the run ends",
+                |model, state| prop_dkg_completes(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "honest nodes never admit a fabricated member",
+                |model, state| prop_no_fabricated_members_admitted(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "a blacklisted key never re-enters membership",
+                |_, state| prop_blacklisted_keys_never_rejoin(state),
+            )
+            .property(
+                Expectation::Always,
+                "members are only removed by an elder majority of remove-witnesses",
+                |model, state| prop_removals_backed_by_elder_majority(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "a handler that rejects a message leaves the node untouched",
+                |_, state| prop_rejections_leave_state_untouched(state),
+            )
+            .property(
+                Expectation::Always,
+                "every member lands in exactly one section after a split",
+                |_, state| prop_split_sections_partition_members(state),
+            )
+            .property(
+                Expectation::Always,
+                "no dbc is spent by different Txs in two sections",
+                |_, state| prop_no_dbc_spent_differently_in_two_sections(state),
+            )
+            .property(
+                Expectation::Always,
+                "health statements match the decided state at their generation",
+                |_, state| prop_health_statements_match_decided_state(state),
+            )
+            .property(
+                Expectation::Always,
+                "queued joiners are admitted in FIFO order",
+                |_, state| prop_join_queue_is_fifo(state),
+            )
+            .property(
+                Expectation::Always,
+                "unanswered challenges never make it into joining members",
+                |model, state| prop_lazy_candidates_never_joining(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "unanswered challenges expire",
+                |model, state| prop_challenges_drain(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "read-only leavers never witness anything after their leave intent",
+                |model, state| prop_read_only_leavers_never_witness(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "an aborted tx never commits",
+                |_, state| prop_aborted_txs_never_commit(state),
+            )
+            .property(
+                Expectation::Always,
+                "inputs of an aborted tx are respent at most once",
+                |_, state| prop_aborted_inputs_respent_at_most_once(state),
+            )
+            .property(
+                Expectation::Always,
+                "abandoned joins and leaves are never pending again",
+                |_, state| prop_abandoned_stays_abandoned(state),
+            )
+            .property(
+                Expectation::Always,
+                "nodes restored from a snapshot agree with nodes that kept full history",
+                |_, state| prop_restored_nodes_agree_with_full_history(state),
+            )
+            .property(
+                Expectation::Always,
+                "membership proofs verify against the elders that signed them",
+                |model, state| prop_membership_proofs_verify(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "no two committed members share an ord_idx",
+                |_, state| prop_ord_idx_unique(state),
+            )
+            .property(
+                Expectation::Always,
+                "converged nodes have event logs that are prefixes of one another",
+                |model, state| prop_event_logs_are_prefixes(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "committed txs hold all of their inputs",
+                |_, state| prop_committed_txs_hold_all_inputs(state),
+            )
+            .property(
+                Expectation::Always,
+                "no dbc is committed to a tx lacking its owner's signature",
+                |_, state| prop_committed_txs_are_authorized(state),
+            )
+            .property(
+                Expectation::Always,
+                "outputs are only spendable with a quorum receipt",
+                |_, state| prop_spendable_outputs_have_receipts(state),
+            )
+            .property(
+                Expectation::Always,
+                "neither tx of a detected double spend ever commits",
+                |model, state| prop_double_spends_never_commit(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "no committed tx holds a malformed commitment",
+                |_, state| prop_committed_commitments_well_formed(state),
+            )
+            .property(
+                Expectation::Always,
+                "txs commit under one elder quorum or are dropped",
+                |model, state| prop_txs_committed_whole_or_dropped(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "no elder commits a child tx before its parent",
+                |_, state| prop_parents_commit_before_children(state),
+            )
+            .property(
+                Expectation::Always,
+                "elders never agree a dbc committed by a majority of them is unspent",
+                |_, state| prop_no_unspent_verdict_for_committed_dbcs(state),
+            )
+            .property(
+                Expectation::Always,
+                "every amount is representable in denominations",
+                |model, state| prop_amounts_representable_in_denominations(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "current elders hold identical spentbooks once anti-entropy settles",
+                |model, state| prop_elder_ledgers_converge(&model.cfg, state),
+            )
+            .property(
+                Expectation::Eventually,
+                "elders agree on the spent state once the network quiesces",
+                |_, state| prop_quiesced_elders_agree_on_spent_state(state),
+            )
+            .property(
+                Expectation::Eventually,
+                "nothing is left pending once the network quiesces",
+                |_, state| prop_quiesced_nodes_have_no_pending(state),
+            )
+            .property(
+                Expectation::Always,
+                "no tx committed under old elders is missing under the new ones once settled",
+                |model, state| prop_no_tx_lost_in_handover(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "lagging nodes have caught up on the SAP chain once the run ends",
+                |model, state| prop_lagging_sap_chains_catch_up(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "honest nodes only sign in elders an honest node decided on",
+                |model, state| prop_honest_saps_sign_in_decided_elders(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "undecided elders are only signed in by a Byzantine majority",
+                |model, state| prop_undecided_saps_need_byzantine_majority(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "honest nodes are never removed on fabricated evidence",
+                |model, state| prop_no_removal_on_fabricated_evidence(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "no double spend commits while the Byzantine elders are within the quorum margin",
+                |model, state| prop_no_double_spends_within_margin(&model.cfg, state),
+            )
+            .property(
+                Expectation::Always,
+                "honest members stay while the Byzantine elders are within the quorum margin",
+                |model, state| prop_honest_members_kept_within_margin(&model.cfg, state),
+            )
+            .property(
+                Expectation::Sometimes,
+                "a non-genesis node becomes an elder",
+                |_, state| prop_non_genesis_elder_promoted(state),
+            )
+            .property(
+                Expectation::Sometimes,
+                "two conflicting Txs are pending at once",
+                |_, state| prop_conflicting_txs_pending(state),
+            )
+    }
+}
@@ -1,329 +1,467 @@
-mod fake_crypto;
-mod ledger;
-mod membership;
-mod stable_set;
-
 use std::{
-    borrow::Cow,
     collections::{BTreeMap, BTreeSet},
-    fmt::Debug,
+    net::{Ipv4Addr, SocketAddrV4},
+    sync::Arc,
 };
 
-use fake_crypto::majority;
-use ledger::{genesis_dbc, Tx, Wallet};
-use membership::Membership;
-use stable_set::StableSet;
+use stable_set::{
+    bandwidth::Traffic,
+    codec, differential,
+    event_log::{Event, InProcess},
+    fake_crypto::{Quorum, QuorumPolicy},
+    membership::Weighting,
+    metrics::Metrics,
+    progress,
+    simulation::SimulationChooser,
+    soak,
+    trace::{self, Trace},
+    ModelCfg,
+};
 use stateright::{
-    actor::{model_peers, Actor, ActorModel, ActorModelState, Id, Network, Out},
-    Expectation, Model,
+    actor::{spawn, Id},
+    Checker, Model, UniformChooser,
 };
 
-const ELDER_COUNT: usize = 4;
-
-pub fn build_msg(membership: &Membership, action: impl Into<Action>) -> Msg {
-    let mut stable_set = membership.stable_set.clone();
-
-    for (_, witnesses) in stable_set.joining_members.iter_mut() {
-        witnesses.clear()
-    }
-
-    for (_, witnesses) in stable_set.leaving_members.iter_mut() {
-        witnesses.clear()
-    }
-
-    Msg {
-        stable_set,
-        action: action.into(),
-    }
-}
-
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-pub struct State {
-    pub membership: Membership,
-    is_leaving: bool,
-    pub wallet: Wallet,
-}
-
-impl State {
-    fn elders(&self) -> BTreeSet<Id> {
-        self.membership.elders()
-    }
-
-    fn build_msg(&self, action: Action) -> Msg {
-        build_msg(&self.membership, action)
-    }
-}
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
-#[derive(Clone)]
-pub struct Node {
-    pub genesis_nodes: BTreeSet<Id>,
-    pub peers: Vec<Id>,
-}
+    let cfg = ModelCfg::default();
 
-#[derive(Clone, Eq, Hash, PartialEq)]
-pub struct Msg {
-    stable_set: StableSet,
-    action: Action,
-}
+    let args = Vec::from_iter(std::env::args().skip(1));
 
-impl Debug for Msg {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Msg({:?}, {:?})", self.stable_set, self.action)
-    }
-}
+    match args.first().map(String::as_str) {
+        Some("simulate") => {
+            let seed = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+            let byte_budget_per_step = args.get(2).and_then(|s| s.parse().ok());
 
-#[derive(Clone, Eq, Hash, PartialEq)]
-pub enum Action {
-    Membership(membership::Msg),
-    Wallet(ledger::Msg),
-    Sync,
-    StartReissue,
-    TriggerLeave,
-}
+            let rates = cfg.flaky_joiner_links();
+            let chooser = SimulationChooser {
+                drop_rates: rates.clone(),
+                byte_budget_per_step,
+            };
 
-impl Debug for Action {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Membership(m) => write!(f, "{m:?}"),
-            Self::Wallet(m) => write!(f, "{m:?}"),
-            Self::Sync => write!(f, "Sync"),
-            Self::StartReissue => write!(f, "StartReissue"),
-            Self::TriggerLeave => write!(f, "TriggerLeave"),
+            let checker = ModelCfg {
+                link_drop_rates: Some(rates),
+                resend_rounds: Some(3),
+                ..cfg
+            }
+            .into_model()
+            .checker()
+            .threads(num_cpus::get())
+            .spawn_simulation(seed, chooser);
+            progress::join(checker).report(&mut std::io::stdout());
         }
-    }
-}
-
-impl From<membership::Msg> for Action {
-    fn from(msg: membership::Msg) -> Self {
-        Self::Membership(msg)
-    }
-}
-
-impl From<ledger::Msg> for Action {
-    fn from(msg: ledger::Msg) -> Self {
-        Self::Wallet(msg)
-    }
-}
-
-impl Actor for Node {
-    type Msg = Msg;
-    type State = State;
-
-    fn on_start(&self, id: Id, o: &mut Out<Self>) -> Self::State {
-        let membership = Membership::new(&self.genesis_nodes);
-        let wallet = Wallet::new(&self.genesis_nodes);
-
-        let state = State {
-            membership,
-            wallet,
-            is_leaving: false,
-        };
-
-        if !self.genesis_nodes.contains(&id) {
-            o.broadcast(&self.genesis_nodes, &state.membership.req_join(id));
+        Some("soak") => {
+            let minutes = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(60);
+            let seed = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
+
+            let rates = cfg.flaky_joiner_links();
+            let chooser = SimulationChooser {
+                drop_rates: rates.clone(),
+                byte_budget_per_step: None,
+            };
+            let model = ModelCfg {
+                link_drop_rates: Some(rates),
+                ..cfg
+            }
+            .into_model();
+
+            soak::soak(
+                &model,
+                &chooser,
+                std::time::Duration::from_secs(minutes * 60),
+                seed,
+            );
         }
-
-        // if id > Id::from(self.peers.len().saturating_sub(2)) {
-        // First two nodes will try to spend the genesis
-        o.send(id, state.build_msg(Action::StartReissue));
-        // }
-
-        state
-    }
-
-    fn on_msg(
-        &self,
-        id: Id,
-        state: &mut Cow<Self::State>,
-        src: Id,
-        msg: Self::Msg,
-        o: &mut Out<Self>,
-    ) {
-        let elders = state.elders();
-        let Msg { stable_set, action } = msg;
-
-        let mut nodes_to_sync = state.to_mut().membership.merge(stable_set, id, src);
-
-        match action {
-            Action::Sync => (),
-            Action::Membership(msg) => {
-                nodes_to_sync.extend(state.to_mut().membership.on_msg(&elders, id, src, msg));
+        Some("leave-modes") => {
+            // Checks the same bounded model with leavers witnessing until they're gone, then with
+            // leavers going read-only at intent, to compare which properties each mode breaks.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for read_only_on_leave in [false, true] {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        read_only_on_leave,
+                        ..cfg.clone()
+                    },
+                    depth,
+                );
+                println!(
+                    "read_only_on_leave: {read_only_on_leave}, {states} states, discoveries: {discoveries:#?}"
+                );
             }
-            Action::Wallet(msg) => {
-                let membership = state.membership.clone();
-                state.to_mut().wallet.on_msg(&membership, id, src, msg, o)
+        }
+        Some("churn-modes") => {
+            // Checks the same bounded model with joins and leaves witnessed concurrently, then one
+            // at a time, to compare state space size and which convergence properties hold.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for serialize_churn in [false, true] {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        serialize_churn,
+                        ..cfg.clone()
+                    },
+                    depth,
+                );
+                println!(
+                    "serialize_churn: {serialize_churn}, {states} states, discoveries: {discoveries:#?}"
+                );
             }
-            Action::StartReissue => {
-                let input = genesis_dbc().clone();
-
-                let reissue_amount =
-                    (0..self.peers.len()).find(|x| Id::from(*x) == id).unwrap() as u64;
-                let difference = input.amount() - reissue_amount;
-
-                let membership = state.membership.clone();
-                state.to_mut().wallet.reissue(
-                    &membership,
-                    vec![input],
-                    vec![reissue_amount, difference],
-                    o,
+        }
+        Some("handover-reissue") => {
+            // Two genesis elders reissue while a third node joins and becomes an elder, so Txs are
+            // still pending when the elders change. Compares waiting on the old shares with handing
+            // the pending Txs over to the new elders.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for hand_over_pending in [false, true] {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        elder_count: 2,
+                        server_count: 3,
+                        hand_over_pending,
+                        ..cfg.clone()
+                    },
+                    depth,
+                );
+                println!(
+                    "hand_over_pending: {hand_over_pending}, {states} states, discoveries: {discoveries:#?}"
+                );
+            }
+        }
+        Some("quorum") => {
+            // The same model with crash-fault thresholds everywhere, BFT thresholds everywhere, and
+            // crash-fault membership under a BFT ledger and handover, to see which properties rely
+            // on two thirds where.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for quorum in [
+                QuorumPolicy::uniform(Quorum::SimpleMajority),
+                QuorumPolicy::uniform(Quorum::TwoThirds),
+                QuorumPolicy {
+                    joins: Quorum::SimpleMajority,
+                    leaves: Quorum::SimpleMajority,
+                    ..QuorumPolicy::uniform(Quorum::TwoThirds)
+                },
+            ] {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        quorum,
+                        ..cfg.clone()
+                    },
+                    depth,
+                );
+                println!("quorum: {quorum:?}, {states} states, discoveries: {discoveries:#?}");
+            }
+        }
+        Some("forgery") => {
+            // Grows the colluding genesis elders one at a time. Below a majority their forged
+            // handover shares never verify and their made up evidence never removes an honest
+            // elder, from a majority on they sign in elders of their own.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for f in 0..=cfg.elder_count {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        byzantine: BTreeSet::from_iter((0..f).map(Id::from)),
+                        report_misbehavior: true,
+                        ..cfg.clone()
+                    },
+                    depth,
                 );
+                println!("byzantine: {f}, {states} states, discoveries: {discoveries:#?}");
             }
-            Action::TriggerLeave => {
-                o.broadcast(&elders, &state.to_mut().membership.req_leave(id).into());
+        }
+        Some("byzantine") => {
+            // Four genesis elders with a growing number of them Byzantine, witnessing both sides of
+            // every double spend and voting the honest elders out. One is within the two thirds
+            // margin of four elders, from two on double spends and removals may get through.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for f in 0..=2 {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        elder_count: 4,
+                        server_count: 4,
+                        byzantine: BTreeSet::from_iter((0..f).map(Id::from)),
+                        equivocate: true,
+                        ..cfg.clone()
+                    },
+                    depth,
+                );
+                println!("byzantine: {f}, {states} states, discoveries: {discoveries:#?}");
+            }
+        }
+        Some("weighted") => {
+            // Membership decisions under two genesis elders with every elder counting once, by age,
+            // where the genesis elders outweigh the node that joins them as an elder, and with the
+            // first genesis elder holding enough stake to decide alone.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for weighting in [
+                Weighting::Equal,
+                Weighting::Age,
+                Weighting::Stake(BTreeMap::from_iter([(Id::from(0), 3)])),
+            ] {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        elder_count: 2,
+                        server_count: 3,
+                        weighting: weighting.clone(),
+                        ..cfg.clone()
+                    },
+                    depth,
+                );
+                println!(
+                    "weighting: {weighting:?}, {states} states, discoveries: {discoveries:#?}"
+                );
+            }
+        }
+        Some("handover-dispute") => {
+            // Two nodes join two genesis elders concurrently, so elders can see different
+            // candidates for the same generation while the joins spread. At most one SAP per
+            // generation may gather a majority.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+            let (states, discoveries) = check_bounded(
+                ModelCfg {
+                    elder_count: 2,
+                    server_count: 4,
+                    ..cfg.clone()
+                },
+                depth,
+            );
+            println!("{states} states, discoveries: {discoveries:#?}");
+        }
+        Some("split") => {
+            // Two nodes join two genesis elders, growing the section past a split threshold of three.
+            // The elders sign in the elders of each half by the lowest bit of the ids, every member
+            // should end up in exactly one half and no dbc spent by different Txs across them.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+            let (states, discoveries) = check_bounded(
+                ModelCfg {
+                    elder_count: 2,
+                    server_count: 4,
+                    split_threshold: Some(3),
+                    ..cfg.clone()
+                },
+                depth,
+            );
+            println!("{states} states, discoveries: {discoveries:#?}");
+        }
+        Some("dkg") => {
+            // Two genesis elders hand over to a set including the joining third node, the new elders
+            // generate their key first. The joiner holds back its contribution until its timer
+            // fires, DKG should still complete and every SAP carry a complete key set.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+
+            for slow_dkg_participant in [None, Some(Id::from(2))] {
+                let (states, discoveries) = check_bounded(
+                    ModelCfg {
+                        elder_count: 2,
+                        server_count: 3,
+                        dkg: true,
+                        slow_dkg_participant,
+                        ..cfg.clone()
+                    },
+                    depth,
+                );
+                println!(
+                    "slow_dkg_participant: {slow_dkg_participant:?}, {states} states, discoveries: {discoveries:#?}"
+                );
+            }
+        }
+        Some("batch-reissues") => {
+            // Two genesis elders spend their genesis share and then chain spends off the outputs,
+            // each commit leaving two outputs to spend at once. Runs the nodes in-process sending
+            // one Tx per reissue request, then batching them, batching should take fewer requests
+            // for the same Txs.
+            let spend_chain_depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(2);
+
+            let [unbatched, batched] = [false, true].map(|batch_reissues| {
+                in_process_traffic(ModelCfg {
+                    elder_count: 2,
+                    server_count: 2,
+                    spend_chain_depth,
+                    batch_reissues,
+                    ..cfg.clone()
+                })
+            });
+            let requests = |traffic: &Traffic| {
+                ["req_reissue", "req_reissue_batch"]
+                    .iter()
+                    .map(|kind| traffic.msgs_by_kind.get(kind).copied().unwrap_or(0))
+                    .sum::<usize>()
+            };
+            for (name, traffic) in [("unbatched", &unbatched), ("batched", &batched)] {
+                println!(
+                    "{name}: {} reissue requests for {} Txs, {} msgs in all",
+                    requests(traffic),
+                    traffic.reissued_txs,
+                    traffic.msgs
+                );
             }
+            assert_eq!(batched.reissued_txs, unbatched.reissued_txs);
+            assert!(
+                requests(&batched) < requests(&unbatched),
+                "batching saved no reissue requests"
+            );
         }
-        if id > Id::from((self.peers.len() * 2) / 3)
-            && state.membership.is_member(id)
-            && !state.is_leaving
-        {
-            state.to_mut().is_leaving = true;
-            o.send(id, state.build_msg(Action::TriggerLeave));
+        Some("compare") => {
+            // Checks the default scenario under two protocol variants to the same depth and lists
+            // side by side which properties each satisfies, marking where they differ.
+            let usage = format!(
+                "usage: compare <variant> <variant> [depth], variants: {:?}",
+                differential::VARIANTS
+            );
+            let [a, b] = [1, 2].map(|i| args.get(i).map(String::as_str).expect(&usage));
+            let depth = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(20);
+            let variant = |name| differential::variant(name).unwrap_or_else(|| panic!("{usage}"));
+
+            let comparison = differential::compare([(a, variant(a)), (b, variant(b))], depth);
+            print!("{comparison}");
         }
+        Some("trace") => {
+            // Writes every discovery of a bounded check of the default model to a file `replay`
+            // takes.
+            let depth = args.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+            let checker = progress::join(
+                cfg.into_model()
+                    .checker()
+                    .threads(num_cpus::get())
+                    .target_max_depth(depth)
+                    .spawn_bfs(),
+            );
+
+            for (property, path) in checker.discoveries() {
+                let trace = Trace::new(property, path);
+                match trace.write(trace.file_name()) {
+                    Ok(()) => println!("{property:?}: wrote {}", trace.file_name()),
+                    Err(err) => log::error!("failed to write {}: {err}", trace.file_name()),
+                }
+            }
+        }
+        Some("replay") => {
+            let path = args.get(1).expect("usage: replay <path>");
+            let trace = Trace::read(path).expect("Failed to read trace");
+            trace::replay(&cfg.into_model(), &trace);
+        }
+        Some("checkpoint") => {
+            let path = args.get(1).expect("usage: checkpoint <path>");
+            let bytes = std::fs::read(path).expect("Failed to read checkpoint");
+            let stable_set = codec::decode(&bytes).expect("Failed to decode checkpoint");
+            println!("{stable_set:#?}");
+        }
+        Some("spawn") => {
+            let base_port = 3100;
+            let peers =
+                Vec::from_iter((0..cfg.server_count).map(|i| {
+                    Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, base_port + i as u16))
+                }));
+
+            let metrics = Arc::new(Metrics::default());
+            metrics
+                .clone()
+                .serve("localhost:9100")
+                .expect("Failed to serve metrics");
+            log::info!("serving metrics on http://localhost:9100/metrics");
+
+            let actors = Vec::from_iter(
+                peers
+                    .iter()
+                    .map(|id| (*id, cfg.node(peers.clone(), Some(metrics.clone())))),
+            );
+
+            let handles = spawn(
+                serde_json::to_vec,
+                |bytes| serde_json::from_slice(bytes),
+                actors,
+            )
+            .expect("Failed to spawn actors");
 
-        nodes_to_sync.extend(state.to_mut().membership.process_pending_actions(id));
-        nodes_to_sync.remove(&id);
+            for handle in handles {
+                handle.join().expect("actor thread panicked");
+            }
+        }
+        _ => {
+            // `--strategy bfs|dfs|simulate` runs the checker headless and prints its report, without
+            // a strategy the explorer is served. `--max-joins`, `--max-leaves` and `--max-reissues`
+            // leave states past that many decided joins, leaves or committed reissues unexplored.
+            let bound = |name| flag(&args, name).and_then(|s| s.parse().ok());
+            let cfg = ModelCfg {
+                max_joins: bound("--max-joins"),
+                max_leaves: bound("--max-leaves"),
+                max_reissues: bound("--max-reissues"),
+                ..cfg
+            };
+            let mut checker = cfg.into_model().checker().threads(
+                flag(&args, "--threads")
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or_else(num_cpus::get),
+            );
+            if let Some(count) = flag(&args, "--target-states").and_then(|s| s.parse().ok()) {
+                checker = checker.target_state_count(count);
+            }
+            if let Some(depth) = flag(&args, "--depth").and_then(|s| s.parse().ok()) {
+                checker = checker.target_max_depth(depth);
+            }
 
-        o.broadcast(&nodes_to_sync, &state.build_msg(Action::Sync))
+            let mut stdout = std::io::stdout();
+            match flag(&args, "--strategy") {
+                None => {
+                    checker.serve("localhost:3000");
+                }
+                Some("bfs") => {
+                    progress::join(checker.spawn_bfs()).report(&mut stdout);
+                }
+                Some("dfs") => {
+                    progress::join(checker.spawn_dfs()).report(&mut stdout);
+                }
+                Some("simulate") => {
+                    let seed = flag(&args, "--seed")
+                        .and_then(|s| s.parse().ok())
+                        .unwrap_or(0);
+                    progress::join(checker.spawn_simulation(seed, UniformChooser))
+                        .report(&mut stdout);
+                }
+                Some(other) => panic!("unknown strategy {other:?}, expected bfs, dfs or simulate"),
+            }
+        }
     }
 }
 
-#[derive(Clone)]
-struct ModelCfg {
-    elder_count: usize,
-    server_count: usize,
-    network: Network<<Node as Actor>::Msg>,
+// The value following `name` on the command line, if any.
+fn flag<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == name)?;
+    args.get(i + 1).map(String::as_str)
 }
 
-fn reference_stable_set(state: &ActorModelState<Node, Vec<Msg>>) -> StableSet {
-    state
-        .actor_states
-        .iter()
-        .filter(|s| !s.is_leaving)
-        .next()
-        .map(|s| s.membership.stable_set.clone())
-        .unwrap_or_default()
-}
-
-fn prop_stable_set_converged(state: &ActorModelState<Node, Vec<Msg>>) -> bool {
-    let reference_members = reference_stable_set(state).members();
+// Drives the nodes in-process until all is quiet, tallying every message they sent.
+fn in_process_traffic(cfg: ModelCfg) -> Traffic {
+    let peers = Vec::from_iter((0..cfg.server_count).map(Id::from));
+    let mut nodes = InProcess::start(peers.iter().map(|id| (*id, cfg.node(peers.clone(), None))));
+    while nodes.step().is_some() {}
 
-    state
-        .actor_states
+    nodes
+        .events
         .iter()
-        .filter(|s| !s.is_leaving)
-        .all(|actor| actor.membership.stable_set.members() == reference_members)
+        .fold(Traffic::default(), |traffic, event| match event {
+            Event::Sent { msg, .. } => traffic.record(msg),
+            _ => traffic,
+        })
 }
 
-fn prop_all_nodes_joined_who_havent_left(state: &ActorModelState<Node, Vec<Msg>>) -> bool {
-    let reference_stable_set = reference_stable_set(state);
-    state
-        .actor_states
-        .iter()
-        .enumerate()
-        .filter(|(_, actor)| !actor.is_leaving)
-        .all(|(id, actor)| reference_stable_set.contains(id.into()))
-}
-
-fn prop_all_nodes_who_are_leaving_eventually_left(state: &ActorModelState<Node, Vec<Msg>>) -> bool {
-    let reference_stable_set = reference_stable_set(state);
-
-    state
-        .actor_states
-        .iter()
-        .enumerate()
-        .filter(|(_, actor)| actor.is_leaving)
-        .all(|(id, _)| !reference_stable_set.contains(id.into()))
-}
-
-#[allow(unused)]
-fn prop_unspent_outputs_equals_genesis_amount(state: &ActorModelState<Node, Vec<Msg>>) -> bool {
-    state
-        .actor_states
-        .iter()
-        .all(|actor| genesis_dbc().amount() == actor.wallet.ledger.sum_unspent_outputs())
-}
-
-fn prop_no_double_spends(state: &ActorModelState<Node, Vec<Msg>>) -> bool {
-    let actor_by_id = BTreeMap::from_iter(
-        state
-            .actor_states
-            .iter()
-            .enumerate()
-            .map(|(id, s)| (Id::from(id), s)),
+// Runs a bounded BFS, returning the number of unique states and the sorted discoveries.
+fn check_bounded(cfg: ModelCfg, depth: usize) -> (usize, Vec<&'static str>) {
+    let checker = progress::join(
+        cfg.into_model()
+            .checker()
+            .threads(num_cpus::get())
+            .target_max_depth(depth)
+            .spawn_bfs(),
     );
 
-    let concurrent_txs = BTreeSet::from_iter(state.actor_states.iter().flat_map(|a| {
-        let mut transactions: BTreeMap<Tx, usize> = Default::default();
-
-        let elders = a.membership.elders();
-
-        for elder in &elders {
-            if let Some(tx) = actor_by_id
-                .get(elder)
-                .unwrap()
-                .wallet
-                .read_tx(&genesis_dbc().id())
-            {
-                let tx_count = transactions.entry(tx).or_default();
-                *tx_count += 1;
-            }
-        }
-
-        transactions
-            .into_iter()
-            .filter(move |(_, count)| majority(*count, elders.len()))
-            .map(|(tx, _)| tx)
-    }));
-
-    concurrent_txs.len() <= 1
-}
-
-impl ModelCfg {
-    fn into_model(self) -> ActorModel<Node, Self, Vec<Msg>> {
-        ActorModel::new(self.clone(), vec![])
-            .actors((0..self.server_count).map(|i| Node {
-                genesis_nodes: BTreeSet::from_iter((0..self.elder_count).into_iter().map(Id::from)),
-                peers: (0..self.server_count).map(Id::from).collect(),
-            }))
-            .init_network(self.network)
-            .property(
-                Expectation::Eventually,
-                "everyone who hasn't left converges on the same stable set",
-                |_, state| prop_stable_set_converged(state),
-            )
-            .property(
-                Expectation::Eventually,
-                "everyone who hasn't left is part of the final stable set",
-                |_, state| prop_all_nodes_joined_who_havent_left(state),
-            )
-            .property(
-                Expectation::Eventually,
-                "everyone who started leaving, will leave",
-                |_, state| prop_all_nodes_who_are_leaving_eventually_left(state),
-            )
-            .property(Expectation::Always, "Ledger balances", |_, state| {
-                prop_unspent_outputs_equals_genesis_amount(state)
-            })
-            .property(
-                Expectation::Always,
-                "Never two nodes aggregate a double spend",
-                |_, state| prop_no_double_spends(state),
-            )
-    }
-}
-
-fn main() {
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
-
-    let network = Network::new_unordered_nonduplicating([]);
-
-    ModelCfg {
-        elder_count: 1,
-        server_count: 5,
-        network,
-    }
-    .into_model()
-    .checker()
-    .threads(num_cpus::get())
-    .serve("localhost:3000");
+    let mut discoveries = Vec::from_iter(checker.discoveries().into_keys());
+    discoveries.sort();
+    (checker.unique_state_count(), discoveries)
 }
@@ -1,13 +1,43 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::fmt::Debug;
 
-use stateright::actor::Id;
+use stateright::actor::{Id, Out};
 
-use crate::stable_set::{Member, StableSet};
-use crate::{build_msg, ELDER_COUNT};
+use crate::fake_crypto::{hash, total_weight, Evidence, PublicKey, QuorumPolicy, SectionSig};
+use crate::section::{id_name, Prefix};
+use crate::stable_set::{Blacklisting, Member, StableSet};
+use crate::{build_msg, Rejected, ELDER_COUNT};
 
 pub type Elders = BTreeSet<Id>;
 
+// How much each elder's share counts towards deciding a join, leave or removal.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub enum Weighting {
+    // Every elder counts once.
+    #[default]
+    Equal,
+    // An elder counts its age plus one, members age with every generation they stay.
+    Age,
+    // Fixed weights, elders missing here count once.
+    Stake(BTreeMap<Id, u64>),
+}
+
+// HACK: stands in for proving storage and bandwidth, any node can answer it.
+pub fn solve_challenge(nonce: u64) -> u64 {
+    hash(&nonce)
+}
+
 #[derive(
     Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
 )]
@@ -15,36 +45,320 @@ pub enum Msg {
     ReqJoin(Id),
     ReqLeave(Id),
     JoinShare(Member),
+    ProposeRemove(Member, Reason),
+    // Elders make candidates prove they have the resources to be a member before voting them in.
+    Challenge(u64),
+    ChallengeResponse(u64),
+    // Hash of our view of the members and the generation it was taken at. Peers whose view hashes
+    // differently answer with their full stable set.
+    MembershipDigest(u64, u64),
+    // An elder caught the member sending a forged share and votes to remove it.
+    Misbehavior(Id, Evidence),
+}
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub enum Reason {
+    Unresponsive,
+}
+
+// Members decided to have joined or left, used to sync peers without resending the whole set.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct MembershipDelta {
+    pub joined: BTreeSet<Member>,
+    pub left: BTreeSet<Member>,
+}
+
+impl MembershipDelta {
+    pub fn is_empty(&self) -> bool {
+        self.joined.is_empty() && self.left.is_empty()
+    }
+
+    pub fn extend(&mut self, other: MembershipDelta) {
+        self.joined.extend(other.joined);
+        self.left.extend(other.left);
+    }
+}
+
+// What a message carries of the sender's stable set.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum SyncPayload {
+    // The whole stable set, or with a generation only the changes on top of it.
+    StableSet(Box<StableSet>, Option<u64>),
+    // Just the stable set generation and the digest of the membership, peers that are behind or hold
+    // something else at the same generation ask for the full stable set.
+    Digest(u64, u64),
+}
+
+// An entry in a node's audit log, `gen` is the stable set generation the decision took it to. Joins
+// carry the elder signature that decided them, leaves the ids that witnessed them.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum MembershipEvent {
+    Joined {
+        gen: u64,
+        member: Member,
+        proof: Option<SectionSig<Member>>,
+    },
+    Left {
+        gen: u64,
+        member: Member,
+        witnesses: BTreeSet<Id>,
+    },
+}
+
+impl MembershipEvent {
+    // Nodes collect different shares and witnesses for the same decision, only what was decided
+    // and at which generation has to match.
+    pub fn same_decision(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Joined { gen, member, .. },
+                Self::Joined {
+                    gen: g, member: m, ..
+                },
+            )
+            | (
+                Self::Left { gen, member, .. },
+                Self::Left {
+                    gen: g, member: m, ..
+                },
+            ) => gen == g && member == m,
+            _ => false,
+        }
+    }
+
+    pub fn gen(&self) -> u64 {
+        match self {
+            Self::Joined { gen, .. } | Self::Left { gen, .. } => *gen,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Membership {
     pub stable_set: StableSet,
+    // Elders of the decided members, refreshed every time the members change.
+    elders: Elders,
+    pub identity_keys: BTreeMap<Id, PublicKey>,
+    pub history: BTreeMap<u64, MembershipDelta>,
+    // Members this node has voted to forcibly remove.
+    pub proposed_removals: BTreeSet<Member>,
+    // The part of the network this node's section is responsible for.
+    pub prefix: Prefix,
+    // Sections split in two once they grow past this many members.
+    pub split_threshold: Option<usize>,
+    // Joins are queued rather than started once members and pending joins reach this many. Only the
+    // ord_idx leader admits candidates then, in the order it queued them, the other elders queue
+    // them too in case the leader changes and back the leader's proposals.
+    pub max_members: Option<usize>,
+    pub join_queue: VecDeque<Id>,
+    // Challenge candidates before voting for them, tracking the nonce each candidate must answer.
+    pub resource_proof: bool,
+    // Along with the timer ticks each challenge went unanswered, they expire like pending joins.
+    pub challenges: BTreeMap<Id, (u64, u64)>,
+    // Set once we intend to leave, we keep learning decisions but no longer witness joins or leaves.
+    pub read_only: bool,
+    // The highest generation a peer was at and its members, adopted once we decide the same members.
+    pub gen_hint: Option<(u64, BTreeSet<Member>)>,
+    // Pending joins and leaves are abandoned after this many timer ticks, tracked locally per entry.
+    pub pending_ttl: Option<u64>,
+    pub pending_age: BTreeMap<Member, u64>,
+    // Joins and leaves we abandoned, peers still holding them pending can't bring them back. Only a
+    // decision or a fresh request from the member itself does.
+    pub abandoned: BTreeSet<Member>,
+    // Only witness one join or leave at a time, candidates wait in the join queue meanwhile.
+    pub serialize_churn: bool,
+    // Messages carry the latest decisions rather than the whole stable set, along with the stable set
+    // generation they apply on top of.
+    pub delta_sync: bool,
+    pub last_change: (u64, MembershipDelta),
+    // Messages carry a digest of the membership rather than any stable set.
+    pub hash_only_sync: bool,
+    // Elders sign a snapshot every this many stable set generations.
+    pub snapshot_period: Option<u64>,
+    // Elders agree on each candidate's ord_idx: the leader elder proposes it by signing the join and
+    // the other elders ack by adding their share, unless a different candidate holds that ord_idx.
+    pub agreed_ord_idx: bool,
+    // The share of the elders it takes to decide a join, and a leave or removal.
+    pub quorum: QuorumPolicy,
+    pub weighting: Weighting,
+    // Every join and leave this node decided, in the order it decided them. Appended to, and
+    // compacted up to each signed snapshot.
+    pub events: Vec<MembershipEvent>,
+    // Set once we're a member at the same generation as everyone else, from then on we decide
+    // changes as they happen. Events up to it were decided in one go when we joined.
+    pub first_hand_gen: Option<u64>,
+    // Members we saw evidence of misbehavior against, first hand or from another elder.
+    pub accused: BTreeMap<Member, Evidence>,
 }
 
 impl Membership {
-    pub fn new(genesis: &BTreeSet<Id>) -> Self {
+    pub fn new(genesis: &BTreeSet<Id>, identity_keys: BTreeMap<Id, PublicKey>) -> Self {
         let mut stable_set = StableSet::default();
 
         for genesis_id in genesis.iter().copied() {
             let genesis_member = Member {
                 id: genesis_id,
                 ord_idx: 0,
+                gen: 0,
+                age: 0,
             };
             for other_genesis_id in genesis.iter().copied() {
-                stable_set.add(genesis_member.clone(), other_genesis_id);
+                stable_set.sign_join(genesis_member.clone(), other_genesis_id, genesis);
             }
         }
 
-        stable_set.process_ready_actions(genesis);
+        stable_set.process_ready_actions(genesis, QuorumPolicy::default(), &BTreeMap::new());
 
         assert_eq!(&BTreeSet::from_iter(stable_set.ids()), genesis);
 
-        Self { stable_set }
+        let events =
+            Vec::from_iter(
+                stable_set
+                    .members()
+                    .iter()
+                    .map(|member| MembershipEvent::Joined {
+                        gen: stable_set.gen(),
+                        member: member.clone(),
+                        proof: stable_set.proofs.get(member).cloned(),
+                    }),
+            );
+
+        Self {
+            elders: stable_set.elders(ELDER_COUNT),
+            stable_set,
+            identity_keys,
+            history: Default::default(),
+            proposed_removals: Default::default(),
+            prefix: Prefix::default(),
+            split_threshold: None,
+            max_members: None,
+            join_queue: Default::default(),
+            resource_proof: false,
+            challenges: Default::default(),
+            read_only: false,
+            gen_hint: None,
+            pending_ttl: None,
+            pending_age: Default::default(),
+            abandoned: Default::default(),
+            serialize_churn: false,
+            delta_sync: false,
+            last_change: Default::default(),
+            hash_only_sync: false,
+            snapshot_period: None,
+            agreed_ord_idx: false,
+            quorum: QuorumPolicy::default(),
+            weighting: Weighting::Equal,
+            events,
+            first_hand_gen: None,
+            accused: Default::default(),
+        }
+    }
+
+    pub fn delta_since(&self, gen: u64) -> MembershipDelta {
+        let mut delta = MembershipDelta::default();
+        for (_, changes) in self.history.range(gen + 1..) {
+            delta.extend(changes.clone());
+        }
+        delta
+    }
+
+    // What goes out with every message, the stable set generation a delta applies on top of is
+    // None for a full sync.
+    pub fn sync_payload(&self) -> SyncPayload {
+        if self.hash_only_sync {
+            return SyncPayload::Digest(self.stable_set.gen(), self.digest());
+        }
+        if !self.delta_sync {
+            return SyncPayload::StableSet(Box::new(self.stable_set.clone()), None);
+        }
+
+        let (since, change) = &self.last_change;
+        SyncPayload::StableSet(
+            Box::new(self.stable_set.delta(&change.joined, &change.left)),
+            Some(*since),
+        )
+    }
+
+    // Whether a peer's digest shows it knows something we don't: it's ahead of us, or at our
+    // generation with other joins or leaves pending, or other shares and witnesses for the same
+    // ones. Peers behind us ask us in turn.
+    pub fn wants_full_sync(&self, gen: u64, digest: u64) -> bool {
+        gen >= self.stable_set.gen() && digest != self.digest()
+    }
+
+    pub fn apply_delta(&mut self, id: Id, src: Id, delta: MembershipDelta) -> BTreeSet<Id> {
+        let mut additional_members_to_sync = BTreeSet::new();
+
+        for member in delta.joined.iter().chain(&delta.left) {
+            self.abandoned.remove(member);
+        }
+
+        for member in delta.joined {
+            let m_id = member.id;
+            if self.handle_join_share(id, member, None) {
+                additional_members_to_sync.insert(m_id);
+                additional_members_to_sync.extend(self.elders());
+            }
+        }
+
+        for member in delta.left {
+            let m_id = member.id;
+            if self.handle_leave_share(id, member, src) {
+                additional_members_to_sync.insert(m_id);
+                additional_members_to_sync.extend(self.elders());
+            }
+        }
+
+        additional_members_to_sync
+    }
+
+    pub fn identity_key(&self, id: Id) -> PublicKey {
+        self.identity_keys
+            .get(&id)
+            .copied()
+            .unwrap_or_else(|| PublicKey::from(id))
+    }
+
+    // Elders sign off on evicting the member holding `key`, the entry is gossiped with the stable set.
+    pub fn blacklist(&mut self, id: Id, key: PublicKey) -> BTreeSet<Id> {
+        let elders = self.elders();
+        if !elders.contains(&id) {
+            return Default::default();
+        }
+
+        let to_evict = Vec::from_iter(
+            self.members()
+                .iter()
+                .filter(|m| self.identity_key(m.id) == key)
+                .cloned(),
+        );
+
+        let mut nodes_to_sync = BTreeSet::new();
+        for member in to_evict {
+            let entry = Blacklisting { key, member };
+            if self.stable_set.add_blacklist_share(entry, &elders, id) {
+                nodes_to_sync.extend(elders.iter().copied());
+            }
+        }
+
+        nodes_to_sync
     }
 
     fn build_msg(&self, msg: Msg) -> crate::Msg {
-        build_msg(&self, msg)
+        build_msg(self, msg)
     }
 
     pub fn req_join(&self, id: Id) -> crate::Msg {
@@ -58,90 +372,302 @@ impl Membership {
         self.build_msg(Msg::ReqLeave(id))
     }
 
+    // Elders vote to remove a member that isn't leaving by itself, it goes once a majority agrees.
+    pub fn propose_remove(&mut self, id: Id, member: Member, reason: Reason) -> crate::Msg {
+        self.proposed_removals.insert(member.clone());
+        self.stable_set.add_removal_share(member.clone(), id);
+        self.build_msg(Msg::ProposeRemove(member, reason))
+    }
+
+    // Removing a member caught misbehaving goes the same way, with the evidence for the other
+    // elders to check.
+    pub fn report_misbehavior(&mut self, id: Id, member: Member, evidence: Evidence) -> crate::Msg {
+        self.accused
+            .entry(member.clone())
+            .or_insert_with(|| evidence.clone());
+        self.proposed_removals.insert(member.clone());
+        self.stable_set.add_removal_share(member.clone(), id);
+        self.build_msg(Msg::Misbehavior(member.id, evidence))
+    }
+
+    // Covers the signature shares and witnesses of pending changes as well as the members. Peers
+    // holding the same pending changes still need each other's shares to decide them.
+    pub fn digest(&self) -> u64 {
+        let joining = Vec::from_iter(
+            self.stable_set
+                .joining_members
+                .iter()
+                .map(|(member, sig)| (member, sig.signers())),
+        );
+        hash(&(
+            self.stable_set.members_digest(),
+            joining,
+            &self.stable_set.leaving_members,
+        ))
+    }
+
     pub fn is_member(&self, id: Id) -> bool {
         self.stable_set.contains(id)
     }
 
-    pub fn members(&self) -> BTreeSet<Member> {
+    pub fn members(&self) -> &BTreeSet<Member> {
         self.stable_set.members()
     }
 
     pub fn elders(&self) -> Elders {
-        BTreeSet::from_iter(self.members().into_iter().take(ELDER_COUNT).map(|m| m.id))
+        self.elders.clone()
     }
 
-    pub fn merge(&mut self, stable_set: StableSet, id: Id, src: Id) -> BTreeSet<Id> {
-        let mut additional_members_to_sync = BTreeSet::new();
+    // The weight of each elder's share under our weighting, empty when every share counts once.
+    pub fn elder_weights(&self) -> BTreeMap<Id, u64> {
+        match &self.weighting {
+            Weighting::Equal => BTreeMap::new(),
+            Weighting::Age => BTreeMap::from_iter(
+                self.members()
+                    .iter()
+                    .filter(|m| self.elders.contains(&m.id))
+                    .map(|m| (m.id, self.stable_set.age(m) + 1)),
+            ),
+            Weighting::Stake(weights) => weights.clone(),
+        }
+    }
 
-        for member in stable_set.members() {
-            let m_id = member.id;
+    // Who witnessed each pending leave or forced removal, as the witnesses are gone once decided.
+    fn leave_witnesses(&self) -> BTreeMap<Member, BTreeSet<Id>> {
+        let mut witnesses = self.stable_set.leaving_members.clone();
+        for (member, shares) in self.stable_set.removing_members.iter() {
+            witnesses
+                .entry(member.clone())
+                .or_default()
+                .extend(shares.ids());
+        }
+        witnesses
+    }
 
-            if self.handle_join_share(id, member, src) {
-                additional_members_to_sync.insert(m_id);
-                additional_members_to_sync.extend(self.elders());
+    // Logs the changes in the order `process_ready_actions` decides them, joins then leaves, each at
+    // the generation it took us to.
+    fn log_events(
+        &mut self,
+        gen_before: u64,
+        before: &BTreeSet<Member>,
+        after: &BTreeSet<Member>,
+        mut witnesses: BTreeMap<Member, BTreeSet<Id>>,
+    ) {
+        let mut gen = gen_before;
+        let mut replaced = BTreeSet::new();
+        for member in after.difference(before) {
+            // A join replacing a higher proposal for the same id only corrects its ord_idx.
+            let existing = before
+                .iter()
+                .find(|m| m.id == member.id && !after.contains(m));
+            if existing.is_none_or(|m| m.ord_idx < member.ord_idx) {
+                gen += 1;
+            }
+            if let Some(existing) = existing {
+                replaced.insert(existing);
+                self.events.push(MembershipEvent::Left {
+                    gen,
+                    member: existing.clone(),
+                    witnesses: witnesses.remove(existing).unwrap_or_default(),
+                });
+            }
+            self.events.push(MembershipEvent::Joined {
+                gen,
+                member: member.clone(),
+                proof: self.stable_set.proofs.get(member).cloned(),
+            });
+        }
+        for member in before.difference(after).filter(|m| !replaced.contains(m)) {
+            gen += 1;
+            self.events.push(MembershipEvent::Left {
+                gen,
+                member: member.clone(),
+                witnesses: witnesses.remove(member).unwrap_or_default(),
+            });
+        }
+    }
+
+    fn refresh_elders(&mut self) {
+        self.elders = self.stable_set.elders(ELDER_COUNT);
+    }
+
+    // Merges a peer's stable set, or the delta it sent on top of generation `since`. Returns who to
+    // sync, the elders and everyone whose membership we learned something about.
+    pub fn merge(
+        &mut self,
+        mut stable_set: StableSet,
+        since: Option<u64>,
+        id: Id,
+        src: Id,
+    ) -> BTreeSet<Id> {
+        // A delta leaves out older members, only the full set tells us the peer's members.
+        let hinted_gen = self.gen_hint.as_ref().map(|(gen, _)| *gen).unwrap_or(0);
+        if since.is_none() && stable_set.gen() > self.stable_set.gen().max(hinted_gen) {
+            self.gen_hint = Some((stable_set.gen(), stable_set.members().clone()));
+        }
+
+        // Witnesses are stripped from messages, the peer vouches for the leaves it passes on.
+        for witnesses in stable_set.leaving_members.values_mut() {
+            witnesses.insert(src);
+        }
+
+        let mut learned = stable_set.diff(&self.stable_set);
+        // A delta leaves out older members, only its dead tell us who the peer has removed.
+        if since.is_some() {
+            learned.left.retain(|m| stable_set.is_dead(m));
+        }
+
+        // Joins we hold but haven't signed, say as we only just became an elder, get our share now.
+        learned.joining.extend(stable_set.joining().filter(|m| {
+            let ours = self.stable_set.joining_members.get(m);
+            ours.is_some_and(|sig| !sig.signers().contains(&id))
+        }));
+
+        learned.joining.retain(|m| !self.abandoned.contains(m));
+        learned.leaving.retain(|m| !self.abandoned.contains(m));
+        for member in learned.joined.iter().chain(&learned.left) {
+            self.abandoned.remove(member);
+        }
+
+        let mut updated = BTreeSet::new();
+
+        for member in learned.joined.iter().cloned() {
+            let m_id = member.id;
+            let proof = stable_set.proofs.get(&member).cloned();
+            if self.handle_join_share(id, member, proof) {
+                updated.insert(m_id);
             }
         }
 
-        for member in stable_set.joining() {
+        for member in learned.joining.iter().cloned() {
             let m_id = member.id;
-            if self.handle_join_share(id, member, src) {
-                additional_members_to_sync.insert(m_id);
+            let sig = stable_set.joining_members.get(&member).cloned();
+            if self.handle_join_share(id, member, sig) {
+                updated.insert(m_id);
+            }
+        }
+
+        let mut additional_members_to_sync = BTreeSet::new();
+        let snapshots = stable_set
+            .snapshot
+            .iter()
+            .map(|(snapshot, sig)| (snapshot, sig))
+            .chain(stable_set.pending_snapshots.iter());
+        for (snapshot, sig) in snapshots {
+            if self.stable_set.merge_snapshot(snapshot.clone(), sig) {
                 additional_members_to_sync.extend(self.elders());
             }
         }
 
-        for member in stable_set.leaving() {
+        for entry in learned.blacklisted.iter().cloned() {
+            let m_id = entry.member.id;
+            let sig = &stable_set.blacklist[&entry];
+            if self.stable_set.merge_blacklist(entry, sig, &self.elders()) {
+                updated.insert(m_id);
+            }
+        }
+
+        for member in learned.removing.iter().cloned() {
             let m_id = member.id;
-            if self.handle_leave_share(id, member, src) {
-                additional_members_to_sync.insert(m_id);
-                additional_members_to_sync.extend(self.elders());
+            let shares = &stable_set.removing_members[&member];
+            if self
+                .stable_set
+                .merge_removal(member, shares, &self.elders())
+            {
+                updated.insert(m_id);
             }
         }
 
-        // For each member we know is leaving, check if the other node has already removed it.
-        let to_handle = Vec::from_iter(
-            self.stable_set
-                .leaving()
-                .filter(|m| !stable_set.is_member(m)),
-        );
-        for member in to_handle {
+        // A departure the other node has decided counts as it witnessing the leave, even if we
+        // never heard of the leave.
+        for member in learned.leaving.iter().chain(&learned.left).cloned() {
             let m_id = member.id;
             if self.handle_leave_share(id, member, src) {
-                additional_members_to_sync.insert(m_id);
-                additional_members_to_sync.extend(self.elders());
+                updated.insert(m_id);
             }
         }
 
+        if !updated.is_empty() {
+            additional_members_to_sync.extend(updated);
+            additional_members_to_sync.extend(self.elders());
+        }
+
         additional_members_to_sync
     }
 
-    pub fn on_msg(&mut self, elders: &BTreeSet<Id>, id: Id, src: Id, msg: Msg) -> BTreeSet<Id> {
+    pub fn on_msg(
+        &mut self,
+        elders: &BTreeSet<Id>,
+        id: Id,
+        src: Id,
+        msg: Msg,
+        o: &mut Out<crate::Node>,
+    ) -> Result<BTreeSet<Id>, Rejected> {
         let mut additional_members_to_sync = BTreeSet::new();
         match msg {
             Msg::ReqJoin(candidate_id) => {
-                if self.stable_set.member_by_id(candidate_id).is_none() && elders.contains(&id) {
-                    let latest_ord_idx = self
-                        .stable_set
-                        .members()
-                        .iter()
-                        .map(|m| m.ord_idx)
-                        .max()
-                        .unwrap_or(0);
-                    let ord_idx = latest_ord_idx + 1;
-
-                    let member = Member {
-                        id: candidate_id,
-                        ord_idx,
-                    };
-
-                    if self.handle_join_share(id, member, id) {
-                        additional_members_to_sync.insert(candidate_id);
-                        additional_members_to_sync.extend(elders);
+                if self
+                    .stable_set
+                    .is_blacklisted(self.identity_key(candidate_id))
+                {
+                    return Err(Rejected::Blacklisted);
+                }
+
+                self.abandoned.retain(|m| m.id != candidate_id);
+                if self.stable_set.member_by_id(candidate_id).is_none()
+                    && self.prefix.matches(id_name(candidate_id))
+                    && elders.contains(&id)
+                {
+                    if self.resource_proof {
+                        let gen = self.stable_set.gen();
+                        let (nonce, _) = *self
+                            .challenges
+                            .entry(candidate_id)
+                            .or_insert_with(|| (hash(&(id, candidate_id, gen)), 0));
+                        o.send(candidate_id, self.build_msg(Msg::Challenge(nonce)));
+                    } else {
+                        additional_members_to_sync.extend(self.accept_candidate(
+                            elders,
+                            id,
+                            candidate_id,
+                        ));
                     }
                 }
             }
+            Msg::Challenge(nonce) => {
+                o.send(
+                    src,
+                    self.build_msg(Msg::ChallengeResponse(solve_challenge(nonce))),
+                );
+            }
+            Msg::ChallengeResponse(answer) => {
+                let nonce = match self.challenges.get(&src) {
+                    Some((nonce, _)) => *nonce,
+                    None => return Ok(additional_members_to_sync),
+                };
+                if solve_challenge(nonce) != answer {
+                    return Err(Rejected::BadResourceProof);
+                }
+
+                self.challenges.remove(&src);
+                if self.stable_set.member_by_id(src).is_none() && elders.contains(&id) {
+                    additional_members_to_sync.extend(self.accept_candidate(elders, id, src));
+                }
+            }
+            Msg::MembershipDigest(digest, _gen) => {
+                if digest != self.digest() {
+                    let reply =
+                        self.build_msg(Msg::MembershipDigest(self.digest(), self.stable_set.gen()));
+                    o.send(src, reply);
+                }
+            }
             Msg::ReqLeave(to_remove) => {
                 if let Some(member) = self.stable_set.member_by_id(to_remove) {
+                    if src == to_remove {
+                        self.abandoned.remove(&member);
+                    } else if self.abandoned.contains(&member) {
+                        return Ok(additional_members_to_sync);
+                    }
                     if self.handle_leave_share(id, member, src) {
                         additional_members_to_sync.insert(to_remove);
                         additional_members_to_sync.extend(elders);
@@ -149,35 +675,419 @@ impl Membership {
                 }
             }
             Msg::JoinShare(member) => {
+                if self.stable_set.is_blacklisted(self.identity_key(member.id)) {
+                    return Err(Rejected::Blacklisted);
+                }
+
                 let m_id = member.id;
-                if self.handle_join_share(id, member, src) {
+                if !self.abandoned.contains(&member) && self.handle_join_share(id, member, None) {
                     additional_members_to_sync.insert(m_id);
                     additional_members_to_sync.extend(elders);
                 }
             }
+            Msg::ProposeRemove(member, _reason) => {
+                // Only the proposing elder is a witness, we don't vote just because someone else did.
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+
+                let m_id = member.id;
+                if self.stable_set.add_removal_share(member, src) {
+                    additional_members_to_sync.insert(m_id);
+                    additional_members_to_sync.extend(elders);
+                }
+            }
+            Msg::Misbehavior(accused, evidence) => {
+                // The evidence shows a share was forged but not who sent it, so the reporting elder
+                // stands as the witness the same as for any other removal.
+                if !elders.contains(&src) {
+                    return Err(Rejected::NotAnElder);
+                }
+                if evidence.sender != accused || !evidence.verify() {
+                    return Err(Rejected::BadEvidence);
+                }
+
+                if let Some(member) = self.stable_set.member_by_id(accused) {
+                    self.accused.entry(member.clone()).or_insert(evidence);
+                    if self.stable_set.add_removal_share(member, src) {
+                        additional_members_to_sync.insert(accused);
+                        additional_members_to_sync.extend(elders);
+                    }
+                }
+            }
         }
-        additional_members_to_sync
+        Ok(additional_members_to_sync)
     }
 
     pub fn process_pending_actions(&mut self, id: Id) -> BTreeSet<Id> {
-        let stable_set_changed = self.stable_set.process_ready_actions(&self.elders());
+        if self.first_hand_gen.is_none() && self.gen_hint.is_none() && self.is_member(id) {
+            self.first_hand_gen = Some(self.stable_set.gen());
+        }
+
+        // Blacklisted members are removed through the regular leave flow.
+        let to_evict = Vec::from_iter(
+            self.stable_set
+                .blacklisted()
+                .map(|entry| entry.member.clone())
+                .filter(|m| self.stable_set.is_member(m) && self.may_witness(m)),
+        );
+        for member in to_evict {
+            self.abandoned.remove(&member);
+            self.handle_leave_share(id, member, id);
+        }
 
-        if stable_set_changed && self.elders().contains(&id) {
-            self.stable_set.ids().filter(|e| e != &id).collect()
+        let members_before = self.members().clone();
+        let gen_before = self.stable_set.gen();
+        let witnesses_before = self.leave_witnesses();
+        let weights = self.elder_weights();
+        let mut stable_set_changed =
+            self.stable_set
+                .process_ready_actions(&self.elders(), self.quorum, &weights);
+        let members_after = self.members().clone();
+        if members_before != members_after {
+            self.refresh_elders();
+            self.log_events(
+                gen_before,
+                &members_before,
+                &members_after,
+                witnesses_before,
+            );
+        }
+        let snapshot_gen = self.stable_set.snapshot_gen();
+        self.events.retain(|event| event.gen() > snapshot_gen);
+
+        // With the previous change decided, it's the next one's turn.
+        if let Some(next) = self
+            .churn_turn()
+            .filter(|_| self.serialize_churn && !self.read_only)
+        {
+            stable_set_changed |= if self.stable_set.is_member(&next) {
+                self.handle_leave_share(id, next, id)
+            } else {
+                self.handle_join_share(id, next, None)
+            };
+        }
+
+        if self.delta_sync && self.stable_set.gen() != gen_before {
+            self.last_change = (
+                gen_before,
+                MembershipDelta {
+                    joined: &members_after - &members_before,
+                    left: &members_before - &members_after,
+                },
+            );
+        }
+
+        if let Some((gen, members)) = self.gen_hint.take() {
+            // Nothing we could send as a delta covers the generations we skipped.
+            if self.stable_set.catch_up_gen(gen, &members) && self.delta_sync {
+                self.last_change = (gen, Default::default());
+            }
+            if gen > self.stable_set.gen() {
+                self.gen_hint = Some((gen, members));
+            }
+        }
+
+        if members_before != members_after {
+            self.history.insert(
+                self.stable_set.gen(),
+                MembershipDelta {
+                    joined: &members_after - &members_before,
+                    left: &members_before - &members_after,
+                },
+            );
+        }
+
+        // Snapshots are only taken while nothing is joining or leaving.
+        if let Some(period) = self.snapshot_period {
+            let is_due = self.stable_set.gen() >= self.stable_set.snapshot_gen() + period;
+            if is_due && self.elders().contains(&id) && !self.has_pending() && !self.read_only {
+                stable_set_changed |= self.stable_set.sign_snapshot(id);
+            }
+        }
+
+        let mut nodes_to_sync = if stable_set_changed && self.elders().contains(&id) {
+            BTreeSet::from_iter(self.stable_set.ids().filter(|e| e != &id))
         } else {
             Default::default()
+        };
+
+        // Candidates the leader proposed or that got in meanwhile are no longer waiting.
+        let joining = BTreeSet::from_iter(self.stable_set.joining().map(|m| m.id));
+        self.join_queue
+            .retain(|c| !joining.contains(c) && self.stable_set.member_by_id(*c).is_none());
+
+        // Candidates that were turned away while the section was full get in as space frees up.
+        while self.admits_from_queue(id) && !self.is_full() && !self.is_churning() {
+            let Some(candidate_id) = self.join_queue.pop_front() else {
+                break;
+            };
+
+            if self.admit(id, candidate_id) {
+                nodes_to_sync.insert(candidate_id);
+                nodes_to_sync.extend(self.elders());
+            }
+        }
+
+        nodes_to_sync
+    }
+
+    // Starts voting the candidate in, or queues it if the section is full.
+    fn accept_candidate(
+        &mut self,
+        elders: &BTreeSet<Id>,
+        id: Id,
+        candidate_id: Id,
+    ) -> BTreeSet<Id> {
+        if !self.admits_from_queue(id) || self.is_full() || self.is_churning() {
+            if !self.join_queue.contains(&candidate_id) {
+                self.join_queue.push_back(candidate_id);
+            }
+        } else if self.admit(id, candidate_id) {
+            return BTreeSet::from_iter(elders.iter().copied().chain([candidate_id]));
+        }
+
+        Default::default()
+    }
+
+    // Restarts from our latest snapshot, dropping the departed and the history behind it. Only done
+    // right at the snapshot's generation so no decision is lost.
+    pub fn restore_snapshot(&mut self) -> bool {
+        match self.stable_set.restore() {
+            Some(restored) if restored.gen() == self.stable_set.gen() => {
+                self.stable_set = restored;
+                self.refresh_elders();
+                self.history.clear();
+                true
+            }
+            _ => false,
         }
     }
 
-    fn handle_join_share(&mut self, id: Id, member: Member, witness: Id) -> bool {
-        if self.stable_set.is_member(&member) {
+    pub fn has_pending(&self) -> bool {
+        !self.stable_set.joining_members.is_empty() || !self.stable_set.leaving_members.is_empty()
+    }
+
+    // Whether expiry has anything left to collect, pending joins and leaves or unanswered challenges.
+    pub fn has_expiring(&self) -> bool {
+        self.has_pending() || !self.challenges.is_empty()
+    }
+
+    // Ages every pending join and leave by a tick and abandons the ones that outlived the ttl,
+    // unanswered challenges likewise. Returns true if anything is still left to expire.
+    pub fn expire_pending(&mut self) -> bool {
+        let Some(ttl) = self.pending_ttl else {
+            return self.has_expiring();
+        };
+
+        self.challenges.retain(|_, (_, age)| {
+            *age += 1;
+            *age < ttl
+        });
+
+        let pending =
+            BTreeSet::from_iter(self.stable_set.joining().chain(self.stable_set.leaving()));
+        self.pending_age.retain(|m, _| pending.contains(m));
+        self.abandoned.retain(|m| !self.stable_set.is_dead(m));
+
+        for member in pending {
+            let age = self.pending_age.entry(member.clone()).or_default();
+            *age += 1;
+            if *age >= ttl {
+                self.pending_age.remove(&member);
+                self.stable_set.abandon(&member);
+                self.abandoned.insert(member);
+            }
+        }
+
+        self.has_expiring()
+    }
+
+    // Whether serialized churn has to wait for the change in flight before starting another.
+    pub fn is_churning(&self) -> bool {
+        self.serialize_churn && self.has_pending()
+    }
+
+    // The one pending change we witness while churn is serialized, leaves go before joins.
+    fn churn_turn(&self) -> Option<Member> {
+        let next_leave = self.stable_set.leaving().min();
+        next_leave.or_else(|| self.stable_set.joining().min())
+    }
+
+    fn may_witness(&self, member: &Member) -> bool {
+        !self.serialize_churn || self.churn_turn().is_none_or(|next| &next == member)
+    }
+
+    // Members and joins in flight both count towards the section size.
+    pub fn is_full(&self) -> bool {
+        self.max_members.is_some_and(|max| {
+            self.stable_set.members().len() + self.stable_set.joining_members.len() >= max
+        })
+    }
+
+    // Whether we take candidates off our queue, every elder does unless the section has a maximum
+    // size or elders agree on ord_idx, then only the ord_idx leader does. The other elders keep
+    // candidates queued in case they take over.
+    pub fn admits_from_queue(&self, id: Id) -> bool {
+        let leader_only = self.max_members.is_some() || self.agreed_ord_idx;
+        self.elders().contains(&id) && (!leader_only || self.ord_idx_leader() == Some(id))
+    }
+
+    // The elder that proposes ord_idx when elders agree on them. An elder with a leave or removal
+    // pending has gone quiet or is on its way out, the next elder takes over from it.
+    pub fn ord_idx_leader(&self) -> Option<Id> {
+        let elders = self.elders();
+        let departing = BTreeSet::from_iter(
+            self.stable_set
+                .leaving()
+                .chain(self.stable_set.removing_members.keys().cloned())
+                .map(|m| m.id),
+        );
+        let leader = elders.iter().find(|e| !departing.contains(e));
+        leader.or(elders.first()).copied()
+    }
+
+    // Past every member and every pending join.
+    fn next_free_ord_idx(&self) -> u64 {
+        let pending = self.stable_set.joining().map(|m| m.ord_idx);
+        pending.fold(self.stable_set.latest_ord_idx(), u64::max) + 1
+    }
+
+    // A different candidate is a member or being voted in at this ord_idx. Two leaders can propose
+    // rivals for the same ord_idx while elders disagree on who leads, once the rivals are deadlocked
+    // the lowest candidate no longer counts the others as taking its place.
+    fn ord_idx_taken(&self, member: &Member) -> bool {
+        let is_rival = |m: &Member| m.ord_idx == member.ord_idx && m.id != member.id;
+        if self.members().iter().any(is_rival) {
+            return true;
+        }
+
+        let rivals = Vec::from_iter(self.stable_set.joining().filter(is_rival));
+        let wins_tie = rivals.iter().all(|m| member.id < m.id);
+        !rivals.is_empty() && (!wins_tie || !self.ord_idx_deadlocked(member.ord_idx))
+    }
+
+    // Every elder backed one of the proposals at this ord_idx and none got a quorum. Short of this
+    // tie-break an elder backs a single candidate per ord_idx, so none of them ever will.
+    fn ord_idx_deadlocked(&self, ord_idx: u64) -> bool {
+        let elders = self.elders();
+        let weights = self.elder_weights();
+        let total = total_weight(&elders, &weights);
+
+        let mut backers = BTreeSet::new();
+        for (_, sig) in self
+            .stable_set
+            .joining_members
+            .iter()
+            .filter(|(m, _)| m.ord_idx == ord_idx)
+        {
+            let signers = &sig.signers() & &elders;
+            let weight = total_weight(&signers, &weights);
+            if self.quorum.joins.reached(weight, total) {
+                return false;
+            }
+            backers.extend(signers);
+        }
+
+        backers == elders
+    }
+
+    fn admit(&mut self, id: Id, candidate_id: Id) -> bool {
+        if self.read_only {
+            return false;
+        }
+
+        // The other elders wait for the leader's proposal to ack.
+        if self.agreed_ord_idx && self.ord_idx_leader() != Some(id) {
+            return false;
+        }
+
+        let ord_idx = if self.agreed_ord_idx {
+            self.next_free_ord_idx()
+        } else {
+            self.stable_set.latest_ord_idx() + 1
+        };
+
+        // Back a proposal another elder already made rather than competing with it.
+        let pending = self.stable_set.joining().find(|m| m.id == candidate_id);
+        let member = pending.unwrap_or(Member {
+            id: candidate_id,
+            ord_idx,
+            gen: self.stable_set.gen(),
+            age: 0,
+        });
+
+        self.handle_join_share(id, member, None)
+    }
+
+    // The sections the elders hand over to next, ours or, once we've grown past the split threshold,
+    // both halves by the next bit of the ids. Every node computes the same halves from the same
+    // decided members.
+    pub fn next_sections(&self) -> BTreeMap<Prefix, Elders> {
+        let threshold = self.split_threshold.unwrap_or(usize::MAX);
+        let members = self.members();
+        let halves = self.prefix.children().filter(|halves| {
+            // Don't leave either half too small to hold a section of its own.
+            members.len() > threshold
+                && halves.iter().all(|half| {
+                    members
+                        .iter()
+                        .filter(|m| half.matches(id_name(m.id)))
+                        .count()
+                        >= threshold / 2
+                })
+        });
+
+        match halves {
+            Some(halves) => BTreeMap::from_iter(
+                halves.map(|half| (half, self.stable_set.elders_within(half, ELDER_COUNT))),
+            ),
+            None => BTreeMap::from_iter([(self.prefix, self.elders())]),
+        }
+    }
+
+    // The next section `id` falls into, along with its elders.
+    pub fn next_section(&self, id: Id) -> Option<(Prefix, Elders)> {
+        self.next_sections()
+            .into_iter()
+            .find(|(prefix, _)| prefix.matches(id_name(id)))
+    }
+
+    // Goes on as the half of our section the elders signed in, dropping the members of the other.
+    pub fn split(&mut self, prefix: Prefix) {
+        self.prefix = prefix;
+        self.stable_set.retain(|m| prefix.matches(id_name(m.id)));
+        self.refresh_elders();
+    }
+
+    // Merges the shares a peer collected for the join, if any, then adds our own.
+    fn handle_join_share(
+        &mut self,
+        id: Id,
+        member: Member,
+        sig: Option<SectionSig<Member>>,
+    ) -> bool {
+        if !self.prefix.matches(id_name(member.id)) {
+            return false;
+        }
+
+        if self.stable_set.has_seen(&member)
+            || self.stable_set.is_blacklisted(self.identity_key(member.id))
+        {
             return false;
         }
 
-        let first_time_seeing_join = self.stable_set.add(member.clone(), witness);
-        self.stable_set.add(member, id);
+        let elders = self.elders();
+        let mut updated = false;
+        if let Some(sig) = sig {
+            updated |= self.stable_set.merge_join(member.clone(), &sig, &elders);
+        }
+        let acks = !self.agreed_ord_idx || !self.ord_idx_taken(&member);
+        if !self.read_only && self.may_witness(&member) && acks {
+            updated |= self.stable_set.sign_join(member, id, &elders);
+        }
 
-        first_time_seeing_join
+        updated
     }
 
     fn handle_leave_share(&mut self, id: Id, member: Member, witness: Id) -> bool {
@@ -185,8 +1095,11 @@ impl Membership {
             return false;
         }
 
+        // A read-only node still signs off on its own leave.
         let first_time_seeing_leave = self.stable_set.remove(member.clone(), witness);
-        self.stable_set.remove(member, id);
+        if (!self.read_only || member.id == id) && self.may_witness(&member) {
+            self.stable_set.remove(member, id);
+        }
 
         first_time_seeing_leave
     }
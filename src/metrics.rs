@@ -0,0 +1,129 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{Read, Write},
+    net::{TcpListener, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use stateright::actor::Id;
+
+use crate::State;
+
+// A metric's name and how to read it off a node's metrics.
+type Counter = (&'static str, fn(&NodeMetrics) -> u64);
+type Gauge = (&'static str, fn(&NodeMetrics) -> usize);
+
+#[derive(Debug, Default, Clone)]
+pub struct NodeMetrics {
+    pub messages: BTreeMap<&'static str, u64>,
+    pub decisions: u64,
+    pub rejections: u64,
+    pub members: usize,
+    pub pending_joins: usize,
+    pub pending_txs: usize,
+    pub gen: usize,
+    pub health_gen: usize,
+}
+
+// Per node counters and gauges for the live runner, exported in the OpenMetrics text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    nodes: Mutex<BTreeMap<Id, NodeMetrics>>,
+}
+
+impl Metrics {
+    fn update(&self, id: Id, f: impl FnOnce(&mut NodeMetrics)) {
+        let mut nodes = self.nodes.lock().expect("metrics lock poisoned");
+        f(nodes.entry(id).or_default())
+    }
+
+    pub fn record_msg(&self, id: Id, kind: &'static str) {
+        self.update(id, |m| *m.messages.entry(kind).or_default() += 1)
+    }
+
+    pub fn record_decision(&self, id: Id) {
+        self.update(id, |m| m.decisions += 1)
+    }
+
+    pub fn record_rejection(&self, id: Id) {
+        self.update(id, |m| m.rejections += 1)
+    }
+
+    pub fn observe(&self, id: Id, state: &State) {
+        self.update(id, |m| {
+            m.members = state.membership.members().len();
+            m.pending_joins = state.membership.stable_set.joining_members.len();
+            m.pending_txs = state.wallet.ledger.pending_commitments.len();
+            m.gen = state.handover.gen();
+            m.health_gen = state.health.latest().map(|r| r.gen as usize).unwrap_or(0);
+        })
+    }
+
+    pub fn render(&self) -> String {
+        let nodes = self.nodes.lock().expect("metrics lock poisoned").clone();
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# TYPE stable_set_messages counter");
+        for (id, m) in nodes.iter() {
+            for (kind, count) in m.messages.iter() {
+                let _ = writeln!(
+                    out,
+                    "stable_set_messages_total{{node=\"{id:?}\",kind=\"{kind}\"}} {count}"
+                );
+            }
+        }
+
+        let counters: [Counter; 2] = [
+            ("decisions", |m| m.decisions),
+            ("rejections", |m| m.rejections),
+        ];
+        for (name, value) in counters {
+            let _ = writeln!(out, "# TYPE stable_set_{name} counter");
+            for (id, m) in nodes.iter() {
+                let _ = writeln!(
+                    out,
+                    "stable_set_{name}_total{{node=\"{id:?}\"}} {}",
+                    value(m)
+                );
+            }
+        }
+
+        let gauges: [Gauge; 5] = [
+            ("members", |m| m.members),
+            ("pending_joins", |m| m.pending_joins),
+            ("pending_txs", |m| m.pending_txs),
+            ("gen", |m| m.gen),
+            ("health_gen", |m| m.health_gen),
+        ];
+        for (name, value) in gauges {
+            let _ = writeln!(out, "# TYPE stable_set_{name} gauge");
+            for (id, m) in nodes.iter() {
+                let _ = writeln!(out, "stable_set_{name}{{node=\"{id:?}\"}} {}", value(m));
+            }
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+
+    // Serves the metrics on every request, Prometheus is expected to scrape `/metrics`.
+    pub fn serve(self: Arc<Self>, addr: impl ToSocketAddrs) -> std::io::Result<JoinHandle<()>> {
+        let listener = TcpListener::bind(addr)?;
+
+        Ok(thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request);
+
+                let body = self.render();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/openmetrics-text; version=1.0.0; charset=utf-8\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        }))
+    }
+}
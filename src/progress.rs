@@ -0,0 +1,42 @@
+use std::time::{Duration, Instant};
+
+use stateright::{Checker, Model};
+
+// How often a headless check logs how far it got.
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+// Waits for the checker to finish, logging every `INTERVAL` the states it explored, how many per
+// second since the last line, the depth it reached and the properties it found a discovery for.
+pub fn join<M: Model, C: Checker<M>>(checker: C) -> C {
+    let names = Vec::from_iter(checker.model().properties().iter().map(|p| p.name));
+    let started = Instant::now();
+    let (mut logged_at, mut logged_states) = (started, 0);
+
+    while !checker.is_done() {
+        std::thread::sleep(Duration::from_millis(100));
+        if logged_at.elapsed() < INTERVAL {
+            continue;
+        }
+
+        let states = checker.state_count();
+        let rate = (states - logged_states) as f64 / logged_at.elapsed().as_secs_f64();
+        let discoveries = checker.discoveries();
+        let discovered =
+            Vec::from_iter(names.iter().filter(|name| discoveries.contains_key(*name)));
+        log::info!(
+            "{states} states ({} unique, {rate:.0}/s), depth {}, {}/{} properties discovered: {discovered:?}",
+            checker.unique_state_count(),
+            checker.max_depth(),
+            discovered.len(),
+            names.len(),
+        );
+        (logged_at, logged_states) = (Instant::now(), states);
+    }
+
+    log::info!(
+        "done after {:?}, {} unique states",
+        started.elapsed(),
+        checker.unique_state_count()
+    );
+    checker.join()
+}
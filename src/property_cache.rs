@@ -0,0 +1,48 @@
+use std::{collections::HashMap, hash::Hash, sync::Mutex};
+
+// Bounds memory on long runs, the cache is simply dropped once it fills up.
+const MAX_CACHED_RESULTS: usize = 1 << 16;
+
+// Memoizes an expensive property on just the actor state it reads. Most steps only touch one or two
+// actors, so the states the checker visits mostly hit the cache. The state itself is the key rather
+// than a digest of it, two states that hash alike can't share a result.
+pub struct PropertyCache<K> {
+    results: Mutex<Option<HashMap<K, bool>>>,
+}
+
+impl<K: Eq + Hash> PropertyCache<K> {
+    pub const fn new() -> Self {
+        Self {
+            results: Mutex::new(None),
+        }
+    }
+
+    pub fn check(&self, key: K, condition: impl FnOnce() -> bool) -> bool {
+        if let Some(result) = self
+            .results
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|results| results.get(&key))
+        {
+            return *result;
+        }
+
+        let result = condition();
+
+        let mut results = self.results.lock().unwrap();
+        let results = results.get_or_insert_with(HashMap::new);
+        if results.len() >= MAX_CACHED_RESULTS {
+            results.clear();
+        }
+        results.insert(key, result);
+
+        result
+    }
+}
+
+impl<K: Eq + Hash> Default for PropertyCache<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,47 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use stateright::actor::Id;
+
+use crate::fake_crypto::{hash, SigScheme};
+
+// ed25519 behind the same interface as the model's hashed keys. Keys are still derived from the
+// signer's id so every node can work out anyone's public key, only the signing layer is real.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Ed25519;
+
+fn key_from_seed(seed: impl std::hash::Hash + Copy) -> SigningKey {
+    let mut bytes = [0u8; 32];
+    for (i, chunk) in bytes.chunks_mut(8).enumerate() {
+        chunk.copy_from_slice(&hash(&(seed, i)).to_le_bytes());
+    }
+    SigningKey::from_bytes(&bytes)
+}
+
+fn signing_key(signer: Id, key_gen: u64) -> SigningKey {
+    key_from_seed(("ed25519 key", signer, key_gen))
+}
+
+impl SigScheme for Ed25519 {
+    // Signatures are 64 bytes, which serde won't derive for as an array.
+    type Tag = Vec<u8>;
+
+    fn sign(signer: Id, key_gen: u64, signed: &[u8]) -> Vec<u8> {
+        signing_key(signer, key_gen).sign(signed).to_vec()
+    }
+
+    fn verify(signer: Id, key_gen: u64, signed: &[u8], tag: &Vec<u8>) -> bool {
+        let Ok(sig) = Signature::from_slice(tag) else {
+            return false;
+        };
+        signing_key(signer, key_gen)
+            .verifying_key()
+            .verify(signed, &sig)
+            .is_ok()
+    }
+
+    // Signed with a key of the forger's own, which the claimed signer's public key rejects.
+    fn forge(claimed_signer: Id, key_gen: u64, signed: &[u8]) -> Vec<u8> {
+        key_from_seed(("forged", claimed_signer, key_gen))
+            .sign(signed)
+            .to_vec()
+    }
+}
@@ -0,0 +1,87 @@
+use stateright::actor::Id;
+
+use crate::{fake_crypto::hash, ledger::DbcId};
+
+// The low `len` bits shared by every name in a section, the whole network starts as the empty prefix
+// and each split extends a section's prefix by one bit.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct Prefix {
+    bits: u64,
+    len: u8,
+}
+
+impl Prefix {
+    pub fn matches(&self, name: u64) -> bool {
+        // A prefix as long as a name matches only that name.
+        let mask = 1u64
+            .checked_shl(u32::from(self.len))
+            .map_or(u64::MAX, |bit| bit - 1);
+        name & mask == self.bits
+    }
+
+    // The two halves this prefix splits into, none once it's as long as a name.
+    pub fn children(&self) -> Option<[Prefix; 2]> {
+        let bit = 1u64.checked_shl(u32::from(self.len))?;
+        let len = self.len + 1;
+        Some([
+            Prefix {
+                bits: self.bits,
+                len,
+            },
+            Prefix {
+                bits: self.bits | bit,
+                len,
+            },
+        ])
+    }
+
+    // The child of this prefix that `name` falls into.
+    pub fn child_for(&self, name: u64) -> Option<Prefix> {
+        self.children()?
+            .into_iter()
+            .find(|child| child.matches(name))
+    }
+
+    // The other half of the split that made this prefix.
+    pub fn sibling(&self) -> Option<Prefix> {
+        let bit = self.len.checked_sub(1)?;
+        Some(Prefix {
+            bits: self.bits ^ (1 << bit),
+            len: self.len,
+        })
+    }
+
+    // Two sections overlap if one is the other, or an ancestor of it.
+    pub fn overlaps(&self, other: &Prefix) -> bool {
+        if self.len <= other.len {
+            self.matches(other.bits)
+        } else {
+            other.matches(self.bits)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+pub fn id_name(id: Id) -> u64 {
+    u64::from(id)
+}
+
+// Dbcs are sharded by a hash of their id so every dbc belongs to exactly one section's ledger.
+pub fn dbc_name(dbc_id: &DbcId) -> u64 {
+    hash(dbc_id)
+}
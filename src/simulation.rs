@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use stateright::{
+    actor::{ActorModel, ActorModelAction, Envelope, Id},
+    Chooser, Model,
+};
+
+use crate::{
+    bandwidth::{Traffic, WireSize},
+    prop_all_nodes_joined_who_havent_left, prop_stable_set_converged, ModelCfg, Msg, Node,
+};
+
+type NodeModel = ActorModel<Node, ModelCfg, Traffic>;
+
+// Probability that a delivery on a link is dropped instead, links not listed use the default.
+#[derive(Clone, Debug, Default)]
+pub struct LinkDropRates {
+    pub default: f64,
+    pub links: BTreeMap<(Id, Id), f64>,
+}
+
+impl LinkDropRates {
+    pub fn drop_rate(&self, src: Id, dst: Id) -> f64 {
+        self.links.get(&(src, dst)).copied().unwrap_or(self.default)
+    }
+}
+
+// Small deterministic PRNG (splitmix64) so simulation runs are reproducible from their seed.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        (self.next_u64() as f64 / u64::MAX as f64) < p
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulationState {
+    rng: Rng,
+    step: usize,
+    // Bytes each link has accumulated towards delivering its next message.
+    link_credit: BTreeMap<(Id, Id), usize>,
+    converged_at: Option<usize>,
+}
+
+// Picks uniformly among non-drop actions, then drops the chosen delivery with its link's drop rate.
+// With a byte budget, each link can only move that many bytes per step: larger messages are
+// deferred until the link has accumulated enough credit, as if they were sent in fragments.
+#[derive(Clone, Debug)]
+pub struct SimulationChooser {
+    pub drop_rates: LinkDropRates,
+    pub byte_budget_per_step: Option<usize>,
+}
+
+impl SimulationChooser {
+    fn within_budget(&self, sim: &SimulationState, src: Id, dst: Id, msg: &Msg) -> bool {
+        match self.byte_budget_per_step {
+            Some(_) => sim.link_credit.get(&(src, dst)).copied().unwrap_or(0) >= msg.wire_size(),
+            None => true,
+        }
+    }
+
+    fn accrue_credit(&self, sim: &mut SimulationState, actions: &[<NodeModel as Model>::Action]) {
+        if let Some(budget) = self.byte_budget_per_step {
+            let busy_links = BTreeSet::from_iter(actions.iter().filter_map(|a| match a {
+                ActorModelAction::Deliver { src, dst, .. } => Some((*src, *dst)),
+                _ => None,
+            }));
+
+            for link in busy_links {
+                *sim.link_credit.entry(link).or_default() += budget;
+            }
+        }
+    }
+}
+
+impl Chooser<NodeModel> for SimulationChooser {
+    type State = SimulationState;
+
+    fn new_state(&self, seed: u64) -> Self::State {
+        SimulationState {
+            rng: Rng::new(seed),
+            step: 0,
+            link_credit: Default::default(),
+            converged_at: None,
+        }
+    }
+
+    fn choose_initial_state(
+        &self,
+        sim: &mut Self::State,
+        initial_states: &[<NodeModel as Model>::State],
+    ) -> usize {
+        sim.rng.below(initial_states.len())
+    }
+
+    fn choose_action(
+        &self,
+        sim: &mut Self::State,
+        current_state: &<NodeModel as Model>::State,
+        actions: &[<NodeModel as Model>::Action],
+    ) -> usize {
+        sim.step += 1;
+
+        if sim.converged_at.is_none()
+            && prop_stable_set_converged(current_state)
+            && prop_all_nodes_joined_who_havent_left(&Default::default(), current_state)
+        {
+            sim.converged_at = Some(sim.step);
+            log::info!(
+                "converged after {} steps (byte budget per step: {:?})",
+                sim.step,
+                self.byte_budget_per_step
+            );
+        }
+
+        self.accrue_credit(sim, actions);
+
+        let candidates = Vec::from_iter(
+            actions
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| match a {
+                    ActorModelAction::Drop(_) => false,
+                    ActorModelAction::Deliver { src, dst, msg } => {
+                        self.within_budget(sim, *src, *dst, msg)
+                    }
+                    _ => true,
+                })
+                .map(|(i, _)| i),
+        );
+
+        if candidates.is_empty() {
+            return sim.rng.below(actions.len());
+        }
+
+        let chosen = candidates[sim.rng.below(candidates.len())];
+
+        if let ActorModelAction::Deliver { src, dst, msg } = &actions[chosen] {
+            if self.byte_budget_per_step.is_some() {
+                let credit = sim.link_credit.entry((*src, *dst)).or_default();
+                *credit = credit.saturating_sub(msg.wire_size());
+            }
+
+            if sim.rng.chance(self.drop_rates.drop_rate(*src, *dst)) {
+                let envelope = Envelope {
+                    src: *src,
+                    dst: *dst,
+                    msg: msg.clone(),
+                };
+                if let Some(drop_ix) = actions
+                    .iter()
+                    .position(|a| a == &ActorModelAction::Drop(envelope.clone()))
+                {
+                    return drop_ix;
+                }
+            }
+        }
+
+        chosen
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    fs::File,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use stateright::{
+    actor::{ActorModel, ActorModelState},
+    Chooser, Expectation, Model,
+};
+
+use crate::{
+    bandwidth::Traffic, codec, joined_count, reissued_count, simulation::SimulationChooser,
+    ModelCfg, Node,
+};
+
+type NodeModel = ActorModel<Node, ModelCfg, Traffic>;
+
+const MAX_STEPS_PER_RUN: usize = 10_000;
+
+// Runs back to back random walks through the model until `duration` elapses, checking every
+// `Always` property at each step. On the first violation the seed and the journal of actions
+// that led to it are written to `soak-failure-<seed>.txt` so the run can be reproduced, along
+// with a checkpoint of each node's stable set.
+pub fn soak(model: &NodeModel, chooser: &SimulationChooser, duration: Duration, first_seed: u64) {
+    let started = Instant::now();
+    let properties = Vec::from_iter(
+        model
+            .properties()
+            .into_iter()
+            .filter(|p| matches!(p.expectation, Expectation::Always)),
+    );
+
+    let mut seed = first_seed;
+    while started.elapsed() < duration {
+        let mut sim = chooser.new_state(seed);
+        let init_states = model.init_states();
+        let mut state = init_states[chooser.choose_initial_state(&mut sim, &init_states)].clone();
+        let mut journal = vec![];
+
+        for _ in 0..MAX_STEPS_PER_RUN {
+            if let Some(violated) = properties.iter().find(|p| !(p.condition)(model, &state)) {
+                log::error!("seed {seed} violated {:?}", violated.name);
+                dump_failure(seed, violated.name, &journal, &format!("{state:#?}"));
+                dump_checkpoints(seed, &state);
+                return;
+            }
+
+            let mut actions = vec![];
+            model.actions(&state, &mut actions);
+            if actions.is_empty() {
+                break;
+            }
+
+            let action = actions[chooser.choose_action(&mut sim, &state, &actions)].clone();
+            journal.push(format!("{action:?}"));
+
+            if let Some(next_state) = model.next_state(&state, action) {
+                state = next_state;
+            }
+        }
+
+        log::info!(
+            "seed {seed} passed after {} steps ({:?} elapsed), traffic: {}",
+            journal.len(),
+            started.elapsed(),
+            state
+                .history
+                .report(joined_count(&state), reissued_count(&state))
+        );
+        seed += 1;
+    }
+}
+
+fn dump_failure(seed: u64, property: &str, journal: &[String], final_state: &str) {
+    let path = format!("soak-failure-{seed}.txt");
+    let result = File::create(&path).and_then(|mut f| {
+        writeln!(f, "seed: {seed}")?;
+        writeln!(f, "violated: {property}")?;
+        writeln!(f, "journal:")?;
+        for (step, action) in journal.iter().enumerate() {
+            writeln!(f, "{step}: {action}")?;
+        }
+        writeln!(f, "final state:\n{final_state}")
+    });
+
+    match result {
+        Ok(()) => log::error!("wrote reproduction to {path}"),
+        Err(err) => log::error!("failed to write {path}: {err}"),
+    }
+}
+
+fn dump_checkpoints(seed: u64, state: &ActorModelState<Node, Traffic>) {
+    for (i, node) in state.actor_states.iter().enumerate() {
+        let path = format!("soak-failure-{seed}-node-{i}.json");
+        let result = codec::encode(&node.membership.stable_set)
+            .map_err(std::io::Error::from)
+            .and_then(|bytes| std::fs::write(&path, bytes));
+
+        if let Err(err) = result {
+            log::error!("failed to write {path}: {err}");
+        }
+    }
+}
@@ -5,7 +5,15 @@ use std::{
 
 use stateright::actor::Id;
 
-use crate::membership::Elders;
+use crate::{
+    codec::{as_pairs, StableSetV0, StableSetV1, StableSetV2},
+    fake_crypto::{
+        hash, total_weight, Proof, PublicKey, Quorum, QuorumPolicy, SectionSig, Sig, SigSet,
+    },
+    membership::Elders,
+    section::{id_name, Prefix},
+    ELDER_COUNT,
+};
 
 pub fn majority(m: usize, n: usize) -> bool {
     3 * m > n * 2
@@ -14,12 +22,121 @@ pub fn majority(m: usize, n: usize) -> bool {
 #[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize)]
 pub struct Member {
     pub ord_idx: u64,
+    // Generation of the stable set the join was proposed against.
+    #[serde(default)]
+    pub gen: u64,
     pub id: Id,
+    // The age the member joined with, see `StableSet::age` for how it grows from there.
+    #[serde(default)]
+    pub age: u8,
 }
 
 impl std::fmt::Debug for Member {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}:{:?}", self.ord_idx, self.id)
+        write!(f, "{}:{:?}", self.ord_idx, self.id)?;
+
+        if self.gen > 0 {
+            write!(f, "#{}", self.gen)?;
+        }
+
+        if self.age > 0 {
+            write!(f, "@{}", self.age)?;
+        }
+
+        Ok(())
+    }
+}
+
+// The order elders are picked in: oldest first, then lowest ord_idx, then lowest id.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ElderOrd<'a> {
+    pub age: u64,
+    pub member: &'a Member,
+}
+
+impl Ord for ElderOrd<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .age
+            .cmp(&self.age)
+            .then(self.member.ord_idx.cmp(&other.member.ord_idx))
+            .then(self.member.id.cmp(&other.member.id))
+    }
+}
+
+impl PartialOrd for ElderOrd<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(
+    Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub struct Blacklisting {
+    pub key: PublicKey,
+    pub member: Member,
+}
+
+// What one stable set knows that another doesn't, see `StableSet::diff`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StableSetDelta {
+    pub joined: BTreeSet<Member>,
+    pub left: BTreeSet<Member>,
+    pub joining: BTreeSet<Member>,
+    pub leaving: BTreeSet<Member>,
+    pub removing: BTreeSet<Member>,
+    pub blacklisted: BTreeSet<Blacklisting>,
+}
+
+impl StableSetDelta {
+    pub fn is_empty(&self) -> bool {
+        self.joined.is_empty()
+            && self.left.is_empty()
+            && self.joining.is_empty()
+            && self.leaving.is_empty()
+            && self.removing.is_empty()
+            && self.blacklisted.is_empty()
+    }
+
+    // Everyone the delta is about.
+    pub fn ids(&self) -> BTreeSet<Id> {
+        let members = self
+            .joined
+            .iter()
+            .chain(&self.left)
+            .chain(&self.joining)
+            .chain(&self.leaving)
+            .chain(&self.removing)
+            .chain(self.blacklisted.iter().map(|entry| &entry.member));
+        BTreeSet::from_iter(members.map(|m| m.id))
+    }
+}
+
+// Lets anyone who knows the elders that voted a member in check it's a member, without holding the
+// stable set. `gen` is the stable set generation the proof was handed out at.
+pub type MembershipProof = Proof<Member>;
+
+// Everything decided up to `gen`. The departed are folded into the highest ord_idx handed out so
+// far, anyone at or below it that isn't a member has left.
+#[derive(
+    Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize,
+)]
+pub struct Snapshot {
+    pub gen: u64,
+    pub members: BTreeSet<Member>,
+    pub max_ord_idx: u64,
+}
+
+impl Snapshot {
+    // Signed by the elders at its own generation.
+    pub fn elders(&self) -> Elders {
+        let stable_set = StableSet {
+            gen: self.gen,
+            members: self.members.clone(),
+            ..Default::default()
+        };
+        stable_set.elders(ELDER_COUNT)
     }
 }
 
@@ -27,15 +144,82 @@ impl std::fmt::Debug for Member {
     Clone, Eq, Hash, PartialEq, PartialOrd, Ord, Default, serde::Serialize, serde::Deserialize,
 )]
 pub struct StableSet {
+    // Bumped on every committed join and leave.
+    #[serde(default)]
+    gen: u64,
     members: BTreeSet<Member>,
-    // dead: BTreeSet<Id>,
-    pub joining_members: BTreeMap<Member, BTreeSet<Id>>,
+    // Members that have left, they may only come back as a new member with a higher ord_idx.
+    dead: BTreeSet<Member>,
+    // Joins are decided by an elder majority signature, which is kept as proof once decided.
+    #[serde(with = "as_pairs")]
+    pub joining_members: BTreeMap<Member, SectionSig<Member>>,
+    #[serde(default, with = "as_pairs")]
+    pub proofs: BTreeMap<Member, SectionSig<Member>>,
+    #[serde(with = "as_pairs")]
     pub leaving_members: BTreeMap<Member, BTreeSet<Id>>,
+    // Forced removals, with the shares of the elders that proposed them.
+    #[serde(default, with = "as_pairs")]
+    pub removing_members: BTreeMap<Member, SigSet<Member>>,
+    #[serde(with = "as_pairs")]
+    pub blacklist: BTreeMap<Blacklisting, SectionSig<Blacklisting>>,
+    // The latest snapshot the elders signed, and shares towards newer ones.
+    #[serde(default)]
+    pub snapshot: Option<(Snapshot, SectionSig<Snapshot>)>,
+    #[serde(default, with = "as_pairs")]
+    pub pending_snapshots: BTreeMap<Snapshot, SectionSig<Snapshot>>,
+}
+
+// Pending joins were only backed by witness ids back then, they can't be turned into signatures
+// so they are dropped and will be proposed again.
+impl From<StableSetV0> for StableSet {
+    fn from(v0: StableSetV0) -> Self {
+        Self {
+            members: v0.members,
+            leaving_members: v0.leaving_members,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<StableSetV1> for StableSet {
+    fn from(v1: StableSetV1) -> Self {
+        Self {
+            gen: v1.gen,
+            members: v1.members,
+            dead: v1.dead,
+            leaving_members: v1.leaving_members,
+            blacklist: v1.blacklist,
+            ..Default::default()
+        }
+    }
+}
+
+// Forced removals were backed by bare witness ids, nothing shows those elders proposed them. They
+// are left for the elders to propose again.
+impl From<StableSetV2> for StableSet {
+    fn from(v2: StableSetV2) -> Self {
+        Self {
+            gen: v2.gen,
+            members: v2.members,
+            dead: v2.dead,
+            joining_members: v2.joining_members,
+            proofs: v2.proofs,
+            leaving_members: v2.leaving_members,
+            removing_members: Default::default(),
+            blacklist: v2.blacklist,
+            snapshot: v2.snapshot,
+            pending_snapshots: v2.pending_snapshots,
+        }
+    }
 }
 
 impl Debug for StableSet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "SS({:?}", self.members)?;
+        write!(f, "SS(gen:{}, {:?}", self.gen, self.members)?;
+
+        if !self.dead.is_empty() {
+            write!(f, ", dead:{:?}", self.dead)?;
+        }
 
         if !self.joining_members.is_empty() {
             write!(f, ", joining:{:?}", self.joining_members)?;
@@ -45,71 +229,372 @@ impl Debug for StableSet {
             write!(f, ", leaving:{:?}", self.leaving_members)?;
         }
 
+        if !self.removing_members.is_empty() {
+            write!(f, ", removing:{:?}", self.removing_members)?;
+        }
+
+        if !self.blacklist.is_empty() {
+            write!(f, ", blacklist:{:?}", self.blacklist)?;
+        }
+
+        if let Some((snapshot, _)) = &self.snapshot {
+            write!(f, ", snapshot:{}", snapshot.gen)?;
+        }
+
         write!(f, ")")
     }
 }
 
 impl StableSet {
-    pub fn process_ready_actions(&mut self, elders: &Elders) -> bool {
+    pub fn process_ready_actions(
+        &mut self,
+        elders: &Elders,
+        quorum: QuorumPolicy,
+        weights: &BTreeMap<Id, u64>,
+    ) -> bool {
         let mut updated = false;
 
+        // Shares collected while the elders were different still count if their signer is an elder.
+        // Whoever collected them, it's our quorum that decides.
+        for (member, sig) in self.joining_members.iter_mut() {
+            if &sig.voters != elders {
+                *sig = sig.for_voters(elders.clone(), member);
+            }
+            sig.quorum = quorum.joins;
+            sig.weights = weights.clone();
+        }
+
         let ready_to_join = Vec::from_iter(
             self.joining_members
                 .iter()
-                .filter(|(_, witnesses)| {
-                    majority(witnesses.intersection(elders).count(), elders.len())
-                })
+                .filter(|(member, sig)| sig.verify(elders, member))
                 .map(|(member, _)| member)
                 .cloned(),
         );
 
         updated |= !ready_to_join.is_empty();
 
+        // Ready joins come out lowest ord_idx first, so concurrent proposals for the same
+        // candidate resolve the same way everywhere: the lowest ord_idx with a quorum wins.
         for member in ready_to_join {
-            self.joining_members.remove(&member);
+            let proof = self.joining_members.remove(&member);
 
+            if self.dead.contains(&member) || self.is_superseded(&member) {
+                continue;
+            }
+
+            let mut is_new_member = true;
             if let Some(existing_member_with_id) = self.member_by_id(member.id) {
-                if existing_member_with_id.ord_idx >= member.ord_idx {
-                    continue;
-                } else {
-                    self.members.remove(&existing_member_with_id);
-                }
+                // Either a rejoin replacing a member on its way out, or we decided a higher
+                // proposal before this one got its quorum. The latter corrects the ord_idx rather
+                // than committing another join.
+                is_new_member = existing_member_with_id.ord_idx < member.ord_idx;
+                self.forget(&existing_member_with_id);
             }
 
+            if let Some(proof) = proof {
+                self.proofs.insert(member.clone(), proof);
+            }
             self.members.insert(member);
+            if is_new_member {
+                self.gen += 1;
+            }
+        }
+
+        let superseded = Vec::from_iter(self.joining().filter(|m| self.is_superseded(m)));
+        for member in superseded {
+            self.joining_members.remove(&member);
         }
 
+        let decided_by = |witnesses: &BTreeSet<Id>| {
+            quorum.leaves.reached(
+                total_weight(witnesses.intersection(elders), weights),
+                total_weight(elders, weights),
+            )
+        };
+
         let ready_to_leave = Vec::from_iter(
             self.leaving_members
                 .iter()
-                .filter(|(_, witnesses)| {
-                    majority(witnesses.intersection(elders).count(), elders.len())
-                })
+                .filter(|(_, witnesses)| decided_by(witnesses))
                 .map(|(member, _)| member)
                 .cloned(),
         );
 
         updated |= !ready_to_leave.is_empty();
 
-        for member in ready_to_leave {
+        let ready_to_remove = Vec::from_iter(
+            self.removing_members
+                .iter()
+                .filter(|(_, shares)| decided_by(&shares.ids()))
+                .map(|(member, _)| member)
+                .cloned(),
+        );
+
+        updated |= !ready_to_remove.is_empty();
+
+        for member in ready_to_leave.into_iter().chain(ready_to_remove) {
             self.leaving_members.remove(&member);
-            self.members.remove(&member);
+            self.removing_members.remove(&member);
+            if self.members.remove(&member) {
+                self.gen += 1;
+            }
+            self.proofs.remove(&member);
+            self.dead.insert(member);
+        }
+
+        let signed = self
+            .pending_snapshots
+            .iter()
+            .filter(|(snapshot, sig)| sig.verify(&snapshot.elders(), snapshot))
+            .max_by_key(|(snapshot, _)| snapshot.gen)
+            .map(|(snapshot, sig)| (snapshot.clone(), sig.clone()));
+        if let Some((snapshot, sig)) = signed {
+            self.pending_snapshots.retain(|s, _| s.gen > snapshot.gen);
+            self.snapshot = Some((snapshot, sig));
+            updated = true;
         }
 
         updated
     }
 
-    pub fn add(&mut self, member: Member, witness: Id) -> bool {
-        if !self.is_member(&member) {
-            self.joining_members
-                .entry(member)
-                .or_default()
-                .insert(witness)
+    pub fn snapshot_gen(&self) -> u64 {
+        self.snapshot
+            .as_ref()
+            .map_or(0, |(snapshot, _)| snapshot.gen)
+    }
+
+    // Joins at or below the ord_idx of a snapshot in the works would be taken as departed once it
+    // is signed, so we stop signing them.
+    fn snapshot_ord_idx(&self) -> Option<u64> {
+        self.snapshot
+            .iter()
+            .map(|(snapshot, _)| snapshot)
+            .chain(self.pending_snapshots.keys())
+            .map(|snapshot| snapshot.max_ord_idx)
+            .max()
+    }
+
+    // Adds our share to a snapshot of everything decided so far, returns true if the share is new.
+    pub fn sign_snapshot(&mut self, signer: Id) -> bool {
+        let snapshot = Snapshot {
+            gen: self.gen,
+            members: self.members.clone(),
+            max_ord_idx: self.latest_ord_idx(),
+        };
+        if snapshot.gen <= self.snapshot_gen() {
+            return false;
+        }
+
+        let sig = self
+            .pending_snapshots
+            .entry(snapshot.clone())
+            .or_insert_with(|| SectionSig::new(snapshot.elders()));
+        if sig.signers().contains(&signer) {
+            return false;
+        }
+        sig.add_share(signer, Sig::sign(signer, snapshot));
+        sig.signers().contains(&signer)
+    }
+
+    // Takes the shares a peer collected for a snapshot. One for a generation we've moved past can
+    // no longer gather shares, so only a signed one is taken then, by the quorum we sign with.
+    pub fn merge_snapshot(&mut self, snapshot: Snapshot, sig: &SectionSig<Snapshot>) -> bool {
+        let elders = snapshot.elders();
+        let signed = || sig.verify_with(&elders, Quorum::default(), &BTreeMap::new(), &snapshot);
+        if snapshot.gen <= self.snapshot_gen() || (snapshot.gen < self.gen && !signed()) {
+            return false;
+        }
+
+        let is_new = !self.pending_snapshots.contains_key(&snapshot);
+        let existing = self
+            .pending_snapshots
+            .entry(snapshot.clone())
+            .or_insert_with(|| SectionSig::new(elders));
+        existing.merge_shares(sig, &snapshot) || is_new
+    }
+
+    // What a node restarting from our latest snapshot holds, none of the departed are kept.
+    pub fn restore(&self) -> Option<StableSet> {
+        let (snapshot, sig) = self.snapshot.clone()?;
+        Some(StableSet {
+            gen: snapshot.gen,
+            members: snapshot.members.clone(),
+            snapshot: Some((snapshot, sig)),
+            ..Default::default()
+        })
+    }
+
+    pub fn gen(&self) -> u64 {
+        self.gen
+    }
+
+    // A node that joined late never saw the changes before it, it takes on the generation of a
+    // peer that has decided the same members. Returns true if we caught up.
+    pub fn catch_up_gen(&mut self, gen: u64, members: &BTreeSet<Member>) -> bool {
+        if gen > self.gen && members == &self.members {
+            self.gen = gen;
+            true
         } else {
             false
         }
     }
 
+    // A member with the same id and a lower ord_idx was decided and isn't on its way out, so this
+    // proposal lost to a concurrent one.
+    pub fn is_superseded(&self, member: &Member) -> bool {
+        self.members.iter().any(|m| {
+            m.id == member.id
+                && m.ord_idx <= member.ord_idx
+                && m != member
+                && !self.leaving_members.contains_key(m)
+        })
+    }
+
+    // The lowest pending proposal for the same candidate, if it isn't this one. Elders at different
+    // generations can propose the same ord_idx, the earlier generation goes first then.
+    pub fn lower_proposal(&self, member: &Member) -> Option<&Member> {
+        self.joining_members
+            .keys()
+            .find(|m| m.id == member.id && (m.ord_idx, m.gen) < (member.ord_idx, member.gen))
+    }
+
+    // Decisions and pending changes we know of that `other` is missing. A pending change counts as
+    // missing if `other` lacks any of our shares or witnesses for it.
+    pub fn diff(&self, other: &StableSet) -> StableSetDelta {
+        let covered = |ours: &BTreeSet<Id>, theirs: Option<&BTreeSet<Id>>| {
+            theirs.is_some_and(|theirs| ours.is_subset(theirs))
+        };
+
+        StableSetDelta {
+            joined: BTreeSet::from_iter(
+                self.members.iter().filter(|m| !other.has_seen(m)).cloned(),
+            ),
+            left: BTreeSet::from_iter(
+                other
+                    .members
+                    .iter()
+                    .filter(|m| {
+                        self.is_dead(m)
+                            || (other.leaving_members.contains_key(m) && !self.is_member(m))
+                    })
+                    .cloned(),
+            ),
+            joining: BTreeSet::from_iter(
+                self.joining_members
+                    .iter()
+                    .filter(|(m, sig)| {
+                        let theirs = other.joining_members.get(m).map(|sig| sig.signers());
+                        !other.has_seen(m) && !covered(&sig.signers(), theirs.as_ref())
+                    })
+                    .map(|(m, _)| m.clone()),
+            ),
+            leaving: BTreeSet::from_iter(
+                self.leaving_members
+                    .iter()
+                    .filter(|(m, witnesses)| {
+                        other.is_member(m) && !covered(witnesses, other.leaving_members.get(m))
+                    })
+                    .map(|(m, _)| m.clone()),
+            ),
+            removing: BTreeSet::from_iter(
+                self.removing_members
+                    .iter()
+                    .filter(|(m, shares)| {
+                        let theirs = other.removing_members.get(m).map(SigSet::ids);
+                        other.is_member(m) && !covered(&shares.ids(), theirs.as_ref())
+                    })
+                    .map(|(m, _)| m.clone()),
+            ),
+            blacklisted: BTreeSet::from_iter(
+                self.blacklist
+                    .iter()
+                    .filter(|(entry, sig)| {
+                        let theirs = other.blacklist.get(entry).map(|sig| sig.signers());
+                        !covered(&sig.signers(), theirs.as_ref())
+                    })
+                    .map(|(entry, _)| entry.clone()),
+            ),
+        }
+    }
+
+    // Every pending change but only the decisions in `joined` and `left`, enough for a peer that has
+    // seen all earlier decisions.
+    pub fn delta(&self, joined: &BTreeSet<Member>, left: &BTreeSet<Member>) -> StableSet {
+        let mut delta = self.clone();
+        delta.members.retain(|m| joined.contains(m));
+        delta.proofs.retain(|m, _| joined.contains(m));
+        delta.dead.retain(|m| left.contains(m));
+        delta
+    }
+
+    // Drops a join or leave that never gathered enough witnesses.
+    pub fn abandon(&mut self, member: &Member) {
+        self.joining_members.remove(member);
+        self.leaving_members.remove(member);
+    }
+
+    fn forget(&mut self, member: &Member) {
+        self.members.remove(member);
+        self.proofs.remove(member);
+        self.leaving_members.remove(member);
+        self.removing_members.remove(member);
+    }
+
+    // Adds our share towards deciding the join, returns true if the join or our share are new.
+    // We only ever sign the lowest proposal we know of for a candidate.
+    pub fn sign_join(&mut self, member: Member, signer: Id, elders: &Elders) -> bool {
+        let below_snapshot = self
+            .snapshot_ord_idx()
+            .is_some_and(|ord_idx| member.ord_idx <= ord_idx);
+        if self.has_seen(&member) || self.is_superseded(&member) || below_snapshot {
+            return false;
+        }
+        if let Some(lower) = self.lower_proposal(&member).cloned() {
+            let is_new = self.merge_join(member, &SectionSig::new(elders.clone()), elders);
+            return self.sign_join(lower, signer, elders) || is_new;
+        }
+
+        let is_new = !self.joining_members.contains_key(&member);
+        let sig = self
+            .joining_members
+            .entry(member.clone())
+            .or_insert_with(|| SectionSig::new(elders.clone()));
+        if &sig.voters != elders {
+            *sig = sig.for_voters(elders.clone(), &member);
+        }
+
+        if sig.signers().contains(&signer) {
+            return is_new;
+        }
+        sig.add_share(signer, Sig::sign(signer, member));
+
+        is_new || sig.signers().contains(&signer)
+    }
+
+    // Takes the shares a peer collected for a join, only those that verify are kept.
+    pub fn merge_join(
+        &mut self,
+        member: Member,
+        sig: &SectionSig<Member>,
+        elders: &Elders,
+    ) -> bool {
+        if self.has_seen(&member) || self.is_superseded(&member) {
+            return false;
+        }
+
+        let is_new = !self.joining_members.contains_key(&member);
+        let existing = self
+            .joining_members
+            .entry(member.clone())
+            .or_insert_with(|| SectionSig::new(elders.clone()));
+        if &existing.voters != elders {
+            *existing = existing.for_voters(elders.clone(), &member);
+        }
+
+        existing.merge_shares(sig, &member) || is_new
+    }
+
     pub fn remove(&mut self, member: Member, witness: Id) -> bool {
         if self.is_member(&member) {
             self.leaving_members
@@ -121,10 +606,92 @@ impl StableSet {
         }
     }
 
+    // Forgets everyone that doesn't satisfy `keep`, leaving the dead untouched.
+    pub fn retain(&mut self, keep: impl Fn(&Member) -> bool) {
+        self.members.retain(|m| keep(m));
+        self.joining_members.retain(|m, _| keep(m));
+        self.proofs.retain(|m, _| keep(m));
+        self.leaving_members.retain(|m, _| keep(m));
+        self.removing_members.retain(|m, _| keep(m));
+    }
+
+    pub fn add_removal_share(&mut self, member: Member, witness: Id) -> bool {
+        if !self.is_member(&member) {
+            return false;
+        }
+
+        let shares = self.removing_members.entry(member.clone()).or_default();
+        let is_new = !shares.ids().contains(&witness);
+        shares.add_share(witness, Sig::sign(witness, member));
+        is_new
+    }
+
+    // Only the elders' genuine shares are taken, a peer can't make up witnesses for a removal.
+    pub fn merge_removal(
+        &mut self,
+        member: Member,
+        shares: &SigSet<Member>,
+        elders: &Elders,
+    ) -> bool {
+        if !self.is_member(&member) {
+            return false;
+        }
+
+        let genuine = shares.genuine(elders, &member);
+        let existing = self.removing_members.entry(member).or_default();
+        let before = existing.ids().len();
+        existing.merge(genuine);
+        existing.ids().len() != before
+    }
+
+    pub fn add_blacklist_share(
+        &mut self,
+        entry: Blacklisting,
+        elders: &Elders,
+        signer: Id,
+    ) -> bool {
+        let sig = self
+            .blacklist
+            .entry(entry.clone())
+            .or_insert_with(|| SectionSig::new(elders.clone()));
+
+        let before = sig.clone();
+        sig.add_share(signer, Sig::sign(signer, entry));
+
+        sig != &before
+    }
+
+    // Takes the shares a peer collected for a blacklisting, only those that verify are kept. The
+    // voters and quorum are ours, never the ones the peer's sig claims.
+    pub fn merge_blacklist(
+        &mut self,
+        entry: Blacklisting,
+        sig: &SectionSig<Blacklisting>,
+        elders: &Elders,
+    ) -> bool {
+        let is_new = !self.blacklist.contains_key(&entry);
+        let existing = self
+            .blacklist
+            .entry(entry.clone())
+            .or_insert_with(|| SectionSig::new(elders.clone()));
+        existing.merge_shares(sig, &entry) || is_new
+    }
+
+    pub fn blacklisted(&self) -> impl Iterator<Item = &Blacklisting> + '_ {
+        self.blacklist
+            .iter()
+            .filter(|(entry, sig)| sig.is_complete(entry))
+            .map(|(entry, _)| entry)
+    }
+
+    pub fn is_blacklisted(&self, key: PublicKey) -> bool {
+        self.blacklisted().any(|entry| entry.key == key)
+    }
+
     pub fn joining_witnesses(&mut self, member: &Member) -> BTreeSet<Id> {
         self.joining_members
             .get(member)
-            .cloned()
+            .map(SectionSig::signers)
             .unwrap_or_default()
     }
 
@@ -139,6 +706,14 @@ impl StableSet {
         self.leaving_members.contains_key(member)
     }
 
+    // Only members we hold the deciding signature for can be proven, those restored from a
+    // snapshot can't.
+    pub fn membership_proof(&self, id: Id) -> Option<MembershipProof> {
+        let member = self.member_by_id(id)?;
+        let sig = self.proofs.get(&member)?.clone();
+        Some(Proof::new(member, sig, self.gen))
+    }
+
     pub fn member_by_id(&self, id: Id) -> Option<Member> {
         self.members.iter().find(|m| m.id == id).cloned()
     }
@@ -147,6 +722,37 @@ impl StableSet {
         self.members.contains(member)
     }
 
+    pub fn is_dead(&self, member: &Member) -> bool {
+        let folded = self.snapshot.as_ref().is_some_and(|(snapshot, _)| {
+            member.ord_idx <= snapshot.max_ord_idx
+                && !snapshot.members.contains(member)
+                && !self.members.contains(member)
+        });
+        self.dead.contains(member) || folded
+    }
+
+    pub fn has_seen(&self, member: &Member) -> bool {
+        self.is_member(member) || self.is_dead(member)
+    }
+
+    pub fn dead(&self) -> impl Iterator<Item = &Member> + '_ {
+        self.dead.iter()
+    }
+
+    pub fn latest_ord_idx(&self) -> u64 {
+        self.members
+            .iter()
+            .chain(self.dead.iter())
+            .map(|m| m.ord_idx)
+            .chain(
+                self.snapshot
+                    .iter()
+                    .map(|(snapshot, _)| snapshot.max_ord_idx),
+            )
+            .max()
+            .unwrap_or(0)
+    }
+
     pub fn contains(&self, id: Id) -> bool {
         self.ids().any(|m| m == id)
     }
@@ -155,8 +761,57 @@ impl StableSet {
         self.members.iter().map(|m| m.id)
     }
 
-    pub fn members(&self) -> BTreeSet<Member> {
-        self.members.clone()
+    // Only digest probes carry an empty stable set, every real one has at least its genesis members.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty() && self.dead.is_empty() && self.joining_members.is_empty()
+    }
+
+    pub fn members(&self) -> &BTreeSet<Member> {
+        &self.members
+    }
+
+    // A single hash over the decided view, built from a hash of each part so views that differ can
+    // be narrowed down to the part they differ in.
+    pub fn digest(&self) -> u64 {
+        hash(&(self.gen, self.members_digest(), self.dead_digest()))
+    }
+
+    pub fn members_digest(&self) -> u64 {
+        hash(&self.members)
+    }
+
+    pub fn dead_digest(&self) -> u64 {
+        hash(&self.dead)
+    }
+
+    // A member ages by one with every generation since its join was proposed, on top of the age it
+    // joined with. Every node at the same generation works out the same ages.
+    pub fn age(&self, member: &Member) -> u64 {
+        member.age as u64 + self.gen.saturating_sub(member.gen)
+    }
+
+    pub fn elders(&self, elder_count: usize) -> Elders {
+        self.elders_within(Prefix::default(), elder_count)
+    }
+
+    // The elders the members under `prefix` would have as a section of their own.
+    pub fn elders_within(&self, prefix: Prefix, elder_count: usize) -> Elders {
+        let mut candidates = Vec::from_iter(
+            self.members
+                .iter()
+                .filter(|m| prefix.matches(id_name(m.id)))
+                .map(|member| ElderOrd {
+                    age: self.age(member),
+                    member,
+                }),
+        );
+        candidates.sort();
+        BTreeSet::from_iter(
+            candidates
+                .into_iter()
+                .take(elder_count)
+                .map(|elder| elder.member.id),
+        )
     }
 
     pub fn leaving(&self) -> impl Iterator<Item = Member> + '_ {
@@ -167,3 +822,55 @@ impl StableSet {
         self.joining_members.keys().cloned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(ord_idx: u64, gen: u64, id: usize) -> Member {
+        Member {
+            ord_idx,
+            gen,
+            id: Id::from(id),
+            age: 0,
+        }
+    }
+
+    // Age goes first, whatever the ord_idx and id, then ord_idx, then id.
+    #[test]
+    fn elder_ord_is_age_then_ord_idx_then_id() {
+        let members = [
+            member(1, 0, 3),
+            member(1, 0, 2),
+            member(0, 0, 4),
+            member(5, 0, 5),
+        ];
+        let ages = [1, 1, 1, 2];
+        let mut order = Vec::from_iter(
+            members
+                .iter()
+                .zip(ages)
+                .map(|(member, age)| ElderOrd { age, member }),
+        );
+        order.sort();
+
+        let ids = Vec::from_iter(order.iter().map(|elder| usize::from(elder.member.id)));
+        assert_eq!(ids, vec![5, 4, 2, 3]);
+    }
+
+    // A member that stayed longer is picked as an elder over a newer one with a lower ord_idx.
+    #[test]
+    fn members_age_with_every_generation() {
+        let older = member(5, 1, 1);
+        let newer = member(3, 4, 2);
+        let stable_set = StableSet {
+            gen: 6,
+            members: BTreeSet::from_iter([older.clone(), newer.clone()]),
+            ..Default::default()
+        };
+
+        assert_eq!(stable_set.age(&older), 5);
+        assert_eq!(stable_set.age(&newer), 2);
+        assert_eq!(stable_set.elders(1), BTreeSet::from_iter([older.id]));
+    }
+}
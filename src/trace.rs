@@ -0,0 +1,155 @@
+use std::path::Path as FsPath;
+
+use stateright::{
+    actor::{ActorModel, ActorModelAction, ActorModelState, Envelope, Id},
+    Model, Path,
+};
+
+use crate::{bandwidth::Traffic, ModelCfg, Msg, Node};
+
+type NodeModel = ActorModel<Node, ModelCfg, Traffic>;
+
+// One step of a schedule as the checker took it.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub enum Step {
+    Deliver { src: Id, dst: Id, msg: Msg },
+    Drop { src: Id, dst: Id, msg: Msg },
+    Timeout(Id),
+}
+
+impl From<ActorModelAction<Msg>> for Step {
+    fn from(action: ActorModelAction<Msg>) -> Self {
+        match action {
+            ActorModelAction::Deliver { src, dst, msg } => Self::Deliver { src, dst, msg },
+            ActorModelAction::Drop(Envelope { src, dst, msg }) => Self::Drop { src, dst, msg },
+            ActorModelAction::Timeout(id) => Self::Timeout(id),
+        }
+    }
+}
+
+impl From<Step> for ActorModelAction<Msg> {
+    fn from(step: Step) -> Self {
+        match step {
+            Step::Deliver { src, dst, msg } => Self::Deliver { src, dst, msg },
+            Step::Drop { src, dst, msg } => Self::Drop(Envelope { src, dst, msg }),
+            Step::Timeout(id) => Self::Timeout(id),
+        }
+    }
+}
+
+// A discovery of the checker, the property it's about and the steps that lead to it from the
+// initial state.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Trace {
+    pub property: String,
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    pub fn new(
+        property: &str,
+        path: Path<ActorModelState<Node, Traffic>, ActorModelAction<Msg>>,
+    ) -> Self {
+        Self {
+            property: property.to_string(),
+            steps: Vec::from_iter(path.into_actions().into_iter().map(Step::from)),
+        }
+    }
+
+    // Where `trace` writes it, named after the property.
+    pub fn file_name(&self) -> String {
+        let slug = String::from_iter(self.property.chars().map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c.to_ascii_lowercase(),
+            _ => '-',
+        }));
+        format!("trace-{slug}.json")
+    }
+
+    pub fn write(&self, path: impl AsRef<FsPath>) -> std::io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(self).map_err(std::io::Error::from)?;
+        std::fs::write(path, bytes)
+    }
+
+    pub fn read(path: impl AsRef<FsPath>) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        serde_json::from_slice(&bytes).map_err(std::io::Error::from)
+    }
+}
+
+// Re-executes the steps from the initial state, printing what each one changed on every node it
+// touched, then whether the property holds at the end. Stops at the first step the model doesn't
+// offer, the trace then came from a model configured differently.
+pub fn replay(model: &NodeModel, trace: &Trace) {
+    let mut state = model.init_states().remove(0);
+
+    for (i, step) in trace.steps.iter().enumerate() {
+        println!("step {i}: {step:?}");
+
+        let action = ActorModelAction::from(step.clone());
+        let mut actions = vec![];
+        model.actions(&state, &mut actions);
+        if !actions.contains(&action) {
+            println!("  the model doesn't offer this step here, stopping");
+            return;
+        }
+
+        let Some(next_state) = model.next_state(&state, action) else {
+            continue;
+        };
+        for (id, (before, after)) in state
+            .actor_states
+            .iter()
+            .zip(next_state.actor_states.iter())
+            .enumerate()
+            .filter(|(_, (before, after))| before != after)
+        {
+            println!("  node {id}:");
+            print_diff(&format!("{before:#?}"), &format!("{after:#?}"));
+        }
+        state = next_state;
+    }
+
+    match model
+        .properties()
+        .into_iter()
+        .find(|p| p.name == trace.property)
+    {
+        Some(p) => println!(
+            "{:?} {} at the end of the trace",
+            p.name,
+            if (p.condition)(model, &state) {
+                "holds"
+            } else {
+                "doesn't hold"
+            }
+        ),
+        None => println!("the model has no property {:?}", trace.property),
+    }
+}
+
+// Only the lines between the first and the last that differ, that's enough to spot a change in a
+// pretty printed state.
+fn print_diff(before: &str, after: &str) {
+    let (before, after) = (
+        Vec::from_iter(before.lines()),
+        Vec::from_iter(after.lines()),
+    );
+    let prefix = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    for line in &before[prefix..before.len() - suffix] {
+        println!("  - {line}");
+    }
+    for line in &after[prefix..after.len() - suffix] {
+        println!("  + {line}");
+    }
+}